@@ -0,0 +1,80 @@
+extern crate chip8;
+#[macro_use]
+extern crate criterion;
+
+use criterion::{black_box, Criterion, Throughput};
+
+use chip8::Cpu;
+
+/// Number of instructions executed per benchmark iteration
+const INSTRUCTIONS: u64 = 10_000;
+
+/// Tight arithmetic loop with a conditional skip and jumps, no display output
+///
+/// 0x200  LD V0, 0x01
+/// 0x202  LD V1, 0x03
+/// 0x204  ADD V0, V1
+/// 0x206  SUB V0, V1
+/// 0x208  XOR V2, V0
+/// 0x20A  SHR V3
+/// 0x20C  ADD V4, 0x01
+/// 0x20E  SE V4, 0x00
+/// 0x210  JP 0x204
+/// 0x212  JP 0x200
+const COMPUTE_HEAVY: [u8; 20] = [
+    0x60, 0x01, 0x61, 0x03, 0x80, 0x14, 0x80, 0x15, 0x82, 0x03, 0x83, 0x06, 0x74, 0x01, 0x34, 0x00,
+    0x12, 0x04, 0x12, 0x00,
+];
+
+/// Loop drawing the built-in "0" glyph across the screen, clearing whenever V1 returns to zero
+///
+/// 0x200  LD I, 0x000
+/// 0x202  DRW V0, V1, 5
+/// 0x204  ADD V0, 0x05
+/// 0x206  DRW V0, V1, 5
+/// 0x208  ADD V1, 0x06
+/// 0x20A  SNE V1, 0x00
+/// 0x20C  CLS
+/// 0x20E  JP 0x202
+const DRAW_HEAVY: [u8; 16] = [
+    0xA0, 0x00, 0xD0, 0x15, 0x70, 0x05, 0xD0, 0x15, 0x71, 0x06, 0x41, 0x00, 0x00, 0xE0, 0x12, 0x02,
+];
+
+/// Returns a CPU with the passed program loaded at 0x200. Seeded, so RND draws the same numbers on every run and the
+/// bench also builds without the `std` feature
+///
+/// # Arguments
+///
+/// * `program` - Program bytes to load
+fn cpu_with_program(program: &[u8]) -> Cpu {
+    let mut cpu = Cpu::with_seed(0);
+    cpu.load_rom_bytes(program)
+        .expect("Unable to load benchmark ROM");
+    cpu
+}
+
+fn bench_workload(c: &mut Criterion, name: &str, program: &[u8]) {
+    let mut cpu = cpu_with_program(program);
+    let mut group = c.benchmark_group("opcode_dispatch");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                cpu.tick().unwrap();
+            }
+            black_box(cpu.has_disp_update());
+        })
+    });
+    group.finish();
+}
+
+fn compute_heavy(c: &mut Criterion) {
+    bench_workload(c, "compute_heavy", &COMPUTE_HEAVY);
+}
+
+fn draw_heavy(c: &mut Criterion) {
+    bench_workload(c, "draw_heavy", &DRAW_HEAVY);
+}
+
+criterion_group!(benches, compute_heavy, draw_heavy);
+criterion_main!(benches);
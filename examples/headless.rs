@@ -0,0 +1,75 @@
+//! Run a ROM without a window and print the final display as text.
+//!
+//! ```text
+//! cargo run --example headless [rom] [frames]
+//! ```
+//!
+//! Without a ROM a built-in demo is run, drawing the digits 0-F.
+extern crate chip8;
+
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8::{display_to_ascii, Cpu};
+
+/// Instructions per 60hz frame, the rate the frontend runs at
+const INSTRUCTIONS_PER_FRAME: usize = 8;
+/// Number of frames run when none are given
+const DEFAULT_FRAMES: u32 = 60;
+
+/// Draws the hex digits 0-F in two rows of eight, then halts
+///
+/// 0x200  LD V0, 0x00   ; digit
+/// 0x202  LD V1, 0x00   ; x
+/// 0x204  LD V2, 0x00   ; y
+/// 0x206  LD F, V0
+/// 0x208  DRW V1, V2, 5
+/// 0x20A  ADD V0, 0x01
+/// 0x20C  ADD V1, 0x08
+/// 0x20E  SE V1, 0x40   ; end of the row
+/// 0x210  JP 0x206
+/// 0x212  LD V1, 0x00
+/// 0x214  ADD V2, 0x08
+/// 0x216  SE V2, 0x10   ; end of the second row
+/// 0x218  JP 0x206
+/// 0x21A  JP 0x21A
+const DEMO: [u8; 28] = [
+    0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xF0, 0x29, 0xD1, 0x25, 0x70, 0x01, 0x71, 0x08, 0x31, 0x40,
+    0x12, 0x06, 0x61, 0x00, 0x72, 0x08, 0x32, 0x10, 0x12, 0x06, 0x12, 0x1A,
+];
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let rom = match args.next() {
+        Some(path) => fs::read(&path).unwrap_or_else(|e| {
+            eprintln!("Unable to read {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => DEMO.to_vec(),
+    };
+    let frames = match args.next().map(|frames| frames.parse::<u32>()) {
+        Some(Ok(frames)) => frames,
+        Some(Err(_)) => {
+            eprintln!("Usage: headless [rom] [frames]");
+            process::exit(1);
+        }
+        None => DEFAULT_FRAMES,
+    };
+
+    // A fixed seed makes ROMs using RND print the same display on every run
+    let mut cpu = Cpu::with_seed(0);
+    if let Err(e) = cpu.load_rom_bytes(&rom) {
+        eprintln!("Unable to load the ROM: {}", e);
+        process::exit(1);
+    }
+    for _ in 0..frames {
+        if let Err(e) = cpu.step_frame(INSTRUCTIONS_PER_FRAME) {
+            eprintln!("CPU stopped: {}", e);
+            break;
+        }
+        // There is no keyboard, let ROMs waiting for a key carry on
+        cpu.skip_key_wait();
+    }
+    print!("{}", display_to_ascii(&cpu.view_display()));
+}
@@ -0,0 +1,29 @@
+#![no_main]
+extern crate chip8;
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use chip8::Cpu;
+
+/// Upper bound on the instructions executed per input, so looping ROMs still finish
+const MAX_STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let mut cpu = Cpu::initialize();
+    cpu.set_safe_mode(true);
+    // ROMs too large for memory are rejected before anything runs
+    if cpu.load_rom_bytes(data).is_err() {
+        return;
+    }
+
+    for step in 0..MAX_STEPS {
+        // Keys are pressed and released from the input too, so key waits and skips are exercised
+        let key = data.get(step % data.len().max(1)).cloned().unwrap_or(0) as usize;
+        if step % 64 == 0 {
+            cpu.set_key_pressed(key);
+        } else if step % 64 == 32 {
+            cpu.set_key_released(key);
+        }
+        cpu.tick().expect("tick only fails in strict mode");
+    }
+});
@@ -0,0 +1,394 @@
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use error::AsmError;
+
+/// Size of the Chip-8 address space
+const MEMORY_SIZE: usize = 4096;
+/// Address programs are loaded at and start executing from
+const PROGRAM_START: usize = 512;
+/// Every mnemonic the assembler knows, including the `DB` and `DW` data directives
+const MNEMONICS: [&str; 28] = [
+    "CLS", "RET", "SCD", "SCR", "SCL", "LOW", "HIGH", "SYS", "JP", "CALL", "SE", "SNE", "LD",
+    "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE",
+    "DB", "DW",
+];
+
+/// Operand of a statement, before labels are resolved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand<'a> {
+    /// Register `Vx`
+    V(u16),
+    /// Index register, `I`
+    I,
+    /// Memory at the index register, `[I]`
+    IndirectI,
+    /// Delay timer, `DT`
+    Dt,
+    /// Sound timer, `ST`
+    St,
+    /// Key press, `K`
+    K,
+    /// Low resolution font sprite, `F`
+    F,
+    /// High resolution font sprite, `HF`
+    Hf,
+    /// BCD digits, `B`
+    B,
+    /// RPL user flags, `R`
+    R,
+    /// Number or label
+    Value(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    /// Parse an operand. Register and other reserved names are case-insensitive, anything else is a value.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Operand without surrounding whitespace
+    fn parse(text: &'a str) -> Operand<'a> {
+        let upper = text.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ if upper.len() == 2 && upper.starts_with('V') => {
+                match u16::from_str_radix(&upper[1..], 16) {
+                    Ok(x) => Operand::V(x),
+                    Err(_) => Operand::Value(text),
+                }
+            }
+            _ => Operand::Value(text),
+        }
+    }
+}
+
+/// An instruction or data directive on one line of source
+struct Statement<'a> {
+    /// Line number, starting at 1
+    line: usize,
+    /// Upper case mnemonic
+    mnemonic: String,
+    /// Operands in source order
+    operands: Vec<Operand<'a>>,
+}
+
+impl<'a> Statement<'a> {
+    /// Returns the number of bytes the statement assembles to
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.len(),
+            "DW" => 2 * self.operands.len(),
+            _ => 2,
+        }
+    }
+
+    /// Returns the error for operands that do not fit the mnemonic
+    fn invalid(&self) -> AsmError {
+        AsmError::InvalidOperands(self.line, self.mnemonic.clone())
+    }
+
+    /// Resolve a number or label operand
+    ///
+    /// # Arguments
+    ///
+    /// * `operand` - Operand to resolve
+    /// * `max` - Largest value that fits the field being assembled
+    /// * `labels` - Address of every label in the program
+    fn resolve(
+        &self,
+        operand: &Operand,
+        max: u16,
+        labels: &BTreeMap<&str, u16>,
+    ) -> Result<u16, AsmError> {
+        let text = match *operand {
+            Operand::Value(text) => text,
+            _ => return Err(self.invalid()),
+        };
+        let value = match parse_number(text) {
+            Some(value) => value,
+            None if is_label(text) => match labels.get(text) {
+                Some(&addr) => addr as u32,
+                None => return Err(AsmError::UndefinedLabel(self.line, text.to_owned())),
+            },
+            None => return Err(self.invalid()),
+        };
+        if value > max as u32 {
+            return Err(AsmError::OutOfRange(self.line, text.to_owned()));
+        }
+        Ok(value as u16)
+    }
+
+    /// Assemble the statement, appending its bytes to `program`
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - Address of every label in the program
+    /// * `program` - Bytes assembled so far
+    fn assemble(
+        &self,
+        labels: &BTreeMap<&str, u16>,
+        program: &mut Vec<u8>,
+    ) -> Result<(), AsmError> {
+        use self::Operand::*;
+
+        let addr = |op: &Operand| self.resolve(op, 0xFFF, labels);
+        let byte = |op: &Operand| self.resolve(op, 0xFF, labels);
+        let nibble = |op: &Operand| self.resolve(op, 0xF, labels);
+        let xy = |x: u16, y: u16| x << 8 | y << 4;
+        let opcode = match (self.mnemonic.as_str(), &self.operands[..]) {
+            ("DB", [_, ..]) => {
+                for op in &self.operands {
+                    program.push(byte(op)? as u8);
+                }
+                return Ok(());
+            }
+            ("DW", [_, ..]) => {
+                for op in &self.operands {
+                    let word = self.resolve(op, 0xFFFF, labels)?;
+                    program.extend_from_slice(&word.to_be_bytes());
+                }
+                return Ok(());
+            }
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [n]) => 0x00C0 | nibble(n)?,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("SYS", [a]) => addr(a)?,
+            ("JP", [V(0), a]) => 0xB000 | addr(a)?,
+            ("JP", [a]) => 0x1000 | addr(a)?,
+            ("CALL", [a]) => 0x2000 | addr(a)?,
+            ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+            ("SE", [V(x), k]) => 0x3000 | x << 8 | byte(k)?,
+            ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+            ("SNE", [V(x), k]) => 0x4000 | x << 8 | byte(k)?,
+            ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+            ("LD", [V(x), Dt]) => 0xF007 | x << 8,
+            ("LD", [V(x), K]) => 0xF00A | x << 8,
+            ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+            ("LD", [V(x), R]) => 0xF085 | x << 8,
+            ("LD", [V(x), k]) => 0x6000 | x << 8 | byte(k)?,
+            ("LD", [I, a]) => 0xA000 | addr(a)?,
+            ("LD", [Dt, V(x)]) => 0xF015 | x << 8,
+            ("LD", [St, V(x)]) => 0xF018 | x << 8,
+            ("LD", [F, V(x)]) => 0xF029 | x << 8,
+            ("LD", [Hf, V(x)]) => 0xF030 | x << 8,
+            ("LD", [B, V(x)]) => 0xF033 | x << 8,
+            ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+            ("LD", [R, V(x)]) => 0xF075 | x << 8,
+            ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+            ("ADD", [V(x), k]) => 0x7000 | x << 8 | byte(k)?,
+            ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+            ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+            ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+            ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+            ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+            // Shifting a register into itself behaves the same with and without the shift quirk
+            ("SHR", [V(x)]) => 0x8006 | xy(*x, *x),
+            ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+            ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+            ("SHL", [V(x)]) => 0x800E | xy(*x, *x),
+            ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+            ("RND", [V(x), k]) => 0xC000 | x << 8 | byte(k)?,
+            ("DRW", [V(x), V(y), n]) => 0xD000 | xy(*x, *y) | nibble(n)?,
+            ("SKP", [V(x)]) => 0xE09E | x << 8,
+            ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+            ("PLANE", [n]) => 0xF001 | nibble(n)? << 8,
+            (mnemonic, _) if MNEMONICS.contains(&mnemonic) => return Err(self.invalid()),
+            (mnemonic, _) => return Err(AsmError::UnknownMnemonic(self.line, mnemonic.to_owned())),
+        };
+        program.extend_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary number
+///
+/// # Arguments
+///
+/// * `text` - Number to parse
+fn parse_number(text: &str) -> Option<u32> {
+    let (digits, radix) =
+        if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+            (hex, 16)
+        } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+            (bin, 2)
+        } else {
+            (text, 10)
+        };
+    u32::from_str_radix(digits, radix).ok()
+}
+
+/// Returns whether `text` is a valid label: a letter or underscore, followed by letters, digits and underscores
+///
+/// # Arguments
+///
+/// * `text` - Label to check
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Assemble a program from the mnemonics of Cowgod's technical reference, as written by `disasm::disassemble`, e.g.
+/// `LD V0, 0x01`. Returns the program bytes to load at 512.
+///
+/// One statement is written per line, with comments starting at `;`. Mnemonics and register names are
+/// case-insensitive. A line may start with a label, e.g. `loop:`, which names the address of the statement that
+/// follows and can be used wherever an address is expected. Numbers are decimal, `0x` hexadecimal or `0b` binary.
+/// `DB` and `DW` emit comma separated bytes and big-endian words, e.g. sprite data. `SHR Vx` and `SHL Vx` shift a
+/// register into itself.
+///
+/// # Arguments
+///
+/// * `source` - Assembly source
+///
+/// # Errors
+///
+/// * `AsmError` - The first statement that does not assemble, with its line number
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    // First pass, find the address of every label
+    let mut labels = BTreeMap::new();
+    let mut statements = Vec::new();
+    let mut addr = PROGRAM_START;
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(AsmError::InvalidLabel(line, label.to_owned()));
+            }
+            if labels.insert(label, addr as u16).is_some() {
+                return Err(AsmError::DuplicateLabel(line, label.to_owned()));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.find(char::is_whitespace) {
+            Some(end) => (&text[..end], text[end..].trim()),
+            None => (text, ""),
+        };
+        let statement = Statement {
+            line,
+            mnemonic: mnemonic.to_ascii_uppercase(),
+            operands: if operands.is_empty() {
+                Vec::new()
+            } else {
+                operands
+                    .split(',')
+                    .map(|op| Operand::parse(op.trim()))
+                    .collect()
+            },
+        };
+        addr += statement.size();
+        if addr > MEMORY_SIZE {
+            return Err(AsmError::TooLarge(line));
+        }
+        statements.push(statement);
+    }
+
+    // Second pass, with every label known
+    let mut program = Vec::with_capacity(addr - PROGRAM_START);
+    for statement in &statements {
+        statement.assemble(&labels, &mut program)?;
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm::disassemble;
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+            ; Draw a digit forever\n\
+            start:  LD V0, 10\n\
+                    ld f, v0\n\
+                    DRW V1, V2, 5  ; 5 rows\n\
+            loop:   JP loop\n\
+            sprite: DB 0xFF, 0b10000001\n\
+                    DW sprite\n";
+        assert_eq!(
+            Ok(vec![
+                0x60, 0x0A, 0xF0, 0x29, 0xD1, 0x25, 0x12, 0x06, 0xFF, 0x81, 0x02, 0x08
+            ]),
+            assemble(source)
+        );
+        assert_eq!(Ok(vec![0x83, 0x36]), assemble("SHR V3"));
+        assert_eq!(Ok(vec![]), assemble("; nothing but comments\n\n"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Every opcode disassembles to something that assembles back to it, data words included
+        for opcode in 0..=0xFFFF_u16 {
+            let text = disassemble(opcode);
+            assert_eq!(
+                Ok(opcode.to_be_bytes().to_vec()),
+                assemble(&text),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(
+            Err(AsmError::UnknownMnemonic(2, "MOV".to_owned())),
+            assemble("CLS\nMOV V0, V1")
+        );
+        assert_eq!(
+            Err(AsmError::InvalidOperands(1, "LD".to_owned())),
+            assemble("LD DT, 5")
+        );
+        assert_eq!(
+            Err(AsmError::InvalidOperands(1, "CLS".to_owned())),
+            assemble("CLS V0")
+        );
+        assert_eq!(
+            Err(AsmError::OutOfRange(1, "256".to_owned())),
+            assemble("LD V0, 256")
+        );
+        assert_eq!(
+            Err(AsmError::UndefinedLabel(1, "nowhere".to_owned())),
+            assemble("JP nowhere")
+        );
+        assert_eq!(
+            Err(AsmError::DuplicateLabel(2, "a".to_owned())),
+            assemble("a: CLS\na: CLS")
+        );
+        assert_eq!(
+            Err(AsmError::InvalidLabel(1, "1st".to_owned())),
+            assemble("1st: CLS")
+        );
+
+        // 0x200 to 0xFFF holds 1792 instructions
+        let mut source = "CLS\n".repeat(1792);
+        assert!(assemble(&source).is_ok());
+        source.push_str("CLS\n");
+        assert_eq!(Err(AsmError::TooLarge(1793)), assemble(&source));
+    }
+}
@@ -0,0 +1,16 @@
+/// Destination for audio events from the `Cpu`. Implemented by frontends (ggez, rodio, web, ...) so the core can
+/// signal sound without knowing anything about the audio backend.
+pub trait AudioSink {
+    /// Start the beep. Called when the sound timer becomes nonzero.
+    fn beep_on(&mut self);
+
+    /// Stop the beep. Called when the sound timer reaches zero.
+    fn beep_off(&mut self);
+
+    /// Play a 1-bit XO-Chip audio pattern. Backends without pattern support may ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - 128 one-bit samples, most significant bit first
+    fn play_pattern(&mut self, _pattern: &[u8; 16]) {}
+}
@@ -0,0 +1,48 @@
+use ggez::audio::{SoundData, SoundSource, Source};
+use ggez::{Context, GameError, GameResult};
+
+use wav;
+
+/// Plays the beep tone through the audio device. Only changes in the requested state start or stop playback, so
+/// setting the sound timer again while it is still running does not stack overlapping tones.
+pub struct Beeper {
+    source: Source,
+    /// Whether the tone is currently playing
+    playing: bool,
+}
+
+impl Beeper {
+    /// Returns a silent beeper with the tone loaded
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - ggez context, used to open the audio device
+    /// * `sample_rate` - Sample rate of the generated tone
+    pub fn new(ctx: &mut Context, sample_rate: u32) -> GameResult<Beeper> {
+        let tone = wav::tone(sample_rate).map_err(|e| GameError::AudioError(e.to_string()))?;
+        let mut source = Source::from_data(ctx, SoundData::from(tone))?;
+        source.set_repeat(true);
+        Ok(Beeper {
+            source,
+            playing: false,
+        })
+    }
+
+    /// Start or stop the tone. Does nothing if it is already in the requested state.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - ggez context
+    /// * `active` - True while the beep should sound
+    pub fn set_active(&mut self, ctx: &Context, active: bool) -> GameResult {
+        if active == self.playing {
+            return Ok(());
+        }
+        self.playing = active;
+        if active {
+            self.source.play(ctx)
+        } else {
+            self.source.stop(ctx)
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Minimal command line runner without the ggez frontend. Runs a ROM for a fixed number of cycles and prints the
+//! final display as text, e.g. for CI or a headless test harness.
+extern crate chip8;
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chip8::{display_to_ascii, Cpu};
+
+/// Number of cycles run when none are given, 10 seconds at 500 instructions per second
+const DEFAULT_CYCLES: u32 = 5000;
+
+/// Number of cycles per 60hz frame, after which the timers are decremented
+const CYCLES_PER_FRAME: u32 = 8;
+
+/// Command line usage summary
+const USAGE: &str = "Usage: chip-8-headless <rom> [cycles]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (rom, cycles) = match args.as_slice() {
+        [rom] => (rom, DEFAULT_CYCLES),
+        [rom, cycles] => match cycles.parse::<u32>() {
+            Ok(cycles) => (rom, cycles),
+            Err(_) => {
+                eprintln!("{}", USAGE);
+                process::exit(1);
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+
+    // Only uses the core of the library, so it also builds without its std feature. The binary itself has std, for
+    // reading the file and seeding RND from the clock
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let mut cpu = match Cpu::try_with_seed(seed) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            eprintln!("Unable to initialize the CPU: {}", e);
+            process::exit(1);
+        }
+    };
+    let loaded = fs::read(rom)
+        .map_err(|e| e.to_string())
+        .and_then(|data| cpu.load_rom_bytes(&data).map_err(|e| e.to_string()));
+    if let Err(e) = loaded {
+        eprintln!("Unable to load {}: {}", rom, e);
+        process::exit(1);
+    }
+    let mut status = 0;
+    for cycle in 1..=cycles {
+        if let Err(e) = cpu.tick() {
+            eprintln!("CPU stopped: {}", e);
+            status = 1;
+            break;
+        }
+        // There is no keyboard, let ROMs waiting for a key carry on
+        cpu.skip_key_wait();
+        if cycle.is_multiple_of(CYCLES_PER_FRAME) {
+            cpu.update_timers();
+        }
+    }
+    print!("{}", display_to_ascii(&cpu.view_display()));
+    process::exit(status);
+}
@@ -0,0 +1,204 @@
+//! Terminal frontend without ggez, e.g. for running a ROM over SSH. Draws the display with block characters and reads
+//! the keyboard from the terminal, using the same key layout as the window.
+#[macro_use]
+extern crate crossterm;
+extern crate chip8;
+
+use std::env;
+use std::io::{self, Stdout, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+use chip8::{Cpu, DisplaySink};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+/// Number of instructions per second
+const CYCLE_HZ: u32 = 500;
+
+/// Duration of a single 60hz frame, after which the timers are decremented
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How long a key stays pressed after the terminal reported it. Terminals only report presses, repeated while the key
+/// is held, so a key is released once no press was reported for this long.
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+/// Command line usage summary
+const USAGE: &str = "Usage: chip-8-term <rom>";
+
+/// Display sink drawing each frame to the terminal, one character per pixel
+struct TerminalSink {
+    out: Stdout,
+    /// Number of rows of the last frame, to clear the terminal when the resolution changes
+    rows: usize,
+}
+
+impl DisplaySink for TerminalSink {
+    fn present(&mut self, display: &[&[bool]]) {
+        let text: Vec<String> = display
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&lit| if lit { '\u{2588}' } else { ' ' })
+                    .collect()
+            })
+            .collect();
+        let mut result = Ok(());
+        if display.len() != self.rows {
+            self.rows = display.len();
+            result = queue!(self.out, Clear(ClearType::All));
+        }
+        // Raw mode does not turn a line feed into a carriage return
+        let result = result
+            .and_then(|()| queue!(self.out, MoveTo(0, 0), Print(text.join("\r\n"))))
+            .and_then(|()| self.out.flush());
+        if let Err(e) = result {
+            eprintln!("Unable to draw: {}", e);
+        }
+    }
+}
+
+/// Puts the terminal in raw mode on an alternate screen, and restores it when dropped, also on errors
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Map a terminal key to a Chip-8 key index, with the layout of the window:
+///
+/// ```text
+/// 1 2 3 4        1 2 3 C
+/// Q W E R   ->   4 5 6 D
+/// A S D F        7 8 9 E
+/// Z X C V        A 0 B F
+/// ```
+///
+/// # Arguments
+///
+/// * `c` - Character typed, in either case
+fn get_idx_from_char(c: char) -> Option<usize> {
+    let idx = match c.to_ascii_lowercase() {
+        '1' => 1,
+        '2' => 2,
+        '3' => 3,
+        '4' => 0xC,
+        'q' => 4,
+        'w' => 5,
+        'e' => 6,
+        'r' => 0xD,
+        'a' => 7,
+        's' => 8,
+        'd' => 9,
+        'f' => 0xE,
+        'z' => 0xA,
+        'x' => 0,
+        'c' => 0xB,
+        'v' => 0xF,
+        _ => return None,
+    };
+    Some(idx)
+}
+
+/// Run the ROM until Escape or Ctrl-C is pressed or the CPU stops with an error
+///
+/// # Arguments
+///
+/// * `cpu` - CPU with the ROM loaded
+fn run(cpu: &mut Cpu) -> io::Result<()> {
+    let _raw = RawTerminal::enter()?;
+    let mut sink = TerminalSink {
+        out: io::stdout(),
+        rows: 0,
+    };
+    sink.present(&cpu.view_display());
+
+    let cycle = Duration::from_secs(1) / CYCLE_HZ;
+    let mut next_cycle = Instant::now();
+    let mut next_frame = Instant::now() + FRAME_DURATION;
+    // Time each pressed key was last reported
+    let mut held: [Option<Instant>; 16] = [None; 16];
+    loop {
+        let now = Instant::now();
+        if now < next_cycle {
+            if event::poll(next_cycle - now)? {
+                if let Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    ..
+                }) = event::read()?
+                {
+                    match code {
+                        KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                            return Ok(());
+                        }
+                        KeyCode::Char(c) if kind != KeyEventKind::Release => {
+                            if let Some(idx) = get_idx_from_char(c) {
+                                cpu.set_key_pressed(idx);
+                                held[idx] = Some(Instant::now());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            continue;
+        }
+        // Drop the instructions missed while the terminal was stalled instead of catching up in a burst
+        if now - next_cycle > Duration::from_secs(1) {
+            next_cycle = now;
+        }
+        next_cycle += cycle;
+
+        for (idx, pressed) in held.iter_mut().enumerate() {
+            if pressed.is_some_and(|at| at.elapsed() >= KEY_HOLD) {
+                cpu.set_key_released(idx);
+                *pressed = None;
+            }
+        }
+        if let Err(e) = cpu.tick() {
+            return Err(io::Error::other(format!("CPU stopped: {}", e)));
+        }
+        cpu.present_display(&mut sink);
+        while Instant::now() >= next_frame {
+            next_frame += FRAME_DURATION;
+            cpu.update_timers();
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let rom = match args.as_slice() {
+        [rom] => rom,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+
+    let mut cpu = Cpu::initialize();
+    if let Err(e) = cpu.load_rom(rom) {
+        eprintln!("Unable to load {}: {}", rom, e);
+        process::exit(1);
+    }
+    if let Err(e) = run(&mut cpu) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,133 @@
+use chip8::Profile;
+
+/// Compatibility database bundled with the binary
+const BUNDLED: &str = include_str!("../compat.json");
+
+/// Known ROM and the profile it runs best with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompatEntry {
+    /// Lowercase hex SHA-1 of the ROM, as returned by `Cpu::rom_sha1`
+    pub sha1: String,
+    /// Name of the game, for messages
+    pub title: String,
+    /// Recommended quirks profile
+    pub profile: Profile,
+}
+
+/// Compatibility database mapping ROM hashes to quirks profiles. Stored as JSON of the form
+///
+/// ```json
+/// { "roms": [ { "sha1": "<40 hex digits>", "title": "Pong", "profile": "vip" } ] }
+/// ```
+pub struct CompatDb {
+    entries: Vec<CompatEntry>,
+}
+
+impl CompatDb {
+    /// Returns the database bundled with the binary
+    pub fn bundled() -> CompatDb {
+        CompatDb::parse(BUNDLED).expect("Bundled compatibility database is invalid")
+    }
+
+    /// Parse a database from JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - Database in the format described on `CompatDb`
+    pub fn parse(json: &str) -> Result<CompatDb, String> {
+        let root: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let roms = root["roms"].as_array().ok_or("expected a \"roms\" array")?;
+        let mut entries = Vec::new();
+        for (idx, rom) in roms.iter().enumerate() {
+            let field = |name: &str| {
+                rom[name]
+                    .as_str()
+                    .ok_or(format!("entry {} has no \"{}\"", idx, name))
+            };
+            let sha1 = field("sha1")?.to_ascii_lowercase();
+            if sha1.len() != 40 || !sha1.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("entry {} has an invalid sha1: {}", idx, sha1));
+            }
+            let profile = field("profile")?;
+            let profile = Profile::from_name(profile)
+                .ok_or(format!("entry {} has an unknown profile: {}", idx, profile))?;
+            entries.push(CompatEntry {
+                sha1,
+                title: field("title")?.to_owned(),
+                profile,
+            });
+        }
+        Ok(CompatDb { entries })
+    }
+
+    /// Add the entries of another database, taking priority over the existing ones
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Database to add, e.g. the user's own entries
+    pub fn extend(&mut self, other: CompatDb) {
+        let mut entries = other.entries;
+        entries.append(&mut self.entries);
+        self.entries = entries;
+    }
+
+    /// Find the entry of a ROM
+    ///
+    /// # Arguments
+    ///
+    /// * `sha1` - Hex SHA-1 of the ROM
+    pub fn lookup(&self, sha1: &str) -> Option<&CompatEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.sha1.eq_ignore_ascii_case(sha1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{ClipMode, Cpu};
+
+    #[test]
+    fn test_bundled_database() {
+        CompatDb::bundled();
+    }
+
+    #[test]
+    fn test_lookup_profile() {
+        // LD V0, 0x01; JP 0x202
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        let sha1 = c8.rom_sha1();
+        let json = format!(
+            r#"{{ "roms": [ {{ "sha1": "{}", "title": "Synthetic", "profile": "VIP" }} ] }}"#,
+            sha1.to_uppercase()
+        );
+
+        let mut db = CompatDb::bundled();
+        db.extend(CompatDb::parse(&json).unwrap());
+        let entry = db.lookup(&sha1).unwrap();
+        assert_eq!("Synthetic", entry.title);
+        assert_eq!(Profile::Vip, entry.profile);
+        c8.set_quirks(entry.profile.quirks());
+        assert!(c8.quirks().shift_uses_vy);
+        assert_eq!(ClipMode::Clip, c8.quirks().clip_mode);
+
+        assert_eq!(None, db.lookup(&"0".repeat(40)));
+        assert!(CompatDb::parse(r#"{ "roms": [ { "sha1": "abc" } ] }"#).is_err());
+        assert!(CompatDb::parse(&json.replace("VIP", "gameboy")).is_err());
+    }
+
+    #[test]
+    fn test_readme_example() {
+        // The example in the README parses once its placeholder is replaced by a hash
+        let readme = include_str!("../README.md");
+        let start = readme.find("```json\n").unwrap() + "```json\n".len();
+        let example = &readme[start..start + readme[start..].find("```").unwrap()];
+        let db =
+            CompatDb::parse(&example.replace("<sha1 of the ROM file>", &"a".repeat(40))).unwrap();
+        let entry = db.lookup(&"A".repeat(40)).unwrap();
+        assert_eq!("Pong", entry.title);
+        assert_eq!(Profile::Vip, entry.profile);
+    }
+}
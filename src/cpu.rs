@@ -1,24 +1,521 @@
-use rand::Rng;
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeSet;
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use asm;
+use audio::AudioSink;
+use debug::{Breakpoint, Watchpoint};
+use disasm::{self, MemoryMap, Region};
+use display::DisplaySink;
+#[cfg(feature = "serde")]
+use error::StateError;
+use error::{AsmError, CpuError, InitError, RomError};
 
 pub const C8_WIDTH: usize = 64;
 pub const C8_HEIGHT: usize = 32;
+/// Width of the SCHIP high resolution mode, enabled with 00FF
+pub const HIRES_WIDTH: usize = 128;
+/// Height of the SCHIP high resolution mode, enabled with 00FF
+pub const HIRES_HEIGHT: usize = 64;
+
+/// Set of breakpoint addresses. A `HashSet` where std is available
+#[cfg(feature = "std")]
+type AddrSet = HashSet<u16>;
+/// Set of breakpoint addresses. A `BTreeSet` without std
+#[cfg(not(feature = "std"))]
+type AddrSet = BTreeSet<u16>;
+
+/// One display plane, large enough for the high resolution mode
+type Plane = [[bool; HIRES_WIDTH]; HIRES_HEIGHT];
+
+/// Built-in hex digit sprites 0-F, five bytes each, loaded at address 0
+pub(crate) const FONTSET: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Large SCHIP hex digit sprites 0-F for Fx30, ten bytes each, loaded right after `FONTSET`. SCHIP only has the
+/// digits 0-9, A-F are the XO-CHIP extension
+pub(crate) const BIG_FONTSET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Behavioral differences between Chip-8 interpreters that ROMs may depend on. The defaults match the behavior most
+/// modern ROMs expect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE copy Vy into Vx before shifting (original COSMAC VIP) instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// Fx55/Fx65 leave I pointing past the last register stored or loaded, i.e. increment it by x + 1 (original COSMAC
+    /// VIP), instead of leaving I unchanged
+    pub load_store_increments_i: bool,
+    /// How Dxyn treats sprite pixels that fall past the edges of the screen
+    pub clip_mode: ClipMode,
+    /// Bnnn is read as Bxnn and jumps to xnn + Vx instead of nnn + V0. x is the high nibble of the address. CHIP-48
+    /// and SCHIP ROMs need this. COSMAC VIP and XO-CHIP ROMs expect nnn + V0
+    pub jump_uses_vx: bool,
+}
+
+/// Sprite edge behaviors of the different interpreters. In every mode the starting coordinate wraps around the screen;
+/// the modes differ in what happens to the pixels of a sprite that extend past the right or bottom edge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipMode {
+    /// Pixels wrap around to the opposite edge in both directions. Expected by most modern and XO-Chip ROMs. This is
+    /// the default.
+    #[default]
+    Wrap,
+    /// Pixels past the edges are dropped, as on the COSMAC VIP and SCHIP. Needed by ROMs that draw partially off
+    /// screen, such as Blitz, which otherwise shows garbage at the top of the screen.
+    Clip,
+    /// Pixels wrap horizontally but are dropped past the bottom edge, matching the Amiga interpreter that some ROMs
+    /// were authored against.
+    Amiga,
+}
+
+/// Identifies a single quirk so it can be toggled at runtime
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quirk {
+    /// See `Quirks::shift_uses_vy`
+    ShiftUsesVy,
+    /// See `Quirks::load_store_increments_i`
+    LoadStoreIncrementsI,
+    /// See `Quirks::jump_uses_vx`
+    JumpUsesVx,
+}
+
+impl Quirks {
+    /// Check whether a quirk is enabled
+    ///
+    /// # Arguments
+    ///
+    /// * `quirk` - Quirk to check
+    pub fn get(&self, quirk: Quirk) -> bool {
+        match quirk {
+            Quirk::ShiftUsesVy => self.shift_uses_vy,
+            Quirk::LoadStoreIncrementsI => self.load_store_increments_i,
+            Quirk::JumpUsesVx => self.jump_uses_vx,
+        }
+    }
+
+    /// Enable or disable a quirk
+    ///
+    /// # Arguments
+    ///
+    /// * `quirk` - Quirk to change
+    /// * `enabled` - New state of the quirk
+    pub fn set(&mut self, quirk: Quirk, enabled: bool) {
+        match quirk {
+            Quirk::ShiftUsesVy => self.shift_uses_vy = enabled,
+            Quirk::LoadStoreIncrementsI => self.load_store_increments_i = enabled,
+            Quirk::JumpUsesVx => self.jump_uses_vx = enabled,
+        }
+    }
+}
+
+/// Named sets of quirks matching the interpreters ROMs were written for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// The original COSMAC VIP interpreter: shifts use Vy, Fx55/Fx65 increment I and sprites are clipped at the edges
+    Vip,
+    /// SCHIP on the HP48: shifts work in place, Bxnn jumps relative to Vx and sprites are clipped at the edges
+    Schip,
+    /// Modern interpreters and XO-Chip: shifts work in place and sprites wrap. Same as the default quirks
+    XoChip,
+}
+
+impl Profile {
+    /// Look up a profile by its name: `vip`, `schip` or `xo-chip`
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the profile, case insensitive
+    pub fn from_name(name: &str) -> Option<Profile> {
+        [Profile::Vip, Profile::Schip, Profile::XoChip]
+            .iter()
+            .cloned()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the name the profile is looked up by
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Vip => "vip",
+            Profile::Schip => "schip",
+            Profile::XoChip => "xo-chip",
+        }
+    }
+
+    /// Check whether the interpreter of the profile has an instruction. The COSMAC VIP only has the original
+    /// instructions, SCHIP adds the scrolls, the high resolution mode, the large font and the RPL flags, and XO-CHIP
+    /// adds the second display plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `instruction` - Instruction to check
+    pub fn supports(self, instruction: Instruction) -> bool {
+        let schip = matches!(
+            instruction,
+            Instruction::Scd
+                | Instruction::Scr
+                | Instruction::Scl
+                | Instruction::Low
+                | Instruction::High
+                | Instruction::Ldhf
+                | Instruction::Ldrx
+                | Instruction::Ldxr
+        );
+        match self {
+            Profile::Vip => !schip && instruction != Instruction::Plane,
+            Profile::Schip => instruction != Instruction::Plane,
+            Profile::XoChip => true,
+        }
+    }
+
+    /// Returns the quirks of the profile
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::Vip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                clip_mode: ClipMode::Clip,
+                jump_uses_vx: false,
+            },
+            Profile::Schip => Quirks {
+                clip_mode: ClipMode::Clip,
+                jump_uses_vx: true,
+                ..Quirks::default()
+            },
+            Profile::XoChip => Quirks::default(),
+        }
+    }
+}
+
+/// How `LD Vx, K` (Fx0A) obtains its key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWaitModel {
+    /// Block until the next key press. Only the timers run while waiting. This is the default.
+    Blocking,
+    /// Take the oldest key buffered with `Cpu::queue_key` without waiting. When none is buffered, a pending key request
+    /// is posted (see `Cpu::waiting_register`) that blocks like `Blocking` until the frontend fulfills it.
+    Queued,
+}
+
+/// What quiet mode does when a ROM does something that would otherwise panic or fail. See `Cpu::set_quiet`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuietPolicy {
+    /// Log the error and carry on as in safe mode. `tick` never fails
+    Continue,
+    /// Stop the instruction and return the error from `tick`, so the frontend can halt
+    Halt,
+}
+
+/// What happens when an instruction accesses memory past the end of the 4KB address space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryModel {
+    /// Addresses wrap around to the start of memory, and so does the program counter
+    Wrap,
+    /// The access is dropped and `tick` returns `CpuError::MemoryOutOfBounds`. This is the default.
+    Error,
+}
+
+/// Per instruction cycle costs for the draw throttle. When enabled, each 60hz frame has a budget of cycles and every
+/// executed instruction spends its cost from it. Once the budget is spent, the remaining ticks of the frame execute
+/// nothing, so frames with a lot of drawing run fewer instructions, approximating the cost of DRW on the COSMAC VIP.
+///
+/// This helps games that pace their animation by drawing rather than with the delay timer, which is common among VIP
+/// era titles: ball and paddle games such as Pong and Brix, or Space Invaders, otherwise speed up whenever few
+/// sprites are on screen. The flat model (throttle disabled) runs every game at a constant instruction rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CycleCosts {
+    /// Cycles available per 60hz frame, i.e. between calls to `Cpu::update_timers`
+    pub frame_budget: u32,
+    /// Cost of every instruction other than DRW
+    pub instruction: u32,
+    /// Base cost of DRW
+    pub draw: u32,
+    /// Additional cost of DRW per sprite row
+    pub draw_row: u32,
+}
+
+impl Default for CycleCosts {
+    /// A budget of 8 plain instructions per frame, where a full height sprite costs about half a frame
+    fn default() -> CycleCosts {
+        CycleCosts {
+            frame_budget: 32,
+            instruction: 4,
+            draw: 8,
+            draw_row: 1,
+        }
+    }
+}
+
+impl CycleCosts {
+    /// Returns the cost of executing an opcode
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode to look up
+    pub fn cost(&self, opcode: u16) -> u32 {
+        if Instruction::decode(opcode) == Instruction::Drw {
+            self.draw + self.draw_row * (opcode & 0xF) as u32
+        } else {
+            self.instruction
+        }
+    }
+}
+
+/// Options for loading a program with `Cpu::load_rom_bytes_with`. All options are off by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Swap the bytes of every pair, for files produced by toolchains that wrongly write opcodes as little endian
+    /// words. A trailing odd byte is loaded as is.
+    pub swap_bytes: bool,
+    /// Maximum number of bytes to load. Bytes past it are discarded like bytes that do not fit in memory. None loads
+    /// as much as fits below the end of memory, 3584 bytes
+    pub max_size: Option<usize>,
+}
+
+/// Outcome of loading a program, to notice oversized or non Chip-8 files
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Number of bytes loaded at 512
+    pub loaded: usize,
+    /// Size of the program passed to the loader, e.g. the file size
+    pub size: usize,
+    /// True if bytes were discarded because the program did not fit in memory or exceeded the maximum size
+    pub truncated: bool,
+}
+
+/// What a single instruction did, returned by `Cpu::step`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepResult {
+    /// Address the instruction was fetched from
+    pub pc: u16,
+    /// The executed opcode
+    pub opcode: u16,
+    /// Disassembly of the opcode, e.g. `ADD V0, V1`
+    pub mnemonic: String,
+    /// True if the instruction changed the display
+    pub display_updated: bool,
+    /// True if the instruction drew a sprite that collided with lit pixels
+    pub collision: bool,
+    /// (x, new value) of every register Vx the instruction changed, in register order
+    pub changed: Vec<(usize, u8)>,
+    /// True if the next instruction is at a breakpoint, so the next step stops before executing it. See
+    /// `Cpu::breakpoint_hit`
+    pub breakpoint: bool,
+    /// True if the instruction wrote to a watched address. See `Cpu::watchpoint_hit`
+    pub watchpoint: bool,
+}
+
+/// Copy of the registers at one point in time, returned by `Cpu::dump_state`. Formats as a hex table with `Display`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    /// General purpose registers V0..VF
+    pub v: [u8; 16],
+    /// I register
+    pub i: u16,
+    /// Program counter
+    pub pc: u16,
+    /// Stack pointer, the number of return addresses on the stack
+    pub sp: u8,
+    /// Delay timer
+    pub delay_timer: u8,
+    /// Sound timer
+    pub sound_timer: u8,
+    /// Return addresses. Only the first `sp` entries are in use
+    pub stack: [u16; 16],
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "PC={:03X} I={:03X} SP={:X} DT={:02X} ST={:02X}",
+            self.pc, self.i, self.sp, self.delay_timer, self.sound_timer
+        )?;
+        for (row, values) in self.v.chunks(8).enumerate() {
+            for (col, value) in values.iter().enumerate() {
+                let sep = if col == 0 { "" } else { " " };
+                write!(f, "{}V{:X}={:02X}", sep, 8 * row + col, value)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "Stack:")?;
+        if self.sp == 0 {
+            write!(f, " empty")?;
+        }
+        for addr in self.stack.iter().take(self.sp as usize) {
+            write!(f, " {:03X}", addr)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maximum number of errors kept by quiet mode until taken with `Cpu::take_logged_errors`. Older errors are dropped.
+const QUIET_LOG_LEN: usize = 64;
+
+/// Version of the save state format written by `Cpu::save_state`. Bump on any change to `SaveState`
+#[cfg(feature = "serde")]
+const STATE_VERSION: u8 = 4;
+
+/// Machine state written by `Cpu::save_state`. Settings such as quirks and sinks, and debugging aids such as
+/// breakpoints and coverage, are not part of it
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SaveState {
+    version: u8,
+    registers: Registers,
+    memory: Vec<u8>,
+    /// Two words per row of the high resolution framebuffer, the leftmost pixel in the most significant bit
+    display: Vec<[u64; 2]>,
+    /// The second display plane, in the same layout as `display`
+    second_plane: Vec<[u64; 2]>,
+    plane_mask: u8,
+    hires: bool,
+    key_state: [bool; 16],
+    waiting: Option<usize>,
+    key_queue: VecDeque<u8>,
+    frame_cycles: u32,
+    rom_len: usize,
+    program: Vec<u8>,
+    rng: ChaCha12Rng,
+    rpl_flags: [u8; 16],
+}
 
 /// Chip-8 "CPU". Contains the registers, memory, and peripherals necessary for operation
 pub struct Cpu {
     registers: Registers,
     memory: [u8; 4096],
-    display: [[bool; C8_WIDTH]; C8_HEIGHT],
+    /// Framebuffer, large enough for the high resolution mode. In the low resolution mode only the top left
+    /// `C8_WIDTH` x `C8_HEIGHT` pixels are used
+    display: Plane,
+    /// Second XO-CHIP display plane. `display` is the first
+    second_plane: Plane,
+    /// Display planes selected with Fn01, bit 0 for the first plane and bit 1 for the second
+    plane_mask: u8,
+    /// SCHIP high resolution mode, 128x64 instead of 64x32
+    hires: bool,
     /// Array to store the current state of all keys
     key_state: [bool; 16],
     /// Option used to for blocking operations that wait for user input. While waiting, the requesting register index is stored
     waiting: Option<usize>,
     /// Flag to limit screen updates to only run when changes have occured. Set by the draw and clear screen instructions
     has_disp_update: bool,
-    /// Counter for the number of cycles. Used to limit the rate of the delay and audio timers
-    cycle_count: usize,
+    /// Cycle costs of the draw throttle, disabled when None
+    cycle_costs: Option<CycleCosts>,
+    /// Cycles spent in the current frame by the draw throttle
+    frame_cycles: u32,
+    /// Instructions executed since the ROM was loaded or the machine was reset
+    instructions: u64,
+    /// Whether the ROM executed a jump to itself, see `is_halted`
+    halted: bool,
+    /// Whether the audio sink was last told to beep
+    sound_active: bool,
+    /// Optional sink notified when the beep starts and stops
+    audio_sink: Option<Box<dyn AudioSink>>,
+    /// Sink receiving the display once per 60hz frame
+    frame_sink: Option<Box<dyn DisplaySink>>,
+    /// Optional writer receiving trace output such as register dumps
+    #[cfg(feature = "std")]
+    trace: Option<Box<dyn Write>>,
+    /// Opcode that dumps the registers to the trace writer instead of executing. Disabled when None
+    debug_opcode: Option<u16>,
+    /// Active interpreter quirks. May be changed at any time and apply from the next instruction
+    quirks: Quirks,
+    /// Interpreter whose instructions are supported, see `set_profile`
+    profile: Profile,
+    /// SCHIP RPL user flags written by Fx75 and read by Fx85. Kept across resets, like on the HP48
+    rpl_flags: [u8; 16],
+    /// Number of program bytes loaded at 512 by the last `load_rom`
+    rom_len: usize,
+    /// Program as loaded, restored into memory on reset
+    program: Vec<u8>,
+    /// Opt-in checks that turn suspicious execution, such as running past the loaded program, into errors
+    strict: bool,
+    /// Sanitize all indexing so no instruction sequence can panic. See `set_safe_mode` for the rules
+    safe_mode: bool,
+    /// Report the situations safe mode sanitizes instead of silently ignoring them. Disabled when None
+    quiet: Option<QuietPolicy>,
+    /// Errors reported by quiet mode under `QuietPolicy::Continue`, oldest first
+    logged_errors: VecDeque<CpuError>,
+    /// Handling of out of range memory accesses outside of safe mode
+    memory_model: MemoryModel,
+    /// How Fx0A obtains its key
+    key_wait_model: KeyWaitModel,
+    /// Key presses buffered for Fx0A under `KeyWaitModel::Queued`, oldest first
+    key_queue: VecDeque<u8>,
+    /// Error raised by the instruction being executed, returned from `tick` once it completes
+    fault: Option<CpuError>,
+    /// Most recently executed (PC, opcode) pairs, oldest first
+    instruction_log: VecDeque<(u16, u16)>,
+    /// Maximum length of the instruction log. Logging is disabled when 0
+    instruction_log_len: usize,
+    /// Memory addresses fetched as part of an executed instruction
+    coverage: [bool; 4096],
+    /// Pixels that collided during the last draw, as (x, y). Recording is disabled when None
+    collisions: Option<Vec<(usize, usize)>>,
+    /// Addresses of the active breakpoints
+    breakpoints: AddrSet,
+    /// Breakpoint execution stopped at, if any. Execution resumes from it on the next attempt
+    breakpoint_hit: Option<u16>,
+    /// Whether execution is paused with `set_paused`. `tick` executes nothing and the beep is silenced while set
+    paused: bool,
+    /// Active watchpoints, in the order they were added
+    watchpoints: Vec<Watchpoint>,
+    /// Watched address the last instruction wrote to, if any
+    watchpoint_hit: Option<u16>,
+    /// Source of random numbers for RND. ChaCha12, the algorithm behind `rand::rngs::StdRng`, named explicitly so its
+    /// state can be saved
+    rng: ChaCha12Rng,
+    /// Seed the random number generator was created with, if any. Reapplied on reset
+    seed: Option<u64>,
 }
 
 /// Registers for the Chip-8
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 struct Registers {
     /// Array of general purpose registers in form: Vx where x is a hexadecimal digit 0..F
     v: [u8; 16],
@@ -30,8 +527,9 @@ struct Registers {
     sound_timer: u8,
     /// Program Counter
     pc: u16,
-    /// Stack Pointer
+    /// Stack Pointer. Number of return addresses on the stack, the next call stores its return address at `stack[sp]`
     sp: u8,
+    /// Return addresses, supporting 16 levels of nested subroutines
     stack: [u16; 16],
 }
 
@@ -53,19 +551,173 @@ impl Registers {
 /// Helper struct that stores all parts of an opcode that could be used for any Chip-8 Operation. Names were chosen to
 /// match the names used in the Chip-8 instruction set. Positions of each attribute in a u16 are documented below with
 ///  Z's representing "Don't care" nibbles.
-struct Opcode {
-    /// aZZZ
-    a: u8,
+pub(crate) struct Opcode {
     /// ZZkk
-    kk: u8,
+    pub(crate) kk: u8,
     /// ZZZn
-    n: u8,
+    pub(crate) n: u8,
     /// Znnn
-    nnn: u16,
+    pub(crate) nnn: u16,
     /// ZxZZ
-    x: usize,
+    pub(crate) x: usize,
     /// ZZyZ
-    y: usize,
+    pub(crate) y: usize,
+    /// Instruction identified by the opcode
+    pub(crate) instruction: Instruction,
+}
+
+/// Identity of a decoded instruction, named after the `Cpu` method executing it. Shared by execution, disassembly and
+/// analysis so the decoding table only exists once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0 - CLS
+    Cls,
+    /// 00EE - RET
+    Ret,
+    /// 00Cn - SCD nibble, SCHIP: scroll the display down n lines
+    Scd,
+    /// 00FB - SCR, SCHIP: scroll the display right 4 pixels
+    Scr,
+    /// 00FC - SCL, SCHIP: scroll the display left 4 pixels
+    Scl,
+    /// 00FE - LOW, SCHIP: switch to the 64x32 low resolution mode
+    Low,
+    /// 00FF - HIGH, SCHIP: switch to the 128x64 high resolution mode
+    High,
+    /// 0nnn - SYS addr
+    Sys,
+    /// 1nnn - JP addr
+    Jp,
+    /// 2nnn - CALL addr
+    Call,
+    /// 3xkk - SE Vx, byte
+    Sec,
+    /// 4xkk - SNE Vx, byte
+    Snec,
+    /// 5xy0 - SE Vx, Vy
+    Se,
+    /// 6xkk - LD Vx, byte
+    Ldc,
+    /// 7xkk - ADD Vx, byte
+    Addc,
+    /// 8xy0 - LD Vx, Vy
+    Ld,
+    /// 8xy1 - OR Vx, Vy
+    Or,
+    /// 8xy2 - AND Vx, Vy
+    And,
+    /// 8xy3 - XOR Vx, Vy
+    Xor,
+    /// 8xy4 - ADD Vx, Vy
+    Add,
+    /// 8xy5 - SUB Vx, Vy
+    Sub,
+    /// 8xy6 - SHR Vx {, Vy}
+    Shr,
+    /// 8xy7 - SUBN Vx, Vy
+    Subn,
+    /// 8xyE - SHL Vx {, Vy}
+    Shl,
+    /// 9xy0 - SNE Vx, Vy
+    Sne,
+    /// Annn - LD I, addr
+    Ldi,
+    /// Bnnn - JP V0, addr
+    Jp0,
+    /// Cxkk - RND Vx, byte
+    Rnd,
+    /// Dxyn - DRW Vx, Vy, nibble
+    Drw,
+    /// Ex9E - SKP Vx
+    Skp,
+    /// ExA1 - SKNP Vx
+    Sknp,
+    /// Fx07 - LD Vx, DT
+    Ldxdt,
+    /// Fx0A - LD Vx, K
+    Ldxk,
+    /// Fx15 - LD DT, Vx
+    Lddtx,
+    /// Fx18 - LD ST, Vx
+    Ldstx,
+    /// Fx1E - ADD I, Vx
+    Addi,
+    /// Fn01 - PLANE n, XO-CHIP: select the display planes to draw on
+    Plane,
+    /// Fx29 - LD F, Vx
+    Ldf,
+    /// Fx30 - LD HF, Vx, SCHIP: point I at the large sprite of a digit
+    Ldhf,
+    /// Fx33 - LD B, Vx
+    Ldb,
+    /// Fx55 - LD [I], Vx
+    Ldix,
+    /// Fx65 - LD Vx, [I]
+    Ldxi,
+    /// Fx75 - LD R, Vx, SCHIP: store registers in the RPL user flags
+    Ldrx,
+    /// Fx85 - LD Vx, R, SCHIP: read registers from the RPL user flags
+    Ldxr,
+    /// Does not decode to an instruction
+    Unknown,
+}
+
+impl Instruction {
+    /// Identify the instruction of an opcode
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - A u16 opcode to identify
+    pub fn decode(op: u16) -> Instruction {
+        let (a, x, y, n) = (op >> 12, op >> 8 & 0xf, op >> 4 & 0xf, op & 0xf);
+        match (a, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Cls,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Ret,
+            (0x0, 0x0, 0xC, _) => Instruction::Scd,
+            (0x0, 0x0, 0xF, 0xB) => Instruction::Scr,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::Scl,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::Low,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::High,
+            (0x0, _, _, _) => Instruction::Sys,
+            (0x1, _, _, _) => Instruction::Jp,
+            (0x2, _, _, _) => Instruction::Call,
+            (0x3, _, _, _) => Instruction::Sec,
+            (0x4, _, _, _) => Instruction::Snec,
+            (0x5, _, _, 0x0) => Instruction::Se,
+            (0x6, _, _, _) => Instruction::Ldc,
+            (0x7, _, _, _) => Instruction::Addc,
+            (0x8, _, _, 0x0) => Instruction::Ld,
+            (0x8, _, _, 0x1) => Instruction::Or,
+            (0x8, _, _, 0x2) => Instruction::And,
+            (0x8, _, _, 0x3) => Instruction::Xor,
+            (0x8, _, _, 0x4) => Instruction::Add,
+            (0x8, _, _, 0x5) => Instruction::Sub,
+            (0x8, _, _, 0x6) => Instruction::Shr,
+            (0x8, _, _, 0x7) => Instruction::Subn,
+            (0x8, _, _, 0xE) => Instruction::Shl,
+            (0x9, _, _, 0x0) => Instruction::Sne,
+            (0xA, _, _, _) => Instruction::Ldi,
+            (0xB, _, _, _) => Instruction::Jp0,
+            (0xC, _, _, _) => Instruction::Rnd,
+            (0xD, _, _, _) => Instruction::Drw,
+            (0xE, _, 0x9, 0xE) => Instruction::Skp,
+            (0xE, _, 0xA, 0x1) => Instruction::Sknp,
+            (0xF, _, 0x0, 0x7) => Instruction::Ldxdt,
+            (0xF, _, 0x0, 0xA) => Instruction::Ldxk,
+            (0xF, _, 0x1, 0x5) => Instruction::Lddtx,
+            (0xF, _, 0x1, 0x8) => Instruction::Ldstx,
+            (0xF, _, 0x1, 0xE) => Instruction::Addi,
+            (0xF, _, 0x0, 0x1) => Instruction::Plane,
+            (0xF, _, 0x2, 0x9) => Instruction::Ldf,
+            (0xF, _, 0x3, 0x0) => Instruction::Ldhf,
+            (0xF, _, 0x3, 0x3) => Instruction::Ldb,
+            (0xF, _, 0x5, 0x5) => Instruction::Ldix,
+            (0xF, _, 0x6, 0x5) => Instruction::Ldxi,
+            (0xF, _, 0x7, 0x5) => Instruction::Ldrx,
+            (0xF, _, 0x8, 0x5) => Instruction::Ldxr,
+            _ => Instruction::Unknown,
+        }
+    }
 }
 
 impl Opcode {
@@ -76,498 +728,4039 @@ impl Opcode {
     /// * `op` - A u16 opcode to break apart
     pub fn from_op(op: u16) -> Self {
         Opcode {
-            a: (op >> 12 & 0xf) as u8,
             kk: (op & 0xff) as u8,
             n: (op & 0xf) as u8,
             nnn: op & 0xfff,
             x: (op >> 8 & 0xf) as usize,
             y: (op >> 4 & 0xf) as usize,
+            instruction: Instruction::decode(op),
         }
     }
 }
 
 impl Cpu {
     /// Returns an initialized Chip-8 "CPU" with its default values
+    #[cfg(feature = "std")]
     pub fn initialize() -> Cpu {
+        Cpu::with_rng(ChaCha12Rng::from_entropy(), None)
+    }
+
+    /// Returns an initialized Chip-8 "CPU" like `initialize`, after checking that the fontset was loaded correctly
+    ///
+    /// # Returns
+    ///
+    /// * `cpu` - The CPU, ready to load a ROM, or the reason it could not be initialized
+    #[cfg(feature = "std")]
+    pub fn try_initialize() -> Result<Cpu, InitError> {
+        Cpu::initialize().validate()
+    }
+
+    /// Returns an initialized Chip-8 "CPU" like `with_seed`, after checking that the fontset was loaded correctly.
+    /// Available without the `std` feature, where there is no source of entropy to seed from.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed for the random number generator
+    ///
+    /// # Returns
+    ///
+    /// * `cpu` - The CPU, ready to load a ROM, or the reason it could not be initialized
+    pub fn try_with_seed(seed: u64) -> Result<Cpu, InitError> {
+        Cpu::with_seed(seed).validate()
+    }
+
+    /// Check that the fontset in memory matches the built-in one
+    fn validate(self) -> Result<Cpu, InitError> {
+        match FONTSET
+            .iter()
+            .zip(self.memory.iter())
+            .position(|(a, b)| a != b)
+        {
+            Some(addr) => Err(InitError::FontsetMismatch(addr)),
+            None => Ok(self),
+        }
+    }
+
+    /// Returns an initialized Chip-8 "CPU" drawing random numbers from `rng`
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - Source of random numbers for RND
+    /// * `seed` - Seed `rng` was created with, if any
+    fn with_rng(rng: ChaCha12Rng, seed: Option<u64>) -> Cpu {
         let mut cpu = Cpu {
             registers: Registers::initialize(),
             memory: [0; 4096],
-            display: [[false; C8_WIDTH]; C8_HEIGHT],
+            display: [[false; HIRES_WIDTH]; HIRES_HEIGHT],
+            second_plane: [[false; HIRES_WIDTH]; HIRES_HEIGHT],
+            plane_mask: 1,
+            hires: false,
             key_state: [false; 16],
             waiting: None,
             has_disp_update: false,
-            cycle_count: 0,
+            cycle_costs: None,
+            frame_cycles: 0,
+            instructions: 0,
+            halted: false,
+            sound_active: false,
+            audio_sink: None,
+            frame_sink: None,
+            #[cfg(feature = "std")]
+            trace: None,
+            debug_opcode: None,
+            quirks: Quirks::default(),
+            profile: Profile::XoChip,
+            rpl_flags: [0; 16],
+            rom_len: 0,
+            program: Vec::new(),
+            strict: false,
+            safe_mode: false,
+            quiet: None,
+            logged_errors: VecDeque::new(),
+            memory_model: MemoryModel::Error,
+            key_wait_model: KeyWaitModel::Blocking,
+            key_queue: VecDeque::new(),
+            fault: None,
+            instruction_log: VecDeque::new(),
+            instruction_log_len: 0,
+            coverage: [false; 4096],
+            collisions: None,
+            breakpoints: AddrSet::new(),
+            breakpoint_hit: None,
+            paused: false,
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            rng,
+            seed,
         };
         cpu.load_sprites();
         cpu
     }
 
-    /// Loads a ROM into memory
+    /// Returns an initialized Chip-8 "CPU" whose random numbers are seeded deterministically, so that RND produces
+    /// the same sequence on every run
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed for the random number generator
+    pub fn with_seed(seed: u64) -> Cpu {
+        Cpu::with_rng(ChaCha12Rng::seed_from_u64(seed), Some(seed))
+    }
+
+    /// Loads a ROM into memory. The file size is checked before reading, so oversized files are rejected without
+    /// touching memory.
     ///
     /// # Arguments
     ///
     /// * `path` - String slice that holds the path to the ROM file
-    pub fn load_rom(&mut self, path: &str) {
+    ///
+    /// # Errors
+    ///
+    /// * `RomError::NotFound` - There is no file at `path`
+    /// * `RomError::Io` - The file could not be read
+    /// * `RomError::TooLarge` - The file does not fit in memory from 0x200 to the end of the address space
+    #[cfg(feature = "std")]
+    pub fn load_rom(&mut self, path: &str) -> Result<(), RomError> {
         use std::fs::File;
         use std::io::prelude::*;
 
-        let mut rom = File::open(path).expect("Unable to open ROM");
-
-        let _ = rom
-            .read(&mut self.memory[512..])
-            .expect("Unable to read ROM into memory");
+        let mut rom = File::open(path)?;
+        let size = rom.metadata()?.len() as usize;
+        if size > self.memory.len() - 512 {
+            return Err(RomError::TooLarge(size));
+        }
+        let mut data = Vec::with_capacity(size);
+        rom.read_to_end(&mut data)?;
+        // Checked again in case the file grew since its size was read
+        self.load_rom_bytes(&data)
     }
 
-    /// Load the pre-defined sprites into memory
-    fn load_sprites(&mut self) {
-        let sprites = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        self.memory[..sprites.len()].copy_from_slice(&sprites[..])
+    /// Loads a ROM from memory, without touching the filesystem. Use `load_rom_bytes_with` to load the part of an
+    /// oversized ROM that fits instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Program bytes to load at 512
+    ///
+    /// # Errors
+    ///
+    /// * `RomError::TooLarge` - `data` does not fit in memory from 0x200 to the end of the address space. Memory is
+    ///   left untouched
+    pub fn load_rom_bytes(&mut self, data: &[u8]) -> Result<(), RomError> {
+        if data.len() > self.memory.len() - 512 {
+            return Err(RomError::TooLarge(data.len()));
+        }
+        self.load_rom_bytes_with(data, LoadOptions::default());
+        Ok(())
     }
 
-    /// Perform a single tick of the Chip-8. If the system is not currently waiting for user input, this function will
-    /// pull a u16 from the memory location pointed to by the program counter, increment the program counter, run the
-    /// opcode, and decrement the timers. If the system is waiting for input, only the timers will be operated.
-    pub fn tick(&mut self) {
-        if self.waiting == None {
-            self.has_disp_update = false;
+    /// Assembles a program and loads it at 512, without touching the filesystem. See `asm::assemble` for the syntax.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Assembly source
+    ///
+    /// # Errors
+    ///
+    /// * `AsmError` - The first line that does not assemble. Memory is left untouched
+    pub fn load_asm(&mut self, source: &str) -> Result<(), AsmError> {
+        let program = asm::assemble(source)?;
+        // The assembler rejects programs running past the end of memory, so nothing is discarded
+        self.load_rom_bytes_with(&program, LoadOptions::default());
+        Ok(())
+    }
 
-            let pc = self.registers.pc as usize;
-            self.registers.pc += 2;
-            self.process_opcode((self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16);
+    /// Loads a ROM from memory like `load_rom_bytes`, with the passed load options. Bytes that do not fit below the
+    /// end of memory, or past `options.max_size`, are discarded rather than rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Program bytes to load at 512
+    /// * `options` - Transformations and limits to apply while loading
+    ///
+    /// # Returns
+    ///
+    /// * `report` - Number of bytes loaded versus the size of `data`, and whether anything was discarded
+    pub fn load_rom_bytes_with(&mut self, data: &[u8], options: LoadOptions) -> LoadReport {
+        let mut limit = self.memory.len() - 512;
+        if let Some(max_size) = options.max_size {
+            limit = limit.min(max_size);
         }
-
-        if self.cycle_count % 8 == 0 {
-            if self.registers.delay_timer > 0 {
-                self.registers.delay_timer -= 1;
+        let mut program = data[..data.len().min(limit)].to_vec();
+        if options.swap_bytes {
+            for pair in program.chunks_exact_mut(2) {
+                pair.swap(0, 1);
             }
+        }
+        self.rom_len = program.len();
+        self.memory[512..512 + self.rom_len].copy_from_slice(&program);
+        self.reset_coverage();
+        self.instructions = 0;
+        self.program = program;
+        LoadReport {
+            loaded: self.rom_len,
+            size: data.len(),
+            truncated: self.rom_len < data.len(),
+        }
+    }
 
-            if self.registers.sound_timer > 0 {
-                //TODO: Make sound
-                self.registers.sound_timer -= 1;
-            }
+    /// Restart the loaded program. Registers, timers, keys and memory return to their initial state and the program
+    /// is reloaded, undoing any self modification. A seeded random number generator starts its sequence over. The
+    /// display is cleared. Configuration such as quirks, sinks and breakpoints is kept.
+    pub fn reset(&mut self) {
+        self.warm_reset();
+        self.set_hires(false);
+    }
+
+    /// Restart the loaded program like `reset`, but leave the display untouched so the last frame stays visible
+    pub fn warm_reset(&mut self) {
+        self.registers = Registers::initialize();
+        self.memory = [0; 4096];
+        self.load_sprites();
+        self.memory[512..512 + self.program.len()].copy_from_slice(&self.program);
+        self.key_state = [false; 16];
+        self.waiting = None;
+        self.key_queue.clear();
+        self.plane_mask = 1;
+        self.frame_cycles = 0;
+        self.instructions = 0;
+        self.halted = false;
+        self.fault = None;
+        self.breakpoint_hit = None;
+        self.watchpoint_hit = None;
+        self.instruction_log.clear();
+        if let Some(seed) = self.seed {
+            self.rng = ChaCha12Rng::seed_from_u64(seed);
         }
-        self.cycle_count += 1;
+        self.update_sound();
     }
 
-    /// Get a non-mutable reference to the display so it can be viewed by a rendering routine.
-    pub fn view_display(&mut self) -> &[[bool; C8_WIDTH]; C8_HEIGHT] {
-        &self.display
+    /// Save the state of the machine in a compact binary format, for `load_state` to restore later. The state covers
+    /// the registers, stack, memory, display, keys, a pending wait for a key press, the loaded program and the random
+    /// number generator, so execution continues identically after loading it. Settings such as quirks, sinks and
+    /// debugging aids are not saved.
+    ///
+    /// # Returns
+    ///
+    /// * `state` - The encoded state
+    #[cfg(feature = "serde")]
+    pub fn save_state(&self) -> Vec<u8> {
+        let state = SaveState {
+            version: STATE_VERSION,
+            registers: self.registers,
+            memory: self.memory.to_vec(),
+            display: pack_plane(&self.display),
+            second_plane: pack_plane(&self.second_plane),
+            plane_mask: self.plane_mask,
+            hires: self.hires,
+            key_state: self.key_state,
+            waiting: self.waiting,
+            key_queue: self.key_queue.clone(),
+            frame_cycles: self.frame_cycles,
+            rom_len: self.rom_len,
+            program: self.program.clone(),
+            rng: self.rng.clone(),
+            rpl_flags: self.rpl_flags,
+        };
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&state, &mut data).expect("Writing to memory cannot fail");
+        data
     }
 
-    /// Set the key at the specified index as pressed. If the system was waiting for the user to press a key, the
-    /// requesting register will be set with the key index and the `waiting` flag will be cleared.
+    /// Restore a state saved with `save_state`, replacing the current machine state. Settings are kept. The display
+    /// is marked as updated so frontends present the restored frame.
     ///
     /// # Arguments
     ///
-    /// * `key` - Index of the key to set as pressed
-    pub fn set_key_pressed(&mut self, key: usize) {
-        self.key_state[key] = true;
-        if let Some(x) = self.waiting {
-            self.registers.v[x] = key as u8;
-            self.waiting = None;
+    /// * `data` - State returned by `save_state`
+    ///
+    /// # Errors
+    ///
+    /// * `StateError::Corrupt` - The data could not be decoded
+    /// * `StateError::Version` - The state was saved in an incompatible format
+    /// * `StateError::Invalid` - The state decoded, but describes an impossible machine
+    ///
+    /// The machine is unchanged on error.
+    #[cfg(feature = "serde")]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let state: SaveState =
+            ciborium::de::from_reader(data).map_err(|e| StateError::Corrupt(e.to_string()))?;
+        if state.version != STATE_VERSION {
+            return Err(StateError::Version(state.version));
         }
+        if state.memory.len() != self.memory.len()
+            || state.display.len() != HIRES_HEIGHT
+            || state.second_plane.len() != HIRES_HEIGHT
+            || state.plane_mask > 0x3
+            || state.registers.sp as usize > state.registers.stack.len()
+            || state.waiting.is_some_and(|x| x > 0xF)
+            || state.key_queue.iter().any(|&key| key > 0xF)
+            || state.program.len() != state.rom_len
+            || state.rom_len > self.memory.len() - 512
+        {
+            return Err(StateError::Invalid);
+        }
+
+        self.registers = state.registers;
+        self.memory.copy_from_slice(&state.memory);
+        unpack_plane(&mut self.display, &state.display);
+        unpack_plane(&mut self.second_plane, &state.second_plane);
+        self.plane_mask = state.plane_mask;
+        self.hires = state.hires;
+        self.key_state = state.key_state;
+        self.waiting = state.waiting;
+        self.key_queue = state.key_queue;
+        self.frame_cycles = state.frame_cycles;
+        self.rom_len = state.rom_len;
+        self.program = state.program;
+        // Detected again by the next tick if the state was saved at a jump to itself
+        self.halted = false;
+        self.rng = state.rng;
+        self.rpl_flags = state.rpl_flags;
+        self.fault = None;
+        self.has_disp_update = true;
+        self.update_sound();
+        Ok(())
     }
 
-    /// Set the key at the specified index as released.
+    /// Compute the SHA-1 hash of the loaded program, as used by the community compatibility database to identify ROMs
+    ///
+    /// # Returns
+    ///
+    /// * `hash` - Lowercase hex digest of the `rom_len` bytes loaded at 512
+    pub fn rom_sha1(&self) -> String {
+        sha1_smol::Sha1::from(&self.memory[512..512 + self.rom_len])
+            .digest()
+            .to_string()
+    }
+
+    /// Enable or disable strict mode. In strict mode, fetching an instruction outside of the loaded program (e.g. after
+    /// a ROM crashes into zeroed memory) stops execution with `CpuError::RanPastProgram`. Disabled by default since
+    /// self-modifying ROMs may legitimately execute code they wrote outside of the program area.
     ///
     /// # Arguments
     ///
-    /// * `key` - Index of the key to set as released
-    pub fn set_key_released(&mut self, key: usize) {
-        self.key_state[key] = false;
+    /// * `strict` - True to enable strict checks
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
     }
 
-    /// Check whether the system has a display update available
+    /// Enable or disable safe mode, which makes the CPU robust against any ROM or instruction sequence. In safe mode:
     ///
-    /// # Returns
+    /// * Memory addresses (fetches, `I` based reads and writes) wrap within the 4KB address space, regardless of the
+    ///   memory model
+    /// * The program counter wraps within the 4KB address space
+    /// * Key indices, from `Vx` or the key APIs, are masked to 0..F
+    /// * `CALL` with a full stack and `RET` with an empty stack are ignored
+    /// * Unknown opcodes are ignored
     ///
-    /// * `has_disp_update` - True if a display update was performed in the past tick. False otherwise.
-    pub fn has_disp_update(&self) -> bool {
-        self.has_disp_update
+    /// Outside of safe mode stack errors and unknown opcodes stop the instruction and `tick` returns them, the other
+    /// situations panic.
+    /// Disabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `safe_mode` - True to enable safe mode
+    pub fn set_safe_mode(&mut self, safe_mode: bool) {
+        self.safe_mode = safe_mode;
+    }
+
+    /// Enable or disable quiet mode, the robustness contract for embedding: no opcode, memory access or key index can
+    /// make the CPU panic, whatever the ROM does. Best set right after construction. Quiet mode turns on safe mode (see
+    /// `set_safe_mode`), and disabling it turns safe mode off again. On top of safe mode, each of these is reported as
+    /// a `CpuError` according to the policy:
+    ///
+    /// * `CALL` with a full stack - `CpuError::StackOverflow`
+    /// * `RET` with an empty stack - `CpuError::StackUnderflow`
+    /// * An unknown opcode - `CpuError::UnknownOpcode`
+    /// * A memory access past the end of the address space under `MemoryModel::Error` -
+    ///   `CpuError::MemoryOutOfBounds`. The access wraps, as in safe mode. Under `MemoryModel::Wrap` wrapping is
+    ///   expected and not reported.
+    ///
+    /// Under `QuietPolicy::Continue` the error is logged, written to the trace writer if one is set, and the
+    /// instruction completes as in safe mode, so `tick` never fails. Under `QuietPolicy::Halt` the instruction is
+    /// abandoned as with other faults and `tick` returns the error. Strict mode checks are unaffected and still
+    /// return `CpuError::RanPastProgram` from `tick`.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - What to do on an error, or None to disable quiet mode
+    pub fn set_quiet(&mut self, policy: Option<QuietPolicy>) {
+        self.quiet = policy;
+        self.safe_mode = policy.is_some();
+        self.logged_errors.clear();
+    }
+
+    /// Take the errors logged by quiet mode under `QuietPolicy::Continue`, oldest first. Only the most recent 64 are
+    /// kept.
+    pub fn take_logged_errors(&mut self) -> Vec<CpuError> {
+        self.logged_errors.drain(..).collect()
     }
 
-    /// Process a single opcode. Matches the opcode against the decoding table and calls the correct operation with the
-    /// necessary arguments.
+    /// Report a situation sanitized by safe mode according to the quiet policy. Does nothing outside quiet mode.
     ///
     /// # Arguments
     ///
-    /// * `opcode` - A single Chip-8 opcode. Invalid opcodes will panic.
-    fn process_opcode(&mut self, opcode: u16) {
-        let op = Opcode::from_op(opcode);
-        match (op.a, op.x, op.y, op.n) {
-            (0x0, 0x0, 0xE, 0x0) => self.cls(),
-            (0x0, 0x0, 0xE, 0xE) => self.ret(),
-            (0x0, _, _, _) => self.sys(op.nnn),
-            (0x1, _, _, _) => self.jp(op.nnn),
-            (0x2, _, _, _) => self.call(op.nnn),
-            (0x3, _, _, _) => self.sec(op.x, op.kk),
-            (0x4, _, _, _) => self.snec(op.x, op.kk),
-            (0x5, _, _, 0x0) => self.se(op.x, op.y),
-            (0x6, _, _, _) => self.ldc(op.x, op.kk),
-            (0x7, _, _, _) => self.addc(op.x, op.kk),
-            (0x8, _, _, 0x0) => self.ld(op.x, op.y),
-            (0x8, _, _, 0x1) => self.or(op.x, op.y),
-            (0x8, _, _, 0x2) => self.and(op.x, op.y),
-            (0x8, _, _, 0x3) => self.xor(op.x, op.y),
-            (0x8, _, _, 0x4) => self.add(op.x, op.y),
-            (0x8, _, _, 0x5) => self.sub(op.x, op.y),
-            (0x8, _, _, 0x6) => self.shr(op.x),
-            (0x8, _, _, 0x7) => self.subn(op.x, op.y),
-            (0x8, _, _, 0xE) => self.shl(op.x),
-            (0x9, _, _, 0x0) => self.sne(op.x, op.y),
-            (0xA, _, _, _) => self.ldi(op.nnn),
-            (0xB, _, _, _) => self.jp0(op.nnn),
-            (0xC, _, _, _) => self.rnd(op.x, op.kk),
-            (0xD, _, _, _) => self.drw(op.x, op.y, op.n),
-            (0xE, _, 0x9, 0xE) => self.skp(op.x),
-            (0xE, _, 0xA, 0x1) => self.sknp(op.x),
-            (0xF, _, 0x0, 0x7) => self.ldxdt(op.x),
-            (0xF, _, 0x0, 0xA) => self.ldxk(op.x),
-            (0xF, _, 0x1, 0x5) => self.lddtx(op.x),
-            (0xF, _, 0x1, 0x8) => self.ldstx(op.x),
-            (0xF, _, 0x1, 0xE) => self.addi(op.x),
-            (0xF, _, 0x2, 0x9) => self.ldf(op.x),
-            (0xF, _, 0x3, 0x3) => self.ldb(op.x),
-            (0xF, _, 0x5, 0x5) => self.ldix(op.x),
-            (0xF, _, 0x6, 0x5) => self.ldxi(op.x),
-            (_, _, _, _) => panic!(
-                "Unidentified opcode: {:X} {:X} {:X} {:X}",
-                op.a, op.x, op.y, op.n
-            ),
-        }
-    }
-
-    /// CLS - Clear display
-    fn cls(&mut self) {
-        for i in 0..self.display.len() {
-            for j in 0..self.display[i].len() {
-                self.display[i][j] = false;
+    /// * `error` - What went wrong
+    fn report(&mut self, error: CpuError) {
+        match self.quiet {
+            Some(QuietPolicy::Continue) => {
+                if self.logged_errors.len() == QUIET_LOG_LEN {
+                    self.logged_errors.pop_front();
+                }
+                self.logged_errors.push_back(error);
+                #[cfg(feature = "std")]
+                {
+                    if let Some(trace) = self.trace.as_mut() {
+                        // Tracing is best effort and must not disturb emulation
+                        let _ = writeln!(trace, "ignored: {}", error);
+                    }
+                }
+            }
+            Some(QuietPolicy::Halt) => {
+                self.fault.get_or_insert(error);
             }
+            None => {}
         }
-        self.has_disp_update = true;
     }
 
-    /// RET - return from subroutine
-    fn ret(&mut self) {
-        if self.registers.sp == 0 {
-            panic!("Returned when stack pointer was already 0");
-        }
-        self.registers.pc = self.registers.stack[self.registers.sp as usize];
-        self.registers.sp -= 1;
+    /// Address of the instruction being executed, for error reports
+    fn current_pc(&self) -> u16 {
+        self.registers.pc.wrapping_sub(2) & 0xFFF
     }
 
-    /// Legacy routine, ignored
-    fn sys(&mut self, _nnn: u16) {}
+    /// Enable or disable throttling instructions by draw activity. See `CycleCosts`.
+    ///
+    /// # Arguments
+    ///
+    /// * `costs` - Cycle costs to throttle with, or None for the flat model of one instruction per tick
+    pub fn set_draw_throttle(&mut self, costs: Option<CycleCosts>) {
+        self.cycle_costs = costs;
+        self.frame_cycles = 0;
+    }
 
-    /// 1nnn - JP addr - Jump to location nnn
-    fn jp(&mut self, nnn: u16) {
-        self.registers.pc = nnn;
+    /// Set how `LD Vx, K` (Fx0A) obtains its key. Keys buffered under `KeyWaitModel::Queued` are dropped when switching
+    /// to `KeyWaitModel::Blocking`.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - Key wait model to use from the next instruction
+    pub fn set_key_wait_model(&mut self, model: KeyWaitModel) {
+        self.key_wait_model = model;
+        if model == KeyWaitModel::Blocking {
+            self.key_queue.clear();
+        }
     }
 
-    /// 2nnn - CALL addr - Call subroutine at nnn
-    fn call(&mut self, nnn: u16) {
-        self.registers.sp += 1;
-        self.registers.stack[self.registers.sp as usize] = self.registers.pc;
-        self.registers.pc = nnn;
+    /// Set how instructions accessing memory past the end of the address space are handled. Safe mode always wraps.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - Memory model to use from the next instruction
+    pub fn set_memory_model(&mut self, model: MemoryModel) {
+        self.memory_model = model;
     }
 
-    /// 3xkk - SE Vx, byte - Skip next instruction if Vx = kk
-    fn sec(&mut self, x: usize, kk: u8) {
-        if self.registers.v[x] == kk {
-            self.registers.pc += 2;
+    /// Resolve a memory address to an index into memory according to the memory model. Records a fault and returns
+    /// None if the address is out of range under `MemoryModel::Error`.
+    fn mem_index(&mut self, addr: usize) -> Option<usize> {
+        if self.safe_mode && self.memory_model == MemoryModel::Error && addr >= self.memory.len() {
+            self.report(CpuError::MemoryOutOfBounds(addr));
+        }
+        if self.safe_mode || self.memory_model == MemoryModel::Wrap {
+            Some(addr & 0xFFF)
+        } else if addr < self.memory.len() {
+            Some(addr)
+        } else {
+            self.fault.get_or_insert(CpuError::MemoryOutOfBounds(addr));
+            None
         }
     }
 
-    /// 4xkk - SNE Vx, byte - Skip next instruction if Vx != kk
-    fn snec(&mut self, x: usize, kk: u8) {
-        if self.registers.v[x] != kk {
-            self.registers.pc += 2;
+    /// Read a byte of memory. All instruction memory reads go through here. Out of range reads return 0 after
+    /// recording a fault.
+    fn read_mem(&mut self, addr: usize) -> u8 {
+        match self.mem_index(addr) {
+            Some(index) => self.memory[index],
+            None => 0,
         }
     }
 
-    /// 5xy0 - SE Vx, Vy - Skip next instruction if Vx = Vy
-    fn se(&mut self, x: usize, y: usize) {
-        if self.registers.v[x] == self.registers.v[y] {
-            self.registers.pc += 2;
+    /// Write a byte of memory. All instruction memory writes go through here. Out of range writes are dropped after
+    /// recording a fault. A write to a watched address is recorded in `watchpoint_hit`.
+    fn write_mem(&mut self, addr: usize, val: u8) {
+        if let Some(index) = self.mem_index(addr) {
+            self.memory[index] = val;
+            if self.watchpoints.iter().any(|w| w.addr as usize == index) {
+                self.watchpoint_hit.get_or_insert(index as u16);
+            }
         }
     }
 
-    /// 6xkk - LD Vx, byte - Set Vx := kk
-    fn ldc(&mut self, x: usize, kk: u8) {
-        self.registers.v[x] = kk;
+    /// Resolve a key number to an index into the key state. Masked to 0..F in safe mode.
+    fn key_index(&self, key: usize) -> usize {
+        if self.safe_mode {
+            key & 0xF
+        } else {
+            key
+        }
     }
 
-    /// 7xkk - ADD Vx, byte - Set Vx := Vx + kk
-    fn addc(&mut self, x: usize, kk: u8) {
-        let val = self.registers.v[x].wrapping_add(kk);
-        self.registers.v[x] = val;
+    /// Advance the program counter past one instruction. Wraps within the address space whenever memory accesses do,
+    /// i.e. in safe mode or under `MemoryModel::Wrap`.
+    fn skip_instruction(&mut self) {
+        if self.safe_mode || self.memory_model == MemoryModel::Wrap {
+            self.registers.pc = self.registers.pc.wrapping_add(2) & 0xFFF;
+        } else {
+            self.registers.pc += 2;
+        }
     }
 
-    /// 8xy0 - LD Vx, Vy - Set Vx := Vy
+    /// Load the pre-defined sprites into memory
+    fn load_sprites(&mut self) {
+        self.memory[..FONTSET.len()].copy_from_slice(&FONTSET);
+        self.memory[FONTSET.len()..FONTSET.len() + BIG_FONTSET.len()].copy_from_slice(&BIG_FONTSET);
+    }
+
+    /// Perform a single tick of the Chip-8. If the system is not currently waiting for user input, this function will
+    /// `step` one instruction: pull a u16 from the memory location pointed to by the program counter, increment the
+    /// program counter and run the opcode. If the system is waiting for input, nothing is executed. The timers are not
+    /// operated, call `update_timers` at 60hz for that, independent of the instruction rate.
+    ///
+    /// The program counter is checked against the breakpoints before fetching. At a breakpoint the tick executes
+    /// nothing and `breakpoint_hit` reports it, also when the ROM starts at one. The next tick resumes by executing the
+    /// instruction at the breakpoint, so a frontend pauses by not ticking and resumes by ticking again. Likewise
+    /// `watchpoint_hit` reports an instruction that wrote to a watched address, right after that instruction.
+    ///
+    /// Instructions are fetched as two consecutive bytes from any address. The program counter does not have to be
+    /// even: after a jump to an odd address the instruction straddling the two byte boundary is executed as is, as on
+    /// the original interpreter. Fetches always read memory as it is now, so self modifying code takes effect
+    /// immediately at either alignment.
+    ///
+    /// # Errors
+    ///
+    /// * `CpuError::RanPastProgram` - Strict mode only. The instruction to fetch lies outside the loaded program.
+    ///   Nothing is executed.
+    /// * `CpuError::MemoryOutOfBounds` - The instruction accessed memory past the end of the address space under
+    ///   `MemoryModel::Error`.
+    /// * `CpuError::StackOverflow` - `CALL` with a full stack, outside of safe mode. The stack is left unchanged.
+    /// * `CpuError::StackUnderflow` - `RET` with an empty stack, outside of safe mode. The stack is left unchanged.
+    /// * `CpuError::UnknownOpcode` - The opcode is not an instruction, outside of safe mode. Nothing is executed.
+    pub fn tick(&mut self) -> Result<(), CpuError> {
+        if self.paused {
+            self.has_disp_update = false;
+            return Ok(());
+        }
+        let throttled = match self.cycle_costs {
+            Some(costs) => self.frame_cycles >= costs.frame_budget,
+            None => false,
+        };
+        if !throttled {
+            self.step()?;
+        } else if self.waiting.is_none() {
+            self.has_disp_update = false;
+        }
+        self.update_sound();
+        Ok(())
+    }
+
+    /// End a 60hz frame: decrement the delay and sound timers by one, hand the display to the frame sink and start a
+    /// new draw throttle budget. Call exactly 60 times per second of emulated time, from a wall clock schedule
+    /// independent of how often `tick` is called, so the timers keep their rate whatever the instruction rate.
+    pub fn update_timers(&mut self) {
+        self.end_frame();
+        self.update_sound();
+    }
+
+    /// Run one 60hz frame: execute up to `ipf` instructions, then decrement the timers once. Execution ends early
+    /// before an instruction at a breakpoint, which `breakpoint_hit` then reports and the next frame resumes from,
+    /// after an instruction that writes to a watched address, or when the ROM starts waiting for a key. The timers are
+    /// decremented either way. The draw throttle does not apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `ipf` - Number of instructions per frame
+    ///
+    /// # Returns
+    ///
+    /// * `executed` - Number of instructions executed. Less than `ipf` if a breakpoint or watchpoint was reached or the
+    ///   ROM is waiting for a key
+    ///
+    /// # Errors
+    ///
+    /// The same as `tick`. The instructions executed before the error keep their effect, the timers are not operated.
+    pub fn step_frame(&mut self, ipf: usize) -> Result<usize, CpuError> {
+        self.watchpoint_hit = None;
+        let mut executed = 0;
+        if self.waiting.is_none() {
+            self.has_disp_update = false;
+        }
+        while executed < ipf && self.waiting.is_none() {
+            if self.stop_at_breakpoint() {
+                break;
+            }
+            self.execute()?;
+            executed += 1;
+            if self.watchpoint_hit.is_some() {
+                break;
+            }
+        }
+        self.update_timers();
+        Ok(executed)
+    }
+
+    /// Execute up to `n` instructions in one call, e.g. to advance a ROM in a test or benchmark. Execution ends early
+    /// once the ROM halts (see `is_halted`), starts waiting for a key, reaches a breakpoint, which `breakpoint_hit`
+    /// then reports as after `tick`, or writes to a watched address, which `watchpoint_hit` reports. Execution stops
+    /// before the instruction at a breakpoint, and calling again continues from it. The timers are not operated, call
+    /// `update_timers` for that.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Maximum number of instructions to execute
+    ///
+    /// # Returns
+    ///
+    /// * `executed` - Number of instructions executed. Less than `n` if execution ended early
+    ///
+    /// # Errors
+    ///
+    /// The same as `tick`. The instructions executed before the error keep their effect.
+    pub fn run_cycles(&mut self, n: usize) -> Result<usize, CpuError> {
+        self.watchpoint_hit = None;
+        let mut executed = 0;
+        if self.waiting.is_none() {
+            self.has_disp_update = false;
+        }
+        while executed < n && self.waiting.is_none() && !self.halted {
+            if self.stop_at_breakpoint() {
+                break;
+            }
+            self.execute()?;
+            executed += 1;
+            if self.watchpoint_hit.is_some() {
+                break;
+            }
+        }
+        self.update_sound();
+        Ok(executed)
+    }
+
+    /// Execute exactly one instruction and report what it did. Unlike `tick`, the timers are not operated, so a ROM
+    /// can be single stepped and inspected between instructions.
+    ///
+    /// # Returns
+    ///
+    /// * `step` - What the instruction did, or None if nothing was executed because the system is waiting for a key or
+    ///   stopped at a breakpoint (see `breakpoint_hit`)
+    ///
+    /// # Errors
+    ///
+    /// The same as `tick`.
+    pub fn step(&mut self) -> Result<Option<StepResult>, CpuError> {
+        self.watchpoint_hit = None;
+        if self.waiting.is_some() || self.stop_at_breakpoint() {
+            return Ok(None);
+        }
+        let pc = self.registers.pc;
+        let before = self.registers.v;
+        self.has_disp_update = false;
+        let opcode = self.execute()?;
+        let collision =
+            Opcode::from_op(opcode).instruction == Instruction::Drw && self.registers.v[0xF] == 1;
+        let changed = (0..16)
+            .filter(|&x| self.registers.v[x] != before[x])
+            .map(|x| (x, self.registers.v[x]))
+            .collect();
+        Ok(Some(StepResult {
+            pc,
+            opcode,
+            mnemonic: disasm::disassemble(opcode),
+            display_updated: self.has_disp_update,
+            collision,
+            changed,
+            breakpoint: self.breakpoints.contains(&self.registers.pc),
+            watchpoint: self.watchpoint_hit.is_some(),
+        }))
+    }
+
+    /// Check the program counter against the breakpoints before fetching. A breakpoint is reported in
+    /// `breakpoint_hit` once, and passed on the next check so that execution resumes from it.
+    ///
+    /// # Returns
+    ///
+    /// * `stop` - True if execution has to stop before the instruction at the program counter
+    fn stop_at_breakpoint(&mut self) -> bool {
+        let pc = self.registers.pc;
+        let resuming = self.breakpoint_hit.take() == Some(pc);
+        if !resuming && self.breakpoints.contains(&pc) {
+            self.breakpoint_hit = Some(pc);
+            return true;
+        }
+        false
+    }
+
+    /// Fetch and execute the instruction at the program counter. See `tick` for the errors.
+    ///
+    /// # Returns
+    ///
+    /// * `opcode` - The executed opcode
+    fn execute(&mut self) -> Result<u16, CpuError> {
+        let pc = self.registers.pc as usize;
+        if self.strict && (pc < 512 || pc + 2 > 512 + self.rom_len) {
+            return Err(CpuError::RanPastProgram(self.registers.pc));
+        }
+        let opcode = (self.read_mem(pc) as u16) << 8 | self.read_mem(pc + 1) as u16;
+        if let Some(e) = self.fault.take() {
+            return Err(e);
+        }
+        self.coverage[pc & 0xFFF] = true;
+        self.coverage[(pc + 1) & 0xFFF] = true;
+        if self.instruction_log_len > 0 {
+            if self.instruction_log.len() == self.instruction_log_len {
+                self.instruction_log.pop_front();
+            }
+            self.instruction_log.push_back((pc as u16, opcode));
+        }
+        self.skip_instruction();
+        self.process_opcode(opcode);
+        if let Some(e) = self.fault.take() {
+            return Err(e);
+        }
+        if let Some(costs) = self.cycle_costs {
+            self.frame_cycles += costs.cost(opcode);
+        }
+        self.instructions += 1;
+        Ok(opcode)
+    }
+
+    /// Decrement the timers and hand the display to the frame sink, once per 60hz frame
+    fn end_frame(&mut self) {
+        if self.registers.delay_timer > 0 {
+            self.registers.delay_timer -= 1;
+        }
+
+        if self.registers.sound_timer > 0 {
+            self.registers.sound_timer -= 1;
+        }
+
+        let (width, height) = self.resolution();
+        if let Some(sink) = self.frame_sink.as_mut() {
+            let rows: Vec<&[bool]> = self.display[..height]
+                .iter()
+                .map(|row| &row[..width])
+                .collect();
+            sink.present(&rows);
+        }
+        self.frame_cycles = 0;
+    }
+
+    /// Notify the audio sink if the beep has started or stopped since the last notification
+    fn update_sound(&mut self) {
+        let active = self.is_sound_active();
+        if active != self.sound_active {
+            self.sound_active = active;
+            if let Some(sink) = self.audio_sink.as_mut() {
+                if active {
+                    sink.beep_on();
+                } else {
+                    sink.beep_off();
+                }
+            }
+        }
+    }
+
+    /// Set the sink to be notified when the beep starts and stops
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Audio sink to receive beep events
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_sink = Some(sink);
+    }
+
+    /// Get the rows of the display at the active resolution so it can be viewed by a rendering routine, 64x32 or
+    /// 128x64 in the high resolution mode. This is the first display plane, see `view_planes` for both.
+    pub fn view_display(&self) -> Vec<&[bool]> {
+        let (width, height) = self.resolution();
+        self.display[..height]
+            .iter()
+            .map(|row| &row[..width])
+            .collect()
+    }
+
+    /// Get the colors of the display at the active resolution, for XO-CHIP ROMs drawing on both planes. Each pixel is
+    /// 0 to 3, with bit 0 set if it is lit in the first plane and bit 1 if it is lit in the second. `view_display`
+    /// only shows the first plane.
+    pub fn view_planes(&self) -> Vec<Vec<u8>> {
+        let (width, height) = self.resolution();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| self.display[y][x] as u8 | (self.second_plane[y][x] as u8) << 1)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns the active display resolution as (width, height), 64x32 or 128x64 in the SCHIP high resolution mode
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (C8_WIDTH, C8_HEIGHT)
+        }
+    }
+
+    /// Set the key at the specified index as pressed. If the system was waiting for the user to press a key, the
+    /// requesting register will be set with the key index and the `waiting` flag will be cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Index of the key to set as pressed
+    pub fn set_key_pressed(&mut self, key: usize) {
+        let key = self.key_index(key);
+        self.key_state[key] = true;
+        if let Some(x) = self.waiting {
+            self.registers.v[x] = key as u8;
+            self.waiting = None;
+        }
+    }
+
+    /// Check whether the system is blocked in `LD Vx, K` (Fx0A) waiting for a key press
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting.is_some()
+    }
+
+    /// Get the register a pending `LD Vx, K` (Fx0A) stores its key in, e.g. to show which register is awaiting a key
+    /// while debugging a game blocked on input
+    ///
+    /// # Returns
+    ///
+    /// * `x` - Index of the requesting register, None if the system is not waiting for a key
+    pub fn waiting_register(&self) -> Option<usize> {
+        self.waiting
+    }
+
+    /// Fulfill the pending key request: store the key in the requesting register and continue with the next
+    /// instruction. Takes effect immediately, without a tick. The key state is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Index of the key to store
+    ///
+    /// # Returns
+    ///
+    /// * `fulfilled` - False if there was no pending request
+    pub fn fulfill_key_request(&mut self, key: usize) -> bool {
+        let key = self.key_index(key);
+        match self.waiting.take() {
+            Some(x) => {
+                self.registers.v[x] = key as u8;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deliver a key press to `LD Vx, K` (Fx0A). A pending request is fulfilled right away. Otherwise, under
+    /// `KeyWaitModel::Queued`, the key is buffered for the next Fx0A; under `KeyWaitModel::Blocking` it is dropped.
+    /// The key state seen by the skip instructions is not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Index of the pressed key
+    pub fn queue_key(&mut self, key: usize) {
+        if !self.fulfill_key_request(key) && self.key_wait_model == KeyWaitModel::Queued {
+            let key = self.key_index(key);
+            self.key_queue.push_back(key as u8);
+        }
+    }
+
+    /// Stop waiting for a key press without storing a key, so the waiting `LD Vx, K` (Fx0A) behaves as a no-op and
+    /// execution continues with the next tick. Does nothing if the system is not waiting for input.
+    pub fn skip_key_wait(&mut self) {
+        self.waiting = None;
+    }
+
+    /// Set the key at the specified index as released.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Index of the key to set as released
+    pub fn set_key_released(&mut self, key: usize) {
+        let key = self.key_index(key);
+        self.key_state[key] = false;
+    }
+
+    /// Check whether the key at the specified index is currently pressed. Keys are numbered by the hex digit on the
+    /// keypad, laid out on the COSMAC VIP as:
+    ///
+    /// ```text
+    /// 1 2 3 C
+    /// 4 5 6 D
+    /// 7 8 9 E
+    /// A 0 B F
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Index of the key to check. Indices past F are never pressed
+    pub fn is_key_pressed(&self, key: usize) -> bool {
+        self.key_state.get(key).copied().unwrap_or(false)
+    }
+
+    /// Returns the state of all 16 keys, indexed by key number as in `is_key_pressed`, e.g. for a keypad overlay
+    pub fn pressed_keys(&self) -> [bool; 16] {
+        self.key_state
+    }
+
+    /// Replace the state of all 16 keys at once, e.g. to carry the keys held on the keyboard over a `load_state`.
+    /// Unlike `set_key_pressed` a pending `LD Vx, K` (Fx0A) is not fulfilled, so it keeps waiting for the next press.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - State of each key, indexed by key number as in `is_key_pressed`
+    pub fn set_pressed_keys(&mut self, keys: [bool; 16]) {
+        self.key_state = keys;
+    }
+
+    /// Check whether the system has a display update available
+    ///
+    /// # Returns
+    ///
+    /// * `has_disp_update` - True if a display update was performed in the past tick. False otherwise.
+    pub fn has_disp_update(&self) -> bool {
+        self.has_disp_update
+    }
+
+    /// Get the active quirks
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Enable or disable a single quirk without resetting the machine. The change applies from the next instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `quirk` - Quirk to change
+    /// * `enabled` - New state of the quirk
+    pub fn set_quirk(&mut self, quirk: Quirk, enabled: bool) {
+        self.quirks.set(quirk, enabled);
+    }
+
+    /// Replace all quirks at once, e.g. with those of a `Profile`. The change applies from the next instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `quirks` - New quirks
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Emulate the interpreter of a profile: apply its quirks and support only its instructions. The `xo-chip`
+    /// profile, which supports every instruction, is the default. The change applies from the next instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - Profile to emulate
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+        self.quirks = profile.quirks();
+    }
+
+    /// Get the profile whose instructions are supported. Quirks changed after `set_profile` are not reflected here,
+    /// see `quirks`.
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    /// Add a breakpoint at an instruction address. Adding an existing breakpoint has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the instruction to stop at
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Remove the breakpoint at an instruction address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the breakpoint to remove
+    ///
+    /// # Returns
+    ///
+    /// * `removed` - True if a breakpoint was removed. False if there was none at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Check whether execution stopped at a breakpoint, so a frontend knows to pause. The instruction at the breakpoint
+    /// has not been executed. Cleared once execution resumes from it.
+    ///
+    /// # Returns
+    ///
+    /// * `addr` - Address of the breakpoint, which is the program counter, or None
+    pub fn breakpoint_hit(&self) -> Option<u16> {
+        self.breakpoint_hit
+    }
+
+    /// Get the active breakpoints, in address order
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
+        let mut breakpoints: Vec<Breakpoint> = self
+            .breakpoints
+            .iter()
+            .map(|&addr| Breakpoint { addr })
+            .collect();
+        breakpoints.sort_by_key(|b| b.addr);
+        breakpoints
+    }
+
+    /// Add a watchpoint on a memory address. Adding an existing watchpoint has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Memory address to watch for writes
+    pub fn add_watchpoint(&mut self, addr: u16) {
+        if !self.watchpoints.iter().any(|w| w.addr == addr) {
+            self.watchpoints.push(Watchpoint { addr });
+        }
+    }
+
+    /// Remove the watchpoint on a memory address
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address of the watchpoint to remove
+    ///
+    /// # Returns
+    ///
+    /// * `removed` - True if a watchpoint was removed. False if there was none at `addr`.
+    pub fn remove_watchpoint(&mut self, addr: u16) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.addr != addr);
+        self.watchpoints.len() != len
+    }
+
+    /// Check whether the last instruction executed by `tick` or `step` wrote to a watched address, so a frontend knows
+    /// to pause. Cleared by the next instruction.
+    ///
+    /// # Returns
+    ///
+    /// * `addr` - Watched address that was written, or None
+    pub fn watchpoint_hit(&self) -> Option<u16> {
+        self.watchpoint_hit
+    }
+
+    /// Get the active watchpoints, in the order they were added
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Set the writer that receives trace output such as register dumps
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Destination for trace output
+    #[cfg(feature = "std")]
+    pub fn set_trace_writer(&mut self, writer: Box<dyn Write>) {
+        self.trace = Some(writer);
+    }
+
+    /// Set the opcode used as a `printf`-style debug instruction. When executed, the registers are dumped to the trace
+    /// writer and execution continues with the next instruction. By convention this is an otherwise unused `0nnn`
+    /// value such as `0x0FFF`. Note that the debug opcode takes priority over any instruction it shadows.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - Opcode that triggers a dump, or None to disable
+    pub fn set_debug_opcode(&mut self, opcode: Option<u16>) {
+        self.debug_opcode = opcode;
+    }
+
+    /// Keep a log of the most recently executed instructions, for post-mortems after a `CpuError`. Cheap enough to
+    /// leave enabled. Shrinking the log drops the oldest entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - Number of instructions to keep, or 0 to disable the log
+    pub fn set_instruction_log_len(&mut self, len: usize) {
+        self.instruction_log_len = len;
+        while self.instruction_log.len() > len {
+            self.instruction_log.pop_front();
+        }
+    }
+
+    /// Returns the number of instructions executed since the ROM was loaded or the machine was reset, by `tick`,
+    /// `step` and `step_frame`. Instructions that fail and ticks that execute nothing, e.g. while waiting for a key,
+    /// are not counted.
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Returns the coverage bitmap, one entry per memory address, true if the address was fetched as part of an
+    /// executed instruction. Shows which code paths a run exercised. Accumulates across resets and is cleared when a
+    /// ROM is loaded or by `reset_coverage`.
+    pub fn coverage(&self) -> &[bool] {
+        &self.coverage
+    }
+
+    /// Clear the coverage bitmap, e.g. to measure the coverage of a single level
+    pub fn reset_coverage(&mut self) {
+        self.coverage = [false; 4096];
+    }
+
+    /// Record the coordinates of every pixel that collides during a draw, for visualizing where sprites overlap.
+    /// Disabled by default to keep drawing cheap.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - True to record collisions from the next draw
+    pub fn set_collision_tracking(&mut self, enabled: bool) {
+        self.collisions = if enabled { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns the (x, y) of every pixel that was switched off by a collision during the last draw, in the order they
+    /// were drawn. Empty unless enabled with `set_collision_tracking`.
+    pub fn last_collisions(&self) -> &[(usize, usize)] {
+        self.collisions.as_deref().unwrap_or(&[])
+    }
+
+    /// Returns the logged instructions as (PC, opcode) pairs, oldest first. The most recent entry is the instruction
+    /// that raised the error when `tick` fails. Empty unless enabled with `set_instruction_log_len`.
+    pub fn recent_instructions(&self) -> Vec<(u16, u16)> {
+        self.instruction_log.iter().cloned().collect()
+    }
+
+    /// Write a single line snapshot of the registers to the trace writer, if one is set
+    #[cfg(feature = "std")]
+    fn dump_registers(&mut self) {
+        let r = &self.registers;
+        if let Some(trace) = self.trace.as_mut() {
+            let mut line = format!(
+                "PC={:03X} I={:03X} SP={:X} DT={:02X} ST={:02X}",
+                r.pc.wrapping_sub(2),
+                r.i,
+                r.sp,
+                r.delay_timer,
+                r.sound_timer
+            );
+            for (idx, v) in r.v.iter().enumerate() {
+                line.push_str(&format!(" V{:X}={:02X}", idx, v));
+            }
+            // Tracing is best effort and must not disturb emulation
+            let _ = writeln!(trace, "{}", line);
+        }
+    }
+
+    /// Without the `std` feature there is no trace writer, so register dumps are dropped
+    #[cfg(not(feature = "std"))]
+    fn dump_registers(&mut self) {}
+
+    /// Set a sink that receives the display exactly once per 60hz frame, whether or not it changed. The sink is
+    /// driven by the timer tick, giving a steady frame stream for encoders and spectators, unlike
+    /// `present_display` which only hands out changed frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Display sink to receive every frame
+    pub fn set_frame_sink(&mut self, sink: Box<dyn DisplaySink>) {
+        self.frame_sink = Some(sink);
+    }
+
+    /// Returns the program counter, the address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.registers.pc
+    }
+
+    /// Returns the general purpose register Vx
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Index of the register, 0..F
+    pub fn v(&self, x: usize) -> u8 {
+        self.registers.v[x]
+    }
+
+    /// Returns the I register
+    pub fn i(&self) -> u16 {
+        self.registers.i
+    }
+
+    /// Returns the stack pointer, the number of return addresses on the stack
+    pub fn sp(&self) -> u8 {
+        self.registers.sp
+    }
+
+    /// Returns the delay and sound timers
+    pub fn timers(&self) -> (u8, u8) {
+        (self.registers.delay_timer, self.registers.sound_timer)
+    }
+
+    /// Returns a copy of all registers and the stack, e.g. for a register panel or to assert on the machine state
+    pub fn dump_state(&self) -> CpuState {
+        let r = &self.registers;
+        CpuState {
+            v: r.v,
+            i: r.i,
+            pc: r.pc,
+            sp: r.sp,
+            delay_timer: r.delay_timer,
+            sound_timer: r.sound_timer,
+            stack: r.stack,
+        }
+    }
+
+    /// Check whether the beep should currently sound, i.e. the sound timer is nonzero and execution is not paused.
+    /// Lets a frontend poll the sound state from its own loop instead of installing an audio sink.
+    pub fn is_sound_active(&self) -> bool {
+        self.registers.sound_timer > 0 && !self.paused
+    }
+
+    /// Pause or resume execution. While paused `tick` executes nothing and the beep is silenced, so a frontend can
+    /// keep calling it on its usual schedule. Resuming continues exactly where execution stopped. `step` and
+    /// `step_frame` still execute while paused, for stepping through a ROM, and `update_timers` still ends a frame,
+    /// so a frontend also stops calling it while paused unless it advances a frame on purpose. Reset keeps the paused
+    /// state.
+    ///
+    /// # Arguments
+    ///
+    /// * `paused` - True to pause, false to resume
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.update_sound();
+    }
+
+    /// Check whether execution is paused with `set_paused`
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Read a byte of memory for inspection, without faulting or affecting execution
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to read
+    ///
+    /// # Returns
+    ///
+    /// * `byte` - The byte at `addr`, or None past the end of memory
+    pub fn peek(&self, addr: usize) -> Option<u8> {
+        self.memory.get(addr).cloned()
+    }
+
+    /// Disassemble the instructions around the program counter for a scrolling code view. Lines are two bytes apart
+    /// and aligned to the program counter, which also works when it is odd. Bytes the reachability analysis of the
+    /// loaded program marks as data are shown as `DB` lines, except at the program counter itself. The window is cut
+    /// short at the ends of memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `radius` - Number of lines to include before and after the program counter
+    ///
+    /// # Returns
+    ///
+    /// * `lines` - (address, disassembly, is the program counter) per line, in address order
+    pub fn disassemble_window(&self, radius: usize) -> Vec<(u16, String, bool)> {
+        let map = MemoryMap::analyze(&self.program);
+        let pc = self.registers.pc as usize;
+        let mut lines = Vec::new();
+        for line in 0..=2 * radius {
+            let addr = match (pc + 2 * line).checked_sub(2 * radius) {
+                Some(addr) if addr + 1 < self.memory.len() => addr,
+                _ => continue,
+            };
+            let (hi, lo) = (self.memory[addr], self.memory[addr + 1]);
+            let text = if addr != pc && map.region(addr as u16) == Region::Data {
+                format!("DB {:#04X}, {:#04X}", hi, lo)
+            } else {
+                disasm::disassemble((hi as u16) << 8 | lo as u16)
+            };
+            lines.push((addr as u16, text, addr == pc));
+        }
+        lines
+    }
+
+    /// Check whether the ROM has halted by executing a jump to itself, the usual way for a ROM to stop once it is done.
+    /// Every further tick executes the same jump again, so a frontend can stop ticking and save the cycles. The
+    /// timers are independent of ticking and keep running, for ROMs that halt while a beep plays out. Cleared by a
+    /// reset. Unlike `at_self_jump`, only set once the jump was executed.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Check whether the next instruction jumps to itself, the usual way for a ROM to halt once it is done
+    pub fn at_self_jump(&self) -> bool {
+        let pc = self.registers.pc as usize;
+        if pc + 1 >= self.memory.len() {
+            return false;
+        }
+        let opcode = (self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16;
+        opcode == 0x1000 | self.registers.pc
+    }
+
+    /// Present the display to a sink if a display update is available. This is the sink-based equivalent of checking
+    /// `has_disp_update` and reading `view_display`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Display sink to receive the framebuffer
+    ///
+    /// # Returns
+    ///
+    /// * `presented` - True if the sink was handed a new frame. False otherwise.
+    pub fn present_display<S: DisplaySink>(&self, sink: &mut S) -> bool {
+        if self.has_disp_update {
+            sink.present(&self.view_display());
+        }
+        self.has_disp_update
+    }
+
+    /// Check whether an opcode decodes to a supported instruction under the current configuration, without executing
+    /// anything. Instructions the interpreter of the active profile does not have, such as the SCHIP ones in the `vip`
+    /// profile, are unsupported, and execute as unknown opcodes. Useful for scanning a ROM ahead of time for
+    /// instructions the interpreter cannot run.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - A single Chip-8 opcode
+    pub fn is_opcode_supported(&self, opcode: u16) -> bool {
+        if self.debug_opcode == Some(opcode) {
+            return true;
+        }
+
+        let instruction = Instruction::decode(opcode);
+        instruction != Instruction::Unknown && self.profile.supports(instruction)
+    }
+
+    /// Process a single opcode. Decodes the opcode and calls the operation of its instruction with the necessary
+    /// arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - A single Chip-8 opcode. Unknown opcodes record a `CpuError::UnknownOpcode` fault outside of safe
+    ///   mode.
+    fn process_opcode(&mut self, opcode: u16) {
+        if self.debug_opcode == Some(opcode) {
+            self.dump_registers();
+            return;
+        }
+
+        let op = Opcode::from_op(opcode);
+        let instruction = if self.profile.supports(op.instruction) {
+            op.instruction
+        } else {
+            Instruction::Unknown
+        };
+        match instruction {
+            Instruction::Cls => self.cls(),
+            Instruction::Ret => self.ret(),
+            Instruction::Scd => self.scd(op.n),
+            Instruction::Scr => self.scr(),
+            Instruction::Scl => self.scl(),
+            Instruction::Low => self.set_hires(false),
+            Instruction::High => self.set_hires(true),
+            Instruction::Sys => self.sys(op.nnn),
+            Instruction::Jp => self.jp(op.nnn),
+            Instruction::Call => self.call(op.nnn),
+            Instruction::Sec => self.sec(op.x, op.kk),
+            Instruction::Snec => self.snec(op.x, op.kk),
+            Instruction::Se => self.se(op.x, op.y),
+            Instruction::Ldc => self.ldc(op.x, op.kk),
+            Instruction::Addc => self.addc(op.x, op.kk),
+            Instruction::Ld => self.ld(op.x, op.y),
+            Instruction::Or => self.or(op.x, op.y),
+            Instruction::And => self.and(op.x, op.y),
+            Instruction::Xor => self.xor(op.x, op.y),
+            Instruction::Add => self.add(op.x, op.y),
+            Instruction::Sub => self.sub(op.x, op.y),
+            Instruction::Shr => self.shr(op.x, op.y),
+            Instruction::Subn => self.subn(op.x, op.y),
+            Instruction::Shl => self.shl(op.x, op.y),
+            Instruction::Sne => self.sne(op.x, op.y),
+            Instruction::Ldi => self.ldi(op.nnn),
+            Instruction::Jp0 => self.jp0(op.x, op.nnn),
+            Instruction::Rnd => self.rnd(op.x, op.kk),
+            Instruction::Drw => self.drw(op.x, op.y, op.n),
+            Instruction::Skp => self.skp(op.x),
+            Instruction::Sknp => self.sknp(op.x),
+            Instruction::Ldxdt => self.ldxdt(op.x),
+            Instruction::Ldxk => self.ldxk(op.x),
+            Instruction::Lddtx => self.lddtx(op.x),
+            Instruction::Ldstx => self.ldstx(op.x),
+            Instruction::Addi => self.addi(op.x),
+            Instruction::Plane => self.plane(op.x),
+            Instruction::Ldf => self.ldf(op.x),
+            Instruction::Ldhf => self.ldhf(op.x),
+            Instruction::Ldb => self.ldb(op.x),
+            Instruction::Ldix => self.ldix(op.x),
+            Instruction::Ldxi => self.ldxi(op.x),
+            Instruction::Ldrx => self.ldrx(op.x),
+            Instruction::Ldxr => self.ldxr(op.x),
+            Instruction::Unknown => {
+                if self.safe_mode {
+                    self.report(CpuError::UnknownOpcode(opcode));
+                } else {
+                    self.fault.get_or_insert(CpuError::UnknownOpcode(opcode));
+                }
+            }
+        }
+    }
+
+    /// CLS - Clear the selected display planes
+    fn cls(&mut self) {
+        for plane in self.selected_planes() {
+            *self.plane_mut(plane) = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        }
+        self.has_disp_update = true;
+    }
+
+    /// Returns the indices of the display planes selected with Fn01, the first plane first
+    fn selected_planes(&self) -> impl Iterator<Item = usize> {
+        let mask = self.plane_mask;
+        (0..2).filter(move |plane| mask >> plane & 1 == 1)
+    }
+
+    /// Returns a display plane, 0 for the first and 1 for the second
+    ///
+    /// # Arguments
+    ///
+    /// * `plane` - Index of the plane
+    fn plane_mut(&mut self, plane: usize) -> &mut Plane {
+        if plane == 0 {
+            &mut self.display
+        } else {
+            &mut self.second_plane
+        }
+    }
+
+    /// 00Cn - SCD nibble - Scroll the selected display planes down n lines at the active resolution. Rows scrolled in
+    /// at the top are off
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Number of lines to scroll
+    fn scd(&mut self, n: u8) {
+        let (width, height) = self.resolution();
+        let n = (n as usize).min(height);
+        for plane in self.selected_planes() {
+            let display = self.plane_mut(plane);
+            for y in (0..height).rev() {
+                let row = if y >= n {
+                    display[y - n]
+                } else {
+                    [false; HIRES_WIDTH]
+                };
+                display[y][..width].copy_from_slice(&row[..width]);
+            }
+        }
+        self.has_disp_update = true;
+    }
+
+    /// 00FB - SCR - Scroll the selected display planes right 4 pixels at the active resolution. Columns scrolled in on
+    /// the left are off
+    fn scr(&mut self) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes() {
+            for row in &mut self.plane_mut(plane)[..height] {
+                row.copy_within(0..width - 4, 4);
+                row[..4].fill(false);
+            }
+        }
+        self.has_disp_update = true;
+    }
+
+    /// 00FC - SCL - Scroll the selected display planes left 4 pixels at the active resolution. Columns scrolled in on
+    /// the right are off
+    fn scl(&mut self) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes() {
+            for row in &mut self.plane_mut(plane)[..height] {
+                row.copy_within(4..width, 0);
+                row[width - 4..width].fill(false);
+            }
+        }
+        self.has_disp_update = true;
+    }
+
+    /// 00FE - LOW / 00FF - HIGH - Switch between the 64x32 and the 128x64 resolution. Both display planes are
+    /// cleared, as in modern SCHIP and XO-CHIP interpreters, so no pixels of the other resolution are left over
+    ///
+    /// # Arguments
+    ///
+    /// * `hires` - True for the high resolution mode
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        self.second_plane = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        self.has_disp_update = true;
+    }
+
+    /// Fn01 - PLANE n - XO-CHIP: select the display planes that CLS, DRW and the scroll instructions operate on. Bit 0
+    /// selects the first plane and bit 1 the second, so 0 selects none and 3 both.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Bit mask of the planes to select
+    fn plane(&mut self, n: usize) {
+        self.plane_mask = n as u8 & 0x3;
+    }
+
+    /// 00EE - RET - Return from subroutine, popping the return address from the stack
+    fn ret(&mut self) {
+        if self.registers.sp == 0 {
+            let pc = self.current_pc();
+            if self.safe_mode {
+                self.report(CpuError::StackUnderflow(pc));
+            } else {
+                self.fault.get_or_insert(CpuError::StackUnderflow(pc));
+            }
+            return;
+        }
+        self.registers.sp -= 1;
+        self.registers.pc = self.registers.stack[self.registers.sp as usize];
+    }
+
+    /// Legacy routine, ignored
+    fn sys(&mut self, _nnn: u16) {}
+
+    /// 1nnn - JP addr - Jump to location nnn. A jump to itself halts the ROM, see `is_halted`
+    fn jp(&mut self, nnn: u16) {
+        if nnn == self.current_pc() {
+            self.halted = true;
+        }
+        self.registers.pc = nnn;
+    }
+
+    /// 2nnn - CALL addr - Call subroutine at nnn, pushing the return address onto the stack
+    fn call(&mut self, nnn: u16) {
+        if self.registers.sp as usize >= self.registers.stack.len() {
+            let pc = self.current_pc();
+            if self.safe_mode {
+                self.report(CpuError::StackOverflow(pc));
+            } else {
+                self.fault.get_or_insert(CpuError::StackOverflow(pc));
+            }
+            return;
+        }
+        self.registers.stack[self.registers.sp as usize] = self.registers.pc;
+        self.registers.sp += 1;
+        self.registers.pc = nnn;
+    }
+
+    /// 3xkk - SE Vx, byte - Skip next instruction if Vx = kk
+    fn sec(&mut self, x: usize, kk: u8) {
+        if self.registers.v[x] == kk {
+            self.skip_instruction();
+        }
+    }
+
+    /// 4xkk - SNE Vx, byte - Skip next instruction if Vx != kk
+    fn snec(&mut self, x: usize, kk: u8) {
+        if self.registers.v[x] != kk {
+            self.skip_instruction();
+        }
+    }
+
+    /// 5xy0 - SE Vx, Vy - Skip next instruction if Vx = Vy
+    fn se(&mut self, x: usize, y: usize) {
+        if self.registers.v[x] == self.registers.v[y] {
+            self.skip_instruction();
+        }
+    }
+
+    /// 6xkk - LD Vx, byte - Set Vx := kk
+    fn ldc(&mut self, x: usize, kk: u8) {
+        self.registers.v[x] = kk;
+    }
+
+    /// 7xkk - ADD Vx, byte - Set Vx := Vx + kk
+    fn addc(&mut self, x: usize, kk: u8) {
+        let val = self.registers.v[x].wrapping_add(kk);
+        self.registers.v[x] = val;
+    }
+
+    /// 8xy0 - LD Vx, Vy - Set Vx := Vy
     fn ld(&mut self, x: usize, y: usize) {
         self.registers.v[x] = self.registers.v[y];
     }
 
-    /// 8xy1 - OR Vx, Vy - Set Vx := Vx OR Vy
-    fn or(&mut self, x: usize, y: usize) {
-        self.registers.v[x] |= self.registers.v[y];
+    /// 8xy1 - OR Vx, Vy - Set Vx := Vx OR Vy
+    fn or(&mut self, x: usize, y: usize) {
+        self.registers.v[x] |= self.registers.v[y];
+    }
+
+    /// 8xy2 - AND Vx, Vy - Set Vx := Vx AND Vy
+    fn and(&mut self, x: usize, y: usize) {
+        self.registers.v[x] &= self.registers.v[y];
+    }
+
+    /// 8xy3 - XOR Vx, Vy - Set Vx := Vx XOR Vy
+    fn xor(&mut self, x: usize, y: usize) {
+        self.registers.v[x] ^= self.registers.v[y];
+    }
+
+    /// 8xy4 - ADD Vx, Vy - Set Vx := Vx + Vy, set VF := carry. The flag is written last, so for ADD VF, Vy the carry
+    /// wins over the sum.
+    fn add(&mut self, x: usize, y: usize) {
+        let (val, carry) = (self.registers.v[x]).overflowing_add(self.registers.v[y]);
+        self.registers.v[x] = val;
+        // Must stay after the sum, VF may be the destination
+        self.registers.v[0xf] = carry as u8;
+    }
+
+    /// 8xy5 - SUB Vx, Vy - Set Vx := Vx - Vy, set VF := NOT borrow
+    fn sub(&mut self, x: usize, y: usize) {
+        let (val, borrow) = (self.registers.v[x]).overflowing_sub(self.registers.v[y]);
+        self.registers.v[x] = val;
+        self.registers.v[0xf] = !borrow as u8;
+    }
+
+    /// 8xy6 - SHR Vx {, Vy} - Set Vx := Vx >> 1, or Vx := Vy >> 1 with the `shift_uses_vy` quirk, set VF := shifted
+    /// out bit. The flag is written last, so for SHR VF the flag wins over the shift result.
+    fn shr(&mut self, x: usize, y: usize) {
+        let src = if self.quirks.shift_uses_vy { y } else { x };
+        let val = self.registers.v[src];
+        self.registers.v[x] = val >> 1;
+        self.registers.v[0xf] = val & 0x1;
+    }
+
+    /// 8xy7 - SUBN Vx, Vy - Set Vx := Vy - Vx, set VF := NOT borrow
+    fn subn(&mut self, x: usize, y: usize) {
+        let (val, borrow) = (self.registers.v[y]).overflowing_sub(self.registers.v[x]);
+        self.registers.v[x] = val;
+        self.registers.v[0xf] = !borrow as u8;
+    }
+
+    /// 8xyE - SHL Vx {, Vy} - Set Vx := Vx << 1, or Vx := Vy << 1 with the `shift_uses_vy` quirk, set VF := shifted
+    /// out bit. The flag is written last, so for SHL VF the flag wins over the shift result.
+    fn shl(&mut self, x: usize, y: usize) {
+        let src = if self.quirks.shift_uses_vy { y } else { x };
+        let val = self.registers.v[src];
+        self.registers.v[x] = val << 1;
+        self.registers.v[0xf] = (val & 0x80) >> 7;
+    }
+
+    /// 9xy0 - SNE Vx, Vy - Skip next instruction if Vx != Vy
+    fn sne(&mut self, x: usize, y: usize) {
+        if self.registers.v[x] != self.registers.v[y] {
+            self.skip_instruction();
+        }
+    }
+
+    /// Annn - LD I, addr - Set I := nnn
+    fn ldi(&mut self, nnn: u16) {
+        self.registers.i = nnn;
+    }
+
+    /// Bnnn - JP V0, addr - Jump to location nnn + V0, or to xnn + Vx with the `jump_uses_vx` quirk. A target past the
+    /// end of the address space wraps to its start, whatever the memory model, as the program counter is 12 bits.
+    fn jp0(&mut self, x: usize, nnn: u16) {
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers.v[x]
+        } else {
+            self.registers.v[0]
+        };
+        self.registers.pc = nnn.wrapping_add(offset as u16) & 0xFFF;
+    }
+
+    /// Cxkk - RND Vx, byte - Set Vx := random byte AND kk
+    fn rnd(&mut self, x: usize, kk: u8) {
+        self.registers.v[x] = self.rng.gen::<u8>() & kk;
+    }
+
+    /// Dxyn - DRW Vx, Vy, nibble - Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
+    /// Pixels past the screen edges wrap or are dropped according to the `clip_mode` quirk. In the high resolution
+    /// mode, Dxy0 draws a 16x16 sprite of 32 bytes, two bytes per row.
+    ///
+    /// The sprite is drawn into every plane selected with Fn01. With both planes selected, the sprite for the second
+    /// plane follows the one for the first in memory. A collision in any selected plane sets VF, unselected planes
+    /// are neither drawn nor checked.
+    fn drw(&mut self, x: usize, y: usize, n: u8) {
+        let (wrap_x, wrap_y) = match self.quirks.clip_mode {
+            ClipMode::Wrap => (true, true),
+            ClipMode::Clip => (false, false),
+            ClipMode::Amiga => (true, false),
+        };
+        let (width, height) = self.resolution();
+        let (rows, row_width) = if self.hires && n == 0 {
+            (16, 16)
+        } else {
+            (n as usize, 8)
+        };
+        let x0 = self.registers.v[x] as usize % width;
+        let y0 = self.registers.v[y] as usize % height;
+
+        self.registers.v[0xF] = 0;
+        if let Some(collisions) = self.collisions.as_mut() {
+            collisions.clear();
+        }
+        let mut start = self.registers.i as usize;
+        for plane in self.selected_planes() {
+            for i in 0..rows {
+                let i_offset = match edge_offset(y0 + i, height, wrap_y) {
+                    Some(offset) => offset,
+                    None => break,
+                };
+                // Sprite row aligned to the most significant bit, so both row widths are read the same way
+                let addr = start + i * row_width / 8;
+                let sprite = if row_width == 16 {
+                    (self.read_mem(addr) as u16) << 8 | self.read_mem(addr + 1) as u16
+                } else {
+                    (self.read_mem(addr) as u16) << 8
+                };
+                for j in 0..row_width {
+                    let j_offset = match edge_offset(x0 + j, width, wrap_x) {
+                        Some(offset) => offset,
+                        None => break,
+                    };
+                    let pixel = (sprite >> (15 - j)) & 0x1;
+
+                    if pixel == 0x1 {
+                        let lit = &mut self.plane_mut(plane)[i_offset][j_offset];
+                        let collided = *lit;
+                        *lit ^= true;
+                        if collided {
+                            self.registers.v[0xF] = 1;
+                            if let Some(collisions) = self.collisions.as_mut() {
+                                collisions.push((j_offset, i_offset));
+                            }
+                        }
+                    }
+                }
+            }
+            start += rows * row_width / 8;
+        }
+        self.has_disp_update = true;
+    }
+
+    /// Ex9E - SKP Vx - Skip next instruction if key with the value of Vx is pressed
+    fn skp(&mut self, x: usize) {
+        if self.key_state[self.key_index(self.registers.v[x] as usize)] {
+            self.skip_instruction();
+        }
+    }
+
+    /// ExA1 - SKNP Vx - Skip next instruction if key with value of Vx is not pressed
+    fn sknp(&mut self, x: usize) {
+        if !self.key_state[self.key_index(self.registers.v[x] as usize)] {
+            self.skip_instruction();
+        }
+    }
+
+    /// Fx07 - LD Vx, DT - Set Vx := Delay timer value. Reads the live timer, which only changes at the end of a 60hz
+    /// frame, so a loop polling it until zero runs for as many frames as the timer was set to, however many
+    /// instructions each frame holds. The read costs one instruction like any other.
+    fn ldxdt(&mut self, x: usize) {
+        self.registers.v[x] = self.registers.delay_timer;
+    }
+
+    /// Fx0A - LD Vx, K - Wait for a key press, store the value of the key in Vx. Under `KeyWaitModel::Queued` a
+    /// buffered key is stored without waiting.
+    fn ldxk(&mut self, x: usize) {
+        match self.key_queue.pop_front() {
+            Some(key) => self.registers.v[x] = key,
+            None => self.waiting = Some(x),
+        }
+    }
+
+    /// Fx15 - LD DT, Vx - Set delay timer := Vx
+    fn lddtx(&mut self, x: usize) {
+        self.registers.delay_timer = self.registers.v[x];
+    }
+
+    /// Fx18 - LD ST, Vx - Set sound timer := Vx. The audio sink hears the beep start right away, so even a sound
+    /// timer of 1 that is cleared at the end of the same frame produces an audible blip.
+    fn ldstx(&mut self, x: usize) {
+        self.registers.sound_timer = self.registers.v[x];
+        if self.registers.sound_timer > 0 {
+            self.update_sound();
+        }
+    }
+
+    /// Fx1E - ADD I, Vx - Set I := I + Vx
+    fn addi(&mut self, x: usize) {
+        //self.registers.i += self.registers.v[x] as u16;
+        let (val, carry) = self.registers.i.overflowing_add(self.registers.v[x] as u16);
+        self.registers.i = val;
+        self.registers.v[0xf] = carry as u8;
+    }
+
+    /// Fx29 - LD F, Vx - Set I := location of sprite for digit Vx
+    fn ldf(&mut self, x: usize) {
+        self.registers.i = self.registers.v[x] as u16 * 5;
+    }
+
+    /// Fx30 - LD HF, Vx - SCHIP: set I := location of the large sprite for digit Vx
+    fn ldhf(&mut self, x: usize) {
+        self.registers.i = FONTSET.len() as u16 + (self.registers.v[x] & 0xF) as u16 * 10;
+    }
+
+    /// Fx33 - LD B, Vx - Store BCD representation of Vx in memory locations I, I+1, and I+2
+    fn ldb(&mut self, x: usize) {
+        let val = self.registers.v[x];
+        let addr = self.registers.i as usize;
+        self.write_mem(addr, val / 100);
+        self.write_mem(addr + 1, val / 10 % 10);
+        self.write_mem(addr + 2, val % 10);
+    }
+
+    /// Fx55 - LD [I], Vx - Store registers V0 through Vx, in memory starting at location I. Addresses past the end of
+    /// memory follow the memory model. I is incremented by x + 1 with the `load_store_increments_i` quirk.
+    fn ldix(&mut self, x: usize) {
+        for i in 0..=x {
+            self.write_mem(self.registers.i as usize + i, self.registers.v[i]);
+        }
+        self.increment_i_after_load_store(x);
+    }
+
+    /// Fx65 - LD Vx, [I] - Read registers V0 through Vx from memory starting at location I. Addresses past the end of
+    /// memory follow the memory model. I is incremented by x + 1 with the `load_store_increments_i` quirk.
+    fn ldxi(&mut self, x: usize) {
+        for i in 0..=x {
+            self.registers.v[i] = self.read_mem(self.registers.i as usize + i);
+        }
+        self.increment_i_after_load_store(x);
+    }
+
+    /// Fx75 - LD R, Vx - SCHIP: store registers V0 through Vx in the RPL user flags. SCHIP only has 8 flags, XO-CHIP
+    /// all 16.
+    fn ldrx(&mut self, x: usize) {
+        self.rpl_flags[..=x].copy_from_slice(&self.registers.v[..=x]);
+    }
+
+    /// Fx85 - LD Vx, R - SCHIP: read registers V0 through Vx from the RPL user flags
+    fn ldxr(&mut self, x: usize) {
+        self.registers.v[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+
+    /// Advance I past the registers stored or loaded by Fx55/Fx65, if the `load_store_increments_i` quirk is enabled
+    fn increment_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.registers.i = self.registers.i.wrapping_add(x as u16 + 1);
+        }
+    }
+}
+
+/// Resolve a sprite pixel coordinate against a screen edge
+///
+/// # Arguments
+///
+/// * `pos` - Coordinate of the pixel, possibly past the edge
+/// * `size` - Screen size along the axis
+/// * `wrap` - True to wrap past the edge, false to clip
+fn edge_offset(pos: usize, size: usize, wrap: bool) -> Option<usize> {
+    if wrap {
+        Some(pos % size)
+    } else if pos < size {
+        Some(pos)
+    } else {
+        None
+    }
+}
+
+/// Pack a display plane into two words per row for a save state, the leftmost pixel in the most significant bit
+///
+/// # Arguments
+///
+/// * `plane` - Display plane to pack
+#[cfg(feature = "serde")]
+fn pack_plane(plane: &Plane) -> Vec<[u64; 2]> {
+    let word = |half: &[bool]| half.iter().fold(0, |bits, &lit| bits << 1 | lit as u64);
+    plane
+        .iter()
+        .map(|row| [word(&row[..64]), word(&row[64..])])
+        .collect()
+}
+
+/// Restore a display plane packed with `pack_plane`
+///
+/// # Arguments
+///
+/// * `plane` - Display plane to overwrite
+/// * `words` - Packed rows, one per row of the plane
+#[cfg(feature = "serde")]
+fn unpack_plane(plane: &mut Plane, words: &[[u64; 2]]) {
+    for (row, words) in plane.iter_mut().zip(words) {
+        for (x, lit) in row.iter_mut().enumerate() {
+            *lit = words[x / 64] >> (63 - x % 64) & 1 == 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_add() {
+        // 8xy4 - ADD Vx, Vy - Set Vx := Vx + Vy, set VF := carry
+        let mut c8 = Cpu::with_seed(0);
+
+        c8.registers.v[0] = 10;
+        c8.registers.v[1] = 15;
+        c8.add(0, 1);
+        assert_eq!(25, c8.registers.v[0]);
+        assert_eq!(0, c8.registers.v[0xf]);
+
+        c8.registers.v[0] = 254;
+        c8.registers.v[1] = 1;
+        c8.add(0, 1);
+        assert_eq!(255, c8.registers.v[0]);
+        assert_eq!(0, c8.registers.v[0xf]);
+
+        c8.registers.v[0] = 255;
+        c8.registers.v[1] = 1;
+        c8.add(0, 1);
+        assert_eq!(0, c8.registers.v[0]);
+        assert_eq!(1, c8.registers.v[0xf]);
+    }
+
+    #[test]
+    fn test_addi() {
+        // Fx1E - ADD I, Vx - Set I := I + Vx
+        let mut c8 = Cpu::with_seed(0);
+
+        c8.registers.i = 15;
+        c8.registers.v[0] = 10;
+        c8.addi(0);
+        assert_eq!(25, c8.registers.i);
+        assert_eq!(0, c8.registers.v[0xf]);
+
+        c8.registers.i = 65534;
+        c8.registers.v[0] = 1;
+        c8.addi(0);
+        assert_eq!(65535, c8.registers.i);
+        assert_eq!(0, c8.registers.v[0xf]);
+
+        c8.registers.i = 65535;
+        c8.registers.v[0] = 1;
+        c8.addi(0);
+        assert_eq!(0, c8.registers.i);
+        assert_eq!(1, c8.registers.v[0xf]);
+    }
+
+    #[test]
+    fn test_bcd() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.registers.i = 0;
+        c8.registers.v[0] = 123;
+        c8.registers.v[1] = 1;
+        c8.registers.v[2] = 45;
+
+        c8.ldb(0);
+        assert_eq!(1, c8.memory[0]);
+        assert_eq!(2, c8.memory[1]);
+        assert_eq!(3, c8.memory[2]);
+
+        c8.ldb(1);
+        assert_eq!(0, c8.memory[0]);
+        assert_eq!(0, c8.memory[1]);
+        assert_eq!(1, c8.memory[2]);
+
+        c8.ldb(2);
+        assert_eq!(0, c8.memory[0]);
+        assert_eq!(4, c8.memory[1]);
+        assert_eq!(5, c8.memory[2]);
+    }
+
+    /// Display sink that counts presented frames and remembers the last one
+    struct CountingSink {
+        frames: usize,
+        last: Vec<Vec<bool>>,
+    }
+
+    impl DisplaySink for CountingSink {
+        fn present(&mut self, display: &[&[bool]]) {
+            self.frames += 1;
+            self.last = display.iter().map(|row| row.to_vec()).collect();
+        }
+    }
+
+    #[test]
+    fn test_present_display() {
+        let mut c8 = Cpu::with_seed(0);
+        let mut sink = CountingSink {
+            frames: 0,
+            last: Vec::new(),
+        };
+        // LD V0, 0x01; LD I, 0x000; DRW V0, V0, 5; CLS
+        c8.memory[512..520].copy_from_slice(&[0x60, 0x01, 0xA0, 0x00, 0xD0, 0x05, 0x00, 0xE0]);
+
+        c8.tick().unwrap();
+        assert!(!c8.present_display(&mut sink));
+        c8.tick().unwrap();
+        assert!(!c8.present_display(&mut sink));
+        assert_eq!(0, sink.frames);
+
+        c8.tick().unwrap();
+        assert!(c8.present_display(&mut sink));
+        assert_eq!(1, sink.frames);
+        assert!(sink.last[1][1]);
+
+        c8.tick().unwrap();
+        assert!(c8.present_display(&mut sink));
+        assert_eq!(2, sink.frames);
+        assert!(!sink.last[1][1]);
+    }
+
+    /// Display sink counting frames through a shared counter so it can be handed to the CPU
+    struct SharedCountingSink {
+        frames: Rc<RefCell<u32>>,
+    }
+
+    impl DisplaySink for SharedCountingSink {
+        fn present(&mut self, _display: &[&[bool]]) {
+            *self.frames.borrow_mut() += 1;
+        }
+    }
+
+    #[test]
+    fn test_frame_sink() {
+        let mut c8 = Cpu::with_seed(0);
+        let frames = Rc::new(RefCell::new(0));
+        c8.set_frame_sink(Box::new(SharedCountingSink {
+            frames: frames.clone(),
+        }));
+        // LD I, 0x000; DRW V0, V0, 5; JP 0x202 - redraws every other instruction
+        c8.memory[512..518].copy_from_slice(&[0xA0, 0x00, 0xD0, 0x05, 0x12, 0x02]);
+
+        // One frame per timer update, however many instructions ran
+        for _ in 0..5 {
+            for _ in 0..8 {
+                c8.tick().unwrap();
+            }
+            c8.update_timers();
+        }
+        assert_eq!(5, *frames.borrow());
+        for _ in 0..100 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(5, *frames.borrow());
+
+        // Frames keep coming while the display is unchanged
+        c8.memory[512..514].copy_from_slice(&[0x12, 0x00]);
+        c8.registers.pc = 512;
+        for _ in 0..3 {
+            c8.tick().unwrap();
+            c8.update_timers();
+        }
+        assert_eq!(8, *frames.borrow());
+    }
+
+    /// Audio sink that records beep events as true (on) and false (off)
+    struct RecordingSink {
+        events: Rc<RefCell<Vec<bool>>>,
+    }
+
+    impl AudioSink for RecordingSink {
+        fn beep_on(&mut self) {
+            self.events.borrow_mut().push(true);
+        }
+
+        fn beep_off(&mut self) {
+            self.events.borrow_mut().push(false);
+        }
+    }
+
+    #[test]
+    fn test_audio_sink() {
+        let mut c8 = Cpu::with_seed(0);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        c8.set_audio_sink(Box::new(RecordingSink {
+            events: events.clone(),
+        }));
+        // LD V0, 0x02; LD ST, V0; JP 0x204
+        c8.memory[512..518].copy_from_slice(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+
+        c8.tick().unwrap();
+        assert!(events.borrow().is_empty());
+        assert!(!c8.is_sound_active());
+        c8.tick().unwrap();
+        assert_eq!(vec![true], *events.borrow());
+        assert!(c8.is_sound_active());
+
+        // Only timer updates decrement the sound timer, so it reaches zero on the second one
+        for _ in 0..20 {
+            c8.tick().unwrap();
+        }
+        c8.update_timers();
+        assert_eq!(vec![true], *events.borrow());
+        c8.update_timers();
+        assert_eq!(vec![true, false], *events.borrow());
+    }
+
+    #[test]
+    fn test_audio_sink_one_frame_beep() {
+        let mut c8 = Cpu::with_seed(0);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        c8.set_audio_sink(Box::new(RecordingSink {
+            events: events.clone(),
+        }));
+        // LD V0, 0x01; LD ST, V0; JP 0x204
+        c8.memory[512..518].copy_from_slice(&[0x60, 0x01, 0xF0, 0x18, 0x12, 0x04]);
+
+        // The timer is cleared at the end of the frame it was set in, the beep still starts and stops
+        c8.step_frame(8).unwrap();
+        assert_eq!(0, c8.registers.sound_timer);
+        assert_eq!(vec![true, false], *events.borrow());
+    }
+
+    /// Trace writer sharing its buffer with the test
+    #[cfg(feature = "std")]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    #[cfg(feature = "std")]
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_debug_opcode() {
+        let mut c8 = Cpu::with_seed(0);
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        c8.set_trace_writer(Box::new(SharedBuffer(buffer.clone())));
+        c8.set_debug_opcode(Some(0x0FFF));
+        // LD V3, 0xAB; debug; LD V4, 0xCD
+        c8.memory[512..518].copy_from_slice(&[0x63, 0xAB, 0x0F, 0xFF, 0x64, 0xCD]);
+
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        let dump = String::from_utf8(buffer.borrow().clone()).unwrap();
+        assert_eq!(1, dump.lines().count());
+        assert!(dump.starts_with("PC=202 "));
+        assert!(dump.contains(" V3=AB "));
+        assert!(dump.contains(" V4=00 "));
+
+        c8.tick().unwrap();
+        assert_eq!(0xCD, c8.registers.v[4]);
+        assert_eq!(518, c8.registers.pc);
+    }
+
+    #[test]
+    fn test_set_quirk_at_runtime() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V1, 0x04; LD V2, 0x10; SHR V1, V2; SHR V1, V2
+        c8.memory[512..520].copy_from_slice(&[0x61, 0x04, 0x62, 0x10, 0x81, 0x26, 0x81, 0x26]);
+
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(0x02, c8.registers.v[1]);
+
+        c8.set_quirk(Quirk::ShiftUsesVy, true);
+        assert!(c8.quirks().shift_uses_vy);
+        c8.tick().unwrap();
+        assert_eq!(0x08, c8.registers.v[1]);
+        assert_eq!(0x10, c8.registers.v[2]);
+        assert_eq!(520, c8.registers.pc);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_strict_ran_past_program() {
+        use std::fs::File;
+
+        let path = std::env::temp_dir().join("chip8-test-ran-past.ch8");
+        // LD V0, 0x01; ADD V0, 0x01
+        File::create(&path)
+            .unwrap()
+            .write_all(&[0x60, 0x01, 0x70, 0x01])
+            .unwrap();
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom(path.to_str().unwrap()).unwrap();
+        c8.set_strict(true);
+        assert_eq!(4, c8.rom_len);
+
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(2, c8.registers.v[0]);
+        assert_eq!(Err(CpuError::RanPastProgram(516)), c8.tick());
+        assert_eq!(516, c8.registers.pc);
+
+        // Without strict mode the zeroed memory is executed as SYS instructions
+        c8.set_strict(false);
+        c8.tick().unwrap();
+        assert_eq!(518, c8.registers.pc);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_warm_reset() {
+        use std::fs::File;
+
+        let path = std::env::temp_dir().join("chip8-test-warm-reset.ch8");
+        // LD I, 0x000; DRW V0, V0, 5; LD [I], V0
+        File::create(&path)
+            .unwrap()
+            .write_all(&[0xA0, 0x00, 0xD0, 0x05, 0xF0, 0x55])
+            .unwrap();
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom(path.to_str().unwrap()).unwrap();
+        c8.registers.v[0] = 0xAB;
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(0xAB, c8.memory[0]);
+        let display = c8.display;
+
+        c8.warm_reset();
+        assert_eq!(512, c8.registers.pc);
+        assert_eq!(0, c8.registers.v[0]);
+        assert_eq!(0xF0, c8.memory[0]);
+        assert_eq!(&[0xA0, 0x00, 0xD0, 0x05, 0xF0, 0x55], &c8.memory[512..518]);
+        assert_eq!(display, c8.display);
+        assert!(c8.display.iter().any(|row| row.iter().any(|&p| p)));
+
+        c8.reset();
+        assert_eq!(512, c8.registers.pc);
+        assert!(c8.display.iter().all(|row| row.iter().all(|&p| !p)));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut c8 = Cpu::with_seed(0);
+        // CALL 0x206; JP 0x202; LD V3, K
+        c8.load_rom_bytes(&[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0xF3, 0x0A])
+            .unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert!(c8.is_waiting_for_key());
+        c8.registers.v[5] = 0x42;
+        c8.registers.i = 0x300;
+        c8.registers.delay_timer = 10;
+        c8.registers.sound_timer = 10;
+        c8.display[3][4] = true;
+        c8.set_key_pressed(7);
+
+        c8.reset();
+        assert_eq!(Cpu::with_seed(0).dump_state(), c8.dump_state());
+        assert!(!c8.is_waiting_for_key());
+        assert!(!c8.is_key_pressed(7));
+        assert!(c8.view_display().iter().all(|row| row.iter().all(|&p| !p)));
+        assert_eq!(&FONTSET[..], &c8.memory[..FONTSET.len()]);
+        assert_eq!(
+            &[0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0xF3, 0x0A],
+            &c8.memory[0x200..0x208]
+        );
+    }
+
+    #[test]
+    fn test_rom_sha1() {
+        let mut c8 = Cpu::with_seed(0);
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", c8.rom_sha1());
+
+        c8.memory[512..515].copy_from_slice(b"abc");
+        c8.rom_len = 3;
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", c8.rom_sha1());
+    }
+
+    #[test]
+    fn test_is_opcode_supported() {
+        let mut c8 = Cpu::with_seed(0);
+        for &op in &[
+            0x00E0, 0x00EE, 0x1234, 0x5120, 0x8126, 0x812E, 0xD125, 0xE19E, 0xF165,
+        ] {
+            assert!(c8.is_opcode_supported(op), "{:04X}", op);
+        }
+        // 5xy1, 8xy8 and ExFF don't decode
+        for &op in &[0x5121, 0x8128, 0xE1FF] {
+            assert!(!c8.is_opcode_supported(op), "{:04X}", op);
+        }
+
+        c8.set_debug_opcode(Some(0x8128));
+        assert!(c8.is_opcode_supported(0x8128));
+    }
+
+    #[test]
+    fn test_opcode_supported_by_profile() {
+        let schip = [
+            0x00FF, 0x00FE, 0x00C4, 0x00FB, 0x00FC, 0xF130, 0xF375, 0xF385,
+        ];
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_profile(Profile::Vip);
+        assert_eq!(Profile::Vip, c8.profile());
+        assert!(c8.quirks().shift_uses_vy);
+        for &op in schip.iter().chain(&[0xF201]) {
+            assert!(!c8.is_opcode_supported(op), "{:04X}", op);
+        }
+        assert!(c8.is_opcode_supported(0x00E0));
+
+        c8.set_profile(Profile::Schip);
+        for &op in &schip {
+            assert!(c8.is_opcode_supported(op), "{:04X}", op);
+        }
+        assert!(!c8.is_opcode_supported(0xF201));
+
+        c8.set_profile(Profile::XoChip);
+        for &op in schip.iter().chain(&[0xF201]) {
+            assert!(c8.is_opcode_supported(op), "{:04X}", op);
+        }
+
+        // Dispatch agrees: HIGH is an unknown opcode on the COSMAC VIP
+        c8.load_rom_bytes(&[0x00, 0xFF]).unwrap();
+        c8.reset();
+        c8.set_profile(Profile::Vip);
+        assert_eq!(Err(CpuError::UnknownOpcode(0x00FF)), c8.tick());
+        c8.reset();
+        c8.set_profile(Profile::Schip);
+        c8.tick().unwrap();
+        assert_eq!((HIRES_WIDTH, HIRES_HEIGHT), c8.resolution());
+    }
+
+    #[test]
+    fn test_schip_font_and_flags() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_profile(Profile::Schip);
+        // LD V0, 0x07; LD HF, V0; LD V1, 0x2A; LD R, V1; LD V0, 0; LD V1, 0; LD V1, R
+        c8.load_rom_bytes(&[
+            0x60, 0x07, 0xF0, 0x30, 0x61, 0x2A, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        ])
+        .unwrap();
+        c8.reset();
+        c8.run_cycles(2).unwrap();
+        let i = c8.registers.i as usize;
+        assert_eq!(&BIG_FONTSET[70..80], &c8.memory[i..i + 10]);
+
+        c8.run_cycles(5).unwrap();
+        assert_eq!([0x07, 0x2A], c8.registers.v[..2]);
+        // The flags survive a reset
+        c8.reset();
+        assert_eq!([0x07, 0x2A], c8.rpl_flags[..2]);
+    }
+
+    #[test]
+    fn test_safe_mode_never_panics() {
+        for opcode in 0..=0xFFFFu16 {
+            // Hostile state: every register at its maximum, full stack and PC at the last byte of memory
+            let mut c8 = Cpu::with_seed(0);
+            c8.set_safe_mode(true);
+            c8.registers.v = [0xFF; 16];
+            c8.registers.i = 0xFFFF;
+            c8.registers.sp = 16;
+            c8.registers.pc = 0xFFF;
+            c8.memory[0xFFF] = (opcode >> 8) as u8;
+            c8.memory[0] = opcode as u8;
+            c8.tick().unwrap();
+            assert!(c8.registers.pc <= 0xFFF);
+            c8.set_key_pressed(0xFF);
+            c8.set_key_released(0xFF);
+
+            // Fresh state: empty stack and PC at the start of the program
+            let mut c8 = Cpu::with_seed(0);
+            c8.set_safe_mode(true);
+            c8.memory[512] = (opcode >> 8) as u8;
+            c8.memory[513] = opcode as u8;
+            c8.tick().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_jp() {
+        // JP 0x300; at 0x300: JP V0, 0x400
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x13, 0x00]).unwrap();
+        c8.memory[0x300..0x302].copy_from_slice(&[0xB4, 0x00]);
+        c8.tick().unwrap();
+        assert_eq!(0x300, c8.pc());
+        assert_eq!(0, c8.sp());
+
+        // Bnnn adds V0 to the target
+        c8.registers.v[0] = 0x2A;
+        c8.tick().unwrap();
+        assert_eq!(0x42A, c8.pc());
+        assert!(!c8.is_halted());
+    }
+
+    #[test]
+    fn test_jump_uses_vx() {
+        // LD V0, 0x10; LD V2, 0x04; JP V0, 0x234
+        let rom = [0x60, 0x10, 0x62, 0x04, 0xB2, 0x34];
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&rom).unwrap();
+        assert_eq!(Ok(3), c8.run_cycles(3));
+        assert_eq!(0x244, c8.pc());
+
+        // Read as JP V2, 0x234 with the quirk
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_quirk(Quirk::JumpUsesVx, true);
+        assert!(c8.quirks().jump_uses_vx);
+        c8.load_rom_bytes(&rom).unwrap();
+        assert_eq!(Ok(3), c8.run_cycles(3));
+        assert_eq!(0x238, c8.pc());
+
+        assert!(Profile::Schip.quirks().jump_uses_vx);
+        assert!(!Profile::Vip.quirks().jump_uses_vx);
+        assert!(!Profile::XoChip.quirks().jump_uses_vx);
+    }
+
+    #[test]
+    fn test_jp0_past_memory_end() {
+        // LD V0, 0x10; JP V0, 0xFFF
+        let rom = [0x60, 0x10, 0xBF, 0xFF];
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_memory_model(MemoryModel::Wrap);
+        c8.load_rom_bytes(&rom).unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(0x00F, c8.pc());
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_safe_mode(true);
+        c8.load_rom_bytes(&rom).unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(0x00F, c8.pc());
+
+        // The target wraps under the error memory model as well
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_memory_model(MemoryModel::Error);
+        c8.load_rom_bytes(&rom).unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(0x00F, c8.pc());
+    }
+
+    #[test]
+    fn test_call_ret() {
+        // CALL 0x208; LD V1, 0x01; JP 0x204; 0x0000; at 0x208: LD V0, 0x07; RET
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[
+            0x22, 0x08, 0x61, 0x01, 0x12, 0x04, 0x00, 0x00, 0x60, 0x07, 0x00, 0xEE,
+        ])
+        .unwrap();
+
+        // The call pushes the address of the instruction after it
+        c8.tick().unwrap();
+        assert_eq!(0x208, c8.pc());
+        assert_eq!(1, c8.sp());
+        assert_eq!(0x202, c8.registers.stack[0]);
+
+        c8.tick().unwrap();
+        assert_eq!(7, c8.v(0));
+
+        // The return pops it and continues right after the call
+        c8.tick().unwrap();
+        assert_eq!(0x202, c8.pc());
+        assert_eq!(0, c8.sp());
+        c8.tick().unwrap();
+        assert_eq!(1, c8.v(1));
+        assert_eq!(0x204, c8.pc());
+    }
+
+    #[test]
+    fn test_skips() {
+        // Returns the PC after executing `opcode` at 0x200 with V0 = 5 and V1 = `v1`
+        let pc_after = |opcode: [u8; 2], v1: u8| -> u16 {
+            let mut c8 = Cpu::with_seed(0);
+            c8.load_rom_bytes(&opcode).unwrap();
+            c8.registers.v[0] = 5;
+            c8.registers.v[1] = v1;
+            c8.tick().unwrap();
+            assert_eq!(5, c8.v(0));
+            assert_eq!(v1, c8.v(1));
+            c8.pc()
+        };
+
+        // SE V0, 0x05 skips the next instruction only if V0 = 5
+        assert_eq!(0x204, pc_after([0x30, 0x05], 0));
+        assert_eq!(0x202, pc_after([0x30, 0x06], 0));
+        // SNE V0, 0x05 skips only if V0 != 5
+        assert_eq!(0x202, pc_after([0x40, 0x05], 0));
+        assert_eq!(0x204, pc_after([0x40, 0x06], 0));
+        // SE V0, V1 skips only if V0 = V1
+        assert_eq!(0x204, pc_after([0x50, 0x10], 5));
+        assert_eq!(0x202, pc_after([0x50, 0x10], 6));
+        // SNE V0, V1 skips only if V0 != V1
+        assert_eq!(0x202, pc_after([0x90, 0x10], 5));
+        assert_eq!(0x204, pc_after([0x90, 0x10], 6));
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut c8 = Cpu::with_seed(0);
+        // A chain of 16 calls, CALL 0x202 at 0x200, CALL 0x204 at 0x202, ... up to a RET at 0x220
+        for level in 0..16u16 {
+            let target = 0x202 + 2 * level;
+            c8.memory[0x200 + 2 * level as usize] = 0x20 | (target >> 8) as u8;
+            c8.memory[0x201 + 2 * level as usize] = target as u8;
+        }
+        c8.memory[0x220..0x222].copy_from_slice(&[0x00, 0xEE]);
+
+        for level in 1..=16u16 {
+            c8.tick().unwrap();
+            assert_eq!(level as u8, c8.sp());
+            assert_eq!(0x200 + 2 * level, c8.pc());
+        }
+        // The first call stored its return address at the bottom of the stack
+        assert_eq!(0x202, c8.registers.stack[0]);
+        assert_eq!(0x220, c8.registers.stack[15]);
+
+        // Replace the calls with returns, so each return lands after the call that made it and returns again, down
+        // to the outermost one
+        for level in 0..15u16 {
+            c8.memory[0x202 + 2 * level as usize..0x204 + 2 * level as usize]
+                .copy_from_slice(&[0x00, 0xEE]);
+        }
+        for level in (0..16u16).rev() {
+            c8.tick().unwrap();
+            assert_eq!(level as u8, c8.sp());
+            assert_eq!(0x202 + 2 * level, c8.pc());
+        }
+    }
+
+    #[test]
+    fn test_skp_sknp() {
+        // SKP V0; LD V1, 0x01; SKNP V0; LD V2, 0x01
+        let rom = [0xE0, 0x9E, 0x61, 0x01, 0xE0, 0xA1, 0x62, 0x01];
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&rom).unwrap();
+        c8.registers.v[0] = 0xA;
+
+        // Without key A held SKP does not skip and SKNP does
+        c8.set_key_pressed(0xB);
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(0x208, c8.pc());
+        assert_eq!([1, 0], c8.registers.v[1..3]);
+
+        // With key A held SKP skips and SKNP does not
+        c8.reset();
+        c8.registers.v[0] = 0xA;
+        c8.set_key_pressed(0xA);
+        assert!(c8.is_key_pressed(0xA));
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(0x208, c8.pc());
+        assert_eq!([0, 1], c8.registers.v[1..3]);
+    }
+
+    #[test]
+    fn test_pressed_keys() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_key_pressed(0x1);
+        c8.set_key_pressed(0xF);
+        let mut expected = [false; 16];
+        expected[0x1] = true;
+        expected[0xF] = true;
+        assert_eq!(expected, c8.pressed_keys());
+        assert!(c8.is_key_pressed(0xF));
+        assert!(!c8.is_key_pressed(0x2));
+        // Out of range indices are not keys
+        assert!(!c8.is_key_pressed(16));
+
+        c8.set_key_released(0x1);
+        assert!(!c8.pressed_keys()[0x1]);
+
+        // Replacing the keys leaves a pending key wait alone
+        c8.load_rom_bytes(&[0xF3, 0x0A]).unwrap();
+        c8.tick().unwrap();
+        assert!(c8.is_waiting_for_key());
+        c8.set_pressed_keys(expected);
+        assert_eq!(expected, c8.pressed_keys());
+        assert_eq!(Some(3), c8.waiting_register());
+    }
+
+    #[test]
+    fn test_is_halted() {
+        // LD V0, 0x05; LD ST, V0; JP 0x206; JP 0x206
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x60, 0x05, 0xF0, 0x18, 0x12, 0x06, 0x12, 0x06])
+            .unwrap();
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        // A jump elsewhere is not a halt
+        assert!(!c8.is_halted());
+        assert!(c8.at_self_jump());
+        c8.tick().unwrap();
+        assert!(c8.is_halted());
+        assert_eq!(0x206, c8.pc());
+
+        // The timers keep running while halted
+        c8.update_timers();
+        assert_eq!(4, c8.timers().1);
+        assert!(c8.is_halted());
+
+        c8.reset();
+        assert!(!c8.is_halted());
+    }
+
+    #[test]
+    fn test_instructions_executed() {
+        // LD V0, 0x00; ADD V0, 0x01; SE V0, 0x0A; JP 0x202; LD V1, K
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x60, 0x00, 0x70, 0x01, 0x30, 0x0A, 0x12, 0x02, 0xF1, 0x0A])
+            .unwrap();
+        assert_eq!(0, c8.instructions_executed());
+        c8.step().unwrap();
+        c8.step_frame(5).unwrap();
+        assert_eq!(6, c8.instructions_executed());
+
+        // 1 + 9 * 3 + 2 + 1 instructions reach the key wait, further ticks execute nothing
+        for _ in 0..50 {
+            c8.tick().unwrap();
+        }
+        assert!(c8.is_waiting_for_key());
+        assert_eq!(31, c8.instructions_executed());
+
+        c8.reset();
+        assert_eq!(0, c8.instructions_executed());
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        // LD V0, 0x01; undefined 8xy8; LD V1, 0x02
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x60, 0x01, 0x80, 0x08, 0x61, 0x02])
+            .unwrap();
+        c8.tick().unwrap();
+        let before = c8.dump_state();
+        assert_eq!(Err(CpuError::UnknownOpcode(0x8008)), c8.tick());
+        assert_eq!(before.v, c8.dump_state().v);
+        assert_eq!(0x204, c8.pc());
+
+        c8.reset();
+        c8.step().unwrap();
+        assert_eq!(Err(CpuError::UnknownOpcode(0x8008)), c8.step());
+
+        // Safe mode skips it
+        c8.reset();
+        c8.set_safe_mode(true);
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        assert_eq!([1, 2], c8.registers.v[..2]);
+    }
+
+    #[test]
+    fn test_stack_errors() {
+        // CALL 0x200, recursing until the stack is full
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x22, 0x00]).unwrap();
+        for _ in 0..16 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(16, c8.sp());
+        assert_eq!(Err(CpuError::StackOverflow(0x200)), c8.tick());
+        assert_eq!(16, c8.sp());
+        assert_eq!(0x202, c8.registers.stack[15]);
+        c8.reset();
+        for _ in 0..16 {
+            c8.step().unwrap();
+        }
+        assert_eq!(Err(CpuError::StackOverflow(0x200)), c8.step());
+
+        // RET with an empty stack
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        assert_eq!(Err(CpuError::StackUnderflow(0x200)), c8.tick());
+        assert_eq!(0, c8.sp());
+        c8.reset();
+        assert_eq!(Err(CpuError::StackUnderflow(0x200)), c8.step());
+    }
+
+    #[test]
+    fn test_quiet_mode() {
+        // RET; unknown opcode; LD I, 0xFFF; LD V0..VF, [I]; CALL 0x208 recursing until the stack overflows
+        let rom = [0x00, 0xEE, 0xFF, 0xFF, 0xAF, 0xFF, 0xFF, 0x65, 0x22, 0x08];
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_quiet(Some(QuietPolicy::Continue));
+        c8.load_rom_bytes(&rom).unwrap();
+        c8.tick().unwrap();
+        assert_eq!(
+            vec![CpuError::StackUnderflow(0x200)],
+            c8.take_logged_errors()
+        );
+        c8.tick().unwrap();
+        assert_eq!(
+            vec![CpuError::UnknownOpcode(0xFFFF)],
+            c8.take_logged_errors()
+        );
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        let expected: Vec<CpuError> = (0x1000..0x100F).map(CpuError::MemoryOutOfBounds).collect();
+        assert_eq!(expected, c8.take_logged_errors());
+        for _ in 0..1000 {
+            c8.tick().unwrap();
+        }
+        // The overflowing CALL is skipped and execution runs on through whatever memory holds
+        let errors = c8.take_logged_errors();
+        assert!(errors.len() <= QUIET_LOG_LEN);
+        assert!(errors.contains(&CpuError::StackOverflow(0x208)));
+
+        // Halting returns each error from tick instead
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_quiet(Some(QuietPolicy::Halt));
+        c8.load_rom_bytes(&rom).unwrap();
+        assert_eq!(Err(CpuError::StackUnderflow(0x200)), c8.tick());
+        assert_eq!(Err(CpuError::UnknownOpcode(0xFFFF)), c8.tick());
+        c8.tick().unwrap();
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
+        assert!(c8.take_logged_errors().is_empty());
+    }
+
+    #[test]
+    fn test_update_timers() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x03; LD DT, V0; JP 0x204
+        c8.memory[512..518].copy_from_slice(&[0x60, 0x03, 0xF0, 0x15, 0x12, 0x04]);
+        for _ in 0..100 {
+            c8.tick().unwrap();
+        }
+        // Ticks never decrement the timers
+        assert_eq!(3, c8.registers.delay_timer);
+        for expected in [2, 1, 0, 0].iter() {
+            c8.update_timers();
+            assert_eq!(*expected, c8.registers.delay_timer);
+        }
+        c8.registers.sound_timer = 2;
+        c8.update_timers();
+        assert_eq!(1, c8.registers.sound_timer);
+    }
+
+    #[test]
+    fn test_breakpoint_hit() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x01; ADD V0, 0x01; SE V0, 0x05; JP 0x202; LD V1, 0x07; JP 0x20A
+        c8.load_rom_bytes(&[
+            0x60, 0x01, 0x70, 0x01, 0x30, 0x05, 0x12, 0x02, 0x61, 0x07, 0x12, 0x0A,
+        ])
+        .unwrap();
+        c8.add_breakpoint(0x208);
+        let ticks = (1..100)
+            .find(|_| {
+                c8.tick().unwrap();
+                c8.breakpoint_hit().is_some()
+            })
+            .unwrap();
+        // 12 instructions reach the breakpoint, the 13th tick stops before fetching
+        assert_eq!(13, ticks);
+        assert_eq!(Some(0x208), c8.breakpoint_hit());
+        assert_eq!(0x208, c8.pc());
+        assert_eq!(5, c8.v(0));
+        // The instruction at the breakpoint has not run yet, ticking again resumes with it
+        assert_eq!(0, c8.v(1));
+        c8.tick().unwrap();
+        assert_eq!(None, c8.breakpoint_hit());
+        assert_eq!(7, c8.v(1));
+
+        // A breakpoint on the starting program counter stops before the first instruction
+        c8.reset();
+        c8.add_breakpoint(0x200);
+        assert_eq!(None, c8.step().unwrap());
+        assert_eq!(Some(0x200), c8.breakpoint_hit());
+        assert_eq!(0, c8.v(0));
+        let step = c8.step().unwrap().unwrap();
+        assert_eq!(0x200, step.pc);
+        assert!(!step.breakpoint);
+        assert_eq!(None, c8.breakpoint_hit());
+
+        // The step before a breakpoint flags it, the next one stops there
+        c8.add_breakpoint(0x204);
+        assert!(c8.step().unwrap().unwrap().breakpoint);
+        assert_eq!(None, c8.step().unwrap());
+        assert_eq!(Some(0x204), c8.breakpoint_hit());
+    }
+
+    #[test]
+    fn test_breakpoint_list() {
+        let mut c8 = Cpu::with_seed(0);
+        assert!(c8.breakpoints().is_empty());
+
+        c8.add_breakpoint(0x204);
+        c8.add_breakpoint(0x200);
+        c8.add_breakpoint(0x204);
+        assert_eq!(
+            vec![Breakpoint { addr: 0x200 }, Breakpoint { addr: 0x204 }],
+            c8.breakpoints()
+        );
+
+        assert!(c8.remove_breakpoint(0x204));
+        assert!(!c8.remove_breakpoint(0x204));
+        assert_eq!(vec![Breakpoint { addr: 0x200 }], c8.breakpoints());
+        assert!(c8.remove_breakpoint(0x200));
+        assert!(c8.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn test_watchpoint_list() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.add_watchpoint(0x300);
+        c8.add_watchpoint(0x301);
+        c8.add_breakpoint(0x300);
+        assert_eq!(
+            &[Watchpoint { addr: 0x300 }, Watchpoint { addr: 0x301 }],
+            c8.watchpoints()
+        );
+
+        assert!(c8.remove_watchpoint(0x300));
+        assert_eq!(&[Watchpoint { addr: 0x301 }], c8.watchpoints());
+        assert_eq!(vec![Breakpoint { addr: 0x300 }], c8.breakpoints());
+    }
+
+    #[test]
+    fn test_watchpoint_hit() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD I, 0x300; LD V0, 0x7B; LD B, V0; LD V1, 0x01; LD [I], V1; JP 0x20A
+        c8.load_rom_bytes(&[
+            0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0x61, 0x01, 0xF1, 0x55, 0x12, 0x0A,
+        ])
+        .unwrap();
+        c8.add_watchpoint(0x302);
+        let ticks = (1..100)
+            .find(|_| {
+                c8.tick().unwrap();
+                c8.watchpoint_hit().is_some()
+            })
+            .unwrap();
+        // Execution stops right after the BCD store wrote the ones digit
+        assert_eq!(3, ticks);
+        assert_eq!(Some(0x302), c8.watchpoint_hit());
+        assert_eq!(0x206, c8.pc());
+        assert_eq!(3, c8.memory[0x302]);
+        c8.tick().unwrap();
+        assert_eq!(None, c8.watchpoint_hit());
+
+        // Stores of several bytes stop after the whole instruction, and calling again continues after it
+        c8.reset();
+        c8.remove_watchpoint(0x302);
+        c8.add_watchpoint(0x300);
+        assert_eq!(3, c8.run_cycles(100).unwrap());
+        assert_eq!(Some(0x300), c8.watchpoint_hit());
+        assert_eq!([1, 2, 3], c8.memory[0x300..0x303]);
+        assert_eq!(2, c8.run_cycles(100).unwrap());
+        assert_eq!(Some(0x300), c8.watchpoint_hit());
+        assert_eq!(0x20A, c8.pc());
+        assert_eq!([0x7B, 0x01], c8.memory[0x300..0x302]);
+
+        c8.reset();
+        assert_eq!(3, c8.step_frame(10).unwrap());
+        assert!(!c8.step().unwrap().unwrap().watchpoint);
+        assert!(c8.step().unwrap().unwrap().watchpoint);
+    }
+
+    /// Draw a 2x2 block at the bottom right corner with the passed clip mode and return the lit pixels as (x, y)
+    fn draw_corner_block(clip_mode: ClipMode) -> Vec<(usize, usize)> {
+        let mut c8 = Cpu::with_seed(0);
+        c8.quirks.clip_mode = clip_mode;
+        c8.memory[0x300] = 0xC0;
+        c8.memory[0x301] = 0xC0;
+        c8.registers.i = 0x300;
+        // Start coordinates wrap in every mode, so (127, 95) is the bottom right pixel
+        c8.registers.v[0] = (C8_WIDTH * 2 - 1) as u8;
+        c8.registers.v[1] = (C8_HEIGHT * 3 - 1) as u8;
+        c8.drw(0, 1, 2);
+        assert_eq!(0, c8.registers.v[0xF]);
+
+        let mut lit = Vec::new();
+        for (y, row) in c8.display.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn test_drw_clip_modes() {
+        let (r, b) = (C8_WIDTH - 1, C8_HEIGHT - 1);
+        assert_eq!(
+            vec![(0, 0), (r, 0), (0, b), (r, b)],
+            draw_corner_block(ClipMode::Wrap)
+        );
+        assert_eq!(vec![(r, b)], draw_corner_block(ClipMode::Clip));
+        assert_eq!(vec![(0, b), (r, b)], draw_corner_block(ClipMode::Amiga));
+    }
+
+    #[test]
+    fn test_hires_mode() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x7F; LD V1, 0x00; LD F, V1; DRW V0, V0, 1; HIGH; DRW V0, V0, 1; LOW
+        c8.load_rom_bytes(&[
+            0x60, 0x7F, 0x61, 0x00, 0xF1, 0x29, 0xD0, 0x01, 0x00, 0xFF, 0xD0, 0x01, 0x00, 0xFE,
+        ])
+        .unwrap();
+        assert_eq!((C8_WIDTH, C8_HEIGHT), c8.resolution());
+        for _ in 0..4 {
+            c8.tick().unwrap();
+        }
+        // (127, 127) wraps to (63, 31) in the low resolution mode
+        assert!(c8.display[31][63]);
+
+        // Switching clears the display
+        c8.tick().unwrap();
+        assert_eq!((HIRES_WIDTH, HIRES_HEIGHT), c8.resolution());
+        assert!(!c8.display[31][63]);
+        let display = c8.view_display();
+        assert_eq!(HIRES_HEIGHT, display.len());
+        assert!(display
+            .iter()
+            .all(|row| row.len() == HIRES_WIDTH && !row.contains(&true)));
+
+        // (127, 127) wraps to (127, 63) in the high resolution mode
+        c8.tick().unwrap();
+        assert!(c8.display[63][127]);
+
+        c8.tick().unwrap();
+        assert_eq!((C8_WIDTH, C8_HEIGHT), c8.resolution());
+        assert_eq!(C8_HEIGHT, c8.view_display().len());
+        assert!(!c8.display[63][127]);
+
+        // Reset returns to the low resolution mode
+        c8.set_hires(true);
+        c8.reset();
+        assert!(!c8.hires);
+    }
+
+    /// Returns the lit pixels of the active resolution as (x, y), in row major order
+    fn lit_pixels(c8: &Cpu) -> Vec<(usize, usize)> {
+        let mut lit = Vec::new();
+        for (y, row) in c8.view_display().iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                if pixel {
+                    lit.push((x, y));
+                }
+            }
+        }
+        lit
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.display[0][0] = true;
+        c8.display[2][C8_WIDTH - 1] = true;
+        c8.display[C8_HEIGHT - 1][5] = true;
+
+        // Pixels scrolled past the bottom are lost, the top rows are scrolled in off
+        c8.process_opcode(0x00C2);
+        assert!(c8.has_disp_update);
+        assert_eq!(vec![(0, 2), (C8_WIDTH - 1, 4)], lit_pixels(&c8));
+
+        c8.process_opcode(0x00FB);
+        assert_eq!(vec![(4, 2)], lit_pixels(&c8));
+
+        c8.process_opcode(0x00FC);
+        c8.process_opcode(0x00FC);
+        assert_eq!(Vec::<(usize, usize)>::new(), lit_pixels(&c8));
+
+        // The high resolution mode scrolls the whole 128x64 display
+        c8.set_hires(true);
+        c8.display[HIRES_HEIGHT - 2][HIRES_WIDTH - 6] = true;
+        c8.process_opcode(0x00C1);
+        c8.process_opcode(0x00FB);
+        assert_eq!(vec![(HIRES_WIDTH - 2, HIRES_HEIGHT - 1)], lit_pixels(&c8));
+        c8.process_opcode(0x00CF);
+        assert!(lit_pixels(&c8).is_empty());
+    }
+
+    #[test]
+    fn test_paused() {
+        let mut c8 = Cpu::with_seed(0);
+        let beeps = Rc::new(RefCell::new(Vec::new()));
+        c8.set_audio_sink(Box::new(RecordingSink {
+            events: beeps.clone(),
+        }));
+        // LD V0, 0x05; LD ST, V0; ADD V0, 0x01; JP 0x204
+        c8.load_rom_bytes(&[0x60, 0x05, 0xF0, 0x18, 0x70, 0x01, 0x12, 0x04])
+            .unwrap();
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert!(c8.is_sound_active());
+
+        c8.set_paused(true);
+        assert!(c8.is_paused());
+        assert!(!c8.is_sound_active());
+        for _ in 0..10 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(0x204, c8.pc());
+        assert_eq!(5, c8.registers.v[0]);
+
+        // Stepping still works while paused
+        c8.step().unwrap();
+        assert_eq!(6, c8.registers.v[0]);
+
+        c8.set_paused(false);
+        assert!(c8.is_sound_active());
+        c8.tick().unwrap();
+        assert_eq!(0x204, c8.pc());
+        assert_eq!(vec![true, false, true], *beeps.borrow());
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut c8 = Cpu::with_seed(0);
+        // Two 1x1 sprites, the first for plane 1 and the second for plane 2
+        c8.memory[0x300] = 0x80;
+        c8.memory[0x301] = 0xC0;
+        c8.registers.i = 0x300;
+
+        // The first plane is selected by default
+        c8.drw(0, 0, 1);
+        assert_eq!(1, c8.view_planes()[0][0]);
+        assert!(!c8.second_plane[0][0]);
+
+        // Only the second plane, with the first sprite. There is no collision in the first plane
+        c8.process_opcode(0xF201);
+        c8.drw(0, 0, 1);
+        assert_eq!(0, c8.registers.v[0xF]);
+        assert_eq!(vec![3, 0], c8.view_planes()[0][..2].to_vec());
+
+        // Both planes, the second plane drawing the sprite following the first
+        c8.process_opcode(0xF301);
+        c8.drw(0, 0, 1);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert_eq!(vec![0, 2], c8.view_planes()[0][..2].to_vec());
+        assert_eq!(&[false, false], &c8.view_display()[0][..2]);
+
+        // No plane selected, nothing is drawn or cleared
+        c8.process_opcode(0xF001);
+        c8.drw(0, 0, 1);
+        c8.process_opcode(0x00E0);
+        assert_eq!(0, c8.registers.v[0xF]);
+        assert_eq!(vec![0, 2], c8.view_planes()[0][..2].to_vec());
+
+        // CLS only clears the selected plane
+        c8.process_opcode(0xF101);
+        c8.drw(0, 0, 1);
+        c8.process_opcode(0x00E0);
+        assert_eq!(vec![0, 2], c8.view_planes()[0][..2].to_vec());
+        c8.process_opcode(0xF201);
+        c8.process_opcode(0x00E0);
+        assert!(c8
+            .view_planes()
+            .iter()
+            .all(|row| row.iter().all(|&p| p == 0)));
+
+        c8.reset();
+        assert_eq!(1, c8.plane_mask);
+    }
+
+    #[test]
+    fn test_drw_16x16() {
+        let mut c8 = Cpu::with_seed(0);
+        // Diagonal line through a 16x16 sprite, two bytes per row
+        for row in 0..16 {
+            let bits = 0x8000u16 >> row;
+            c8.memory[0x300 + 2 * row] = (bits >> 8) as u8;
+            c8.memory[0x301 + 2 * row] = bits as u8;
+        }
+        c8.registers.i = 0x300;
+        c8.registers.v[0] = 4;
+
+        // Dxy0 draws nothing in the low resolution mode
+        c8.drw(0, 0, 0);
+        assert!(!c8.display.iter().any(|row| row.contains(&true)));
+
+        c8.set_hires(true);
+        c8.drw(0, 0, 0);
+        for y in 0..HIRES_HEIGHT {
+            for x in 0..HIRES_WIDTH {
+                let on_line = (4..20).contains(&y) && x == y;
+                assert_eq!(on_line, c8.display[y][x], "({}, {})", x, y);
+            }
+        }
+        assert_eq!(0, c8.registers.v[0xF]);
+
+        c8.drw(0, 0, 0);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert!(!c8.display.iter().any(|row| row.contains(&true)));
     }
 
-    /// 8xy2 - AND Vx, Vy - Set Vx := Vx AND Vy
-    fn and(&mut self, x: usize, y: usize) {
-        self.registers.v[x] &= self.registers.v[y];
+    #[test]
+    fn test_drw_clip_collision() {
+        // Collisions are only reported for pixels that are actually drawn
+        let mut c8 = Cpu::with_seed(0);
+        c8.quirks.clip_mode = ClipMode::Clip;
+        c8.display[0][0] = true;
+        c8.memory[0x300] = 0xC0;
+        c8.registers.i = 0x300;
+        c8.registers.v[0] = (C8_WIDTH - 1) as u8;
+        c8.drw(0, 1, 1);
+        assert_eq!(0, c8.registers.v[0xF]);
+
+        c8.quirks.clip_mode = ClipMode::Amiga;
+        c8.drw(0, 1, 1);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert!(!c8.display[0][0]);
     }
 
-    /// 8xy3 - XOR Vx, Vy - Set Vx := Vx XOR Vy
-    fn xor(&mut self, x: usize, y: usize) {
-        self.registers.v[x] ^= self.registers.v[y];
+    #[test]
+    fn test_drw_sprite() {
+        // The font sprite for 0 at (10, 5): F0 90 90 90 F0
+        let mut c8 = Cpu::with_seed(0);
+        c8.registers.i = 0;
+        c8.registers.v[0] = 10;
+        c8.registers.v[1] = 5;
+        c8.registers.v[0xF] = 1;
+        c8.drw(0, 1, 5);
+        assert_eq!(0, c8.registers.v[0xF]);
+        assert!(c8.has_disp_update());
+        let mut expected = vec![(10, 5), (11, 5), (12, 5), (13, 5)];
+        for y in 6..9 {
+            expected.extend_from_slice(&[(10, y), (13, y)]);
+        }
+        expected.extend_from_slice(&[(10, 9), (11, 9), (12, 9), (13, 9)]);
+        assert_eq!(expected, lit_pixels(&c8));
+
+        // The most significant bit is the leftmost pixel, 0 rows draw nothing
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[0x300] = 0x81;
+        c8.memory[0x301] = 0x00;
+        c8.memory[0x302] = 0x18;
+        c8.registers.i = 0x300;
+        c8.drw(0, 0, 3);
+        assert_eq!(vec![(0, 0), (7, 0), (3, 2), (4, 2)], lit_pixels(&c8));
+
+        // N = 0 draws nothing in the low resolution mode
+        let mut c8 = Cpu::with_seed(0);
+        c8.drw(0, 0, 0);
+        assert_eq!(Vec::<(usize, usize)>::new(), lit_pixels(&c8));
     }
 
-    /// 8xy4 - ADD Vx, Vy - Set Vx := Vx + Vy, set VF := carry
-    fn add(&mut self, x: usize, y: usize) {
-        let (val, carry) = (self.registers.v[x]).overflowing_add(self.registers.v[y]);
-        self.registers.v[x] = val;
-        self.registers.v[0xf] = carry as u8;
+    #[test]
+    fn test_drw_collision() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[0x300] = 0xF0;
+        c8.memory[0x301] = 0x80;
+        c8.registers.i = 0x300;
+
+        // Drawing the same sprite twice erases it and reports the collision
+        c8.drw(0, 1, 1);
+        assert_eq!(0, c8.registers.v[0xF]);
+        c8.drw(0, 1, 1);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert_eq!(Vec::<(usize, usize)>::new(), lit_pixels(&c8));
+
+        // A sprite next to another touches no lit pixel, and clears VF from the last draw
+        c8.drw(0, 1, 1);
+        c8.registers.v[0] = 4;
+        c8.registers.v[0xF] = 1;
+        c8.drw(0, 1, 1);
+        assert_eq!(0, c8.registers.v[0xF]);
+        assert_eq!(8, lit_pixels(&c8).len());
+
+        // A single overlapping pixel is enough, and only that pixel is switched off
+        c8.registers.i = 0x301;
+        c8.registers.v[0] = 3;
+        c8.drw(0, 1, 1);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert_eq!(
+            vec![(0, 0), (1, 0), (2, 0), (4, 0), (5, 0), (6, 0), (7, 0)],
+            lit_pixels(&c8)
+        );
+
+        // Pixels switched on next to lit ones are no collision
+        c8.registers.v[1] = 1;
+        c8.drw(0, 1, 1);
+        assert_eq!(0, c8.registers.v[0xF]);
+        assert!(c8.display[1][3]);
     }
 
-    /// 8xy5 - SUB Vx, Vy - Set Vx := Vx - Vy, set VF := NOT borrow
-    fn sub(&mut self, x: usize, y: usize) {
-        let (val, borrow) = (self.registers.v[x]).overflowing_sub(self.registers.v[y]);
-        self.registers.v[x] = val;
-        self.registers.v[0xf] = !borrow as u8;
+    #[test]
+    fn test_drw_wrap() {
+        let mut c8 = Cpu::with_seed(0);
+        for addr in 0x300..0x303 {
+            c8.memory[addr] = 0xFF;
+        }
+        c8.registers.i = 0x300;
+        c8.registers.v[0] = 60;
+        c8.registers.v[1] = 30;
+
+        // A sprite crossing the bottom right corner continues on the opposite edges
+        // Returns the pixels at the passed columns of each of the rows, in row major order
+        let rows = |xs: Vec<usize>, ys: &[usize]| -> Vec<(usize, usize)> {
+            ys.iter()
+                .flat_map(|&y| xs.iter().map(move |&x| (x, y)))
+                .collect()
+        };
+        c8.drw(0, 1, 3);
+        assert_eq!(0, c8.registers.v[0xF]);
+        let wrapped: Vec<usize> = (0..4).chain(60..64).collect();
+        assert_eq!(rows(wrapped, &[0, 30, 31]), lit_pixels(&c8));
+
+        // Wrapped pixels collide like any other
+        c8.registers.v[0] = 0;
+        c8.registers.v[1] = 0;
+        c8.drw(0, 1, 1);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert_eq!(rows((4..8).collect(), &[0]), lit_pixels(&c8)[..4]);
+
+        // Clipping drops the parts past the edges instead
+        let mut c8 = Cpu::with_seed(0);
+        c8.quirks.clip_mode = ClipMode::Clip;
+        for addr in 0x300..0x303 {
+            c8.memory[addr] = 0xFF;
+        }
+        c8.registers.i = 0x300;
+        c8.registers.v[0] = 60;
+        c8.registers.v[1] = 30;
+        c8.drw(0, 1, 3);
+        assert_eq!(rows((60..64).collect(), &[30, 31]), lit_pixels(&c8));
     }
 
-    /// 8xy6 - SHR Vx - Set Vx := Vx >> 1
-    fn shr(&mut self, x: usize) {
-        self.registers.v[0xf] = self.registers.v[x] & 0x1;
-        self.registers.v[x] >>= 1;
+    #[test]
+    fn test_step() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x05; LD V1, 0x07; ADD V0, V1; LD F, V0; DRW V0, V0, 5; DRW V0, V0, 5
+        c8.load_rom_bytes(&[
+            0x60, 0x05, 0x61, 0x07, 0x80, 0x14, 0xF0, 0x29, 0xD0, 0x05, 0xD0, 0x05,
+        ])
+        .unwrap();
+        c8.registers.delay_timer = 3;
+        c8.step().unwrap();
+        c8.step().unwrap();
+        let step = c8.step().unwrap().unwrap();
+        assert_eq!(0x204, step.pc);
+        assert_eq!(0x8014, step.opcode);
+        assert_eq!("ADD V0, V1", step.mnemonic);
+        assert!(!step.display_updated);
+        // VF is written too, but the carry flag stays 0
+        assert_eq!(vec![(0, 0x0C)], step.changed);
+        // The timers are not operated
+        assert_eq!(3, c8.timers().0);
+
+        c8.step().unwrap();
+        let step = c8.step().unwrap().unwrap();
+        assert!(step.display_updated);
+        assert!(!step.collision);
+        let step = c8.step().unwrap().unwrap();
+        assert!(step.collision);
+        assert_eq!(vec![(0xF, 1)], step.changed);
+
+        // Nothing is executed while waiting for a key
+        c8.ldxk(2);
+        assert_eq!(Ok(None), c8.step());
     }
 
-    /// 8xy7 - SUBN Vx, Vy - Set Vx := Vy - Vx, set VF := NOT borrow
-    fn subn(&mut self, x: usize, y: usize) {
-        let (val, borrow) = (self.registers.v[y]).overflowing_sub(self.registers.v[x]);
-        self.registers.v[x] = val;
-        self.registers.v[0xf] = !borrow as u8;
+    #[test]
+    fn test_dump_state() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x05; LD VF, 0x07; CALL 0x208; JP 0x206; LD I, 0x2A0; LD DT, V0
+        c8.load_rom_bytes(&[
+            0x60, 0x05, 0x6F, 0x07, 0x22, 0x08, 0x12, 0x06, 0xA2, 0xA0, 0xF0, 0x15,
+        ])
+        .unwrap();
+        for _ in 0..5 {
+            c8.tick().unwrap();
+        }
+        let state = c8.dump_state();
+        assert_eq!(0x20C, state.pc);
+        assert_eq!(0x2A0, state.i);
+        assert_eq!(1, state.sp);
+        assert_eq!(0x206, state.stack[0]);
+        assert_eq!((5, 0), (state.delay_timer, state.sound_timer));
+        assert_eq!(5, state.v[0]);
+        assert_eq!(
+            "PC=20C I=2A0 SP=1 DT=05 ST=00
+V0=05 V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00
+V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=07
+Stack: 206",
+            state.to_string()
+        );
+        assert!(Cpu::with_seed(0)
+            .dump_state()
+            .to_string()
+            .ends_with("Stack: empty"));
     }
 
-    /// 8xyE - SHL Vx - Set Vx := Vx << 1
-    fn shl(&mut self, x: usize) {
-        self.registers.v[0xf] = (self.registers.v[x] & 0x80) >> 7;
-        self.registers.v[x] <<= 1;
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_save_state() {
+        // RND V0, 0x3F; RND V1, 0x1F; LD F, V2; DRW V0, V1, 5; ADD V2, 0x01; CALL 0x20E; JP 0x200; RET
+        let rom = [
+            0xC0, 0x3F, 0xC1, 0x1F, 0xF2, 0x29, 0xD0, 0x15, 0x72, 0x01, 0x22, 0x0E, 0x12, 0x00,
+            0x00, 0xEE,
+        ];
+        let run = |c8: &mut Cpu| {
+            let mut trace = Vec::new();
+            for _ in 0..50 {
+                c8.tick().unwrap();
+                trace.push((c8.dump_state(), c8.display));
+            }
+            trace
+        };
+        let mut c8 = Cpu::with_seed(1);
+        c8.load_rom_bytes(&rom).unwrap();
+        for _ in 0..101 {
+            c8.tick().unwrap();
+        }
+        let state = c8.save_state();
+        let expected = run(&mut c8);
+
+        c8.load_state(&state).unwrap();
+        assert!(expected == run(&mut c8));
+        // A machine with a different ROM and seed becomes identical too
+        let mut other = Cpu::with_seed(2);
+        other.load_state(&state).unwrap();
+        assert!(expected == run(&mut other));
+        assert_eq!(&rom[..], &other.program[..]);
+
+        // The RPL flags are machine state
+        c8.rpl_flags[3] = 0x5A;
+        let flags = c8.save_state();
+        c8.rpl_flags = [0; 16];
+        c8.load_state(&flags).unwrap();
+        assert_eq!(0x5A, c8.rpl_flags[3]);
+
+        // Errors leave the machine unchanged
+        let before = other.dump_state();
+        assert!(matches!(
+            other.load_state(&state[..state.len() / 2]),
+            Err(StateError::Corrupt(_))
+        ));
+        let mut decoded: SaveState = ciborium::de::from_reader(&state[..]).unwrap();
+        decoded.version = STATE_VERSION + 1;
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&decoded, &mut data).unwrap();
+        assert_eq!(
+            Err(StateError::Version(STATE_VERSION + 1)),
+            other.load_state(&data)
+        );
+        decoded.version = STATE_VERSION;
+        decoded.registers.sp = 17;
+        data.clear();
+        ciborium::ser::into_writer(&decoded, &mut data).unwrap();
+        assert_eq!(Err(StateError::Invalid), other.load_state(&data));
+        assert_eq!(before, other.dump_state());
     }
 
-    /// 9xy0 - SNE Vx, Vy - Skip next instruction if Vx != Vy
-    fn sne(&mut self, x: usize, y: usize) {
-        if self.registers.v[x] != self.registers.v[y] {
-            self.registers.pc += 2;
+    #[test]
+    fn test_coverage() {
+        let mut c8 = Cpu::with_seed(0);
+        // LD V0, 0x01; SE V0, 0x01; LD V1, 0x02; JP 0x208
+        c8.load_rom_bytes(&[0x60, 0x01, 0x30, 0x01, 0x61, 0x02, 0x12, 0x06])
+            .unwrap();
+        for _ in 0..4 {
+            c8.tick().unwrap();
         }
+        let covered: Vec<usize> = (0..4096).filter(|&addr| c8.coverage()[addr]).collect();
+        // The skipped LD V1 at 0x204 is not covered
+        assert_eq!(vec![0x200, 0x201, 0x202, 0x203, 0x206, 0x207], covered);
+        assert_eq!(0, c8.v(1));
+
+        c8.reset_coverage();
+        assert!(c8.coverage().iter().all(|&executed| !executed));
     }
 
-    /// Annn - LD I, addr - Set I := nnn
-    fn ldi(&mut self, nnn: u16) {
-        self.registers.i = nnn;
+    #[test]
+    fn test_last_collisions() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[0x300..0x302].copy_from_slice(&[0xF0, 0x90]);
+        c8.registers.i = 0x300;
+        c8.registers.v[0] = 10;
+        c8.registers.v[1] = 4;
+        c8.drw(0, 1, 2);
+        // Not recorded unless enabled
+        c8.drw(0, 1, 2);
+        assert!(c8.last_collisions().is_empty());
+
+        c8.set_collision_tracking(true);
+        c8.drw(0, 1, 2);
+        assert!(c8.last_collisions().is_empty());
+        // Overlap the first sprite one pixel to the right
+        c8.registers.v[0] = 11;
+        c8.drw(0, 1, 2);
+        assert_eq!(1, c8.registers.v[0xF]);
+        assert_eq!(&[(11, 4), (12, 4), (13, 4)], c8.last_collisions());
+
+        c8.set_collision_tracking(false);
+        assert!(c8.last_collisions().is_empty());
     }
 
-    /// Bnnn - JP V0, addr - Jump to location nnn + V0
-    fn jp0(&mut self, nnn: u16) {
-        self.registers.pc = nnn + self.registers.v[0] as u16;
+    #[test]
+    fn test_load_store_increments_i() {
+        for &increments in [false, true].iter() {
+            let mut c8 = Cpu::with_seed(0);
+            c8.quirks.load_store_increments_i = increments;
+            c8.registers.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+            c8.registers.i = 0x300;
+            c8.ldix(3);
+            assert_eq!([1, 2, 3, 4], c8.memory[0x300..0x304]);
+            let expected = if increments { 0x304 } else { 0x300 };
+            assert_eq!(expected, c8.registers.i);
+
+            c8.ldxi(1);
+            let (loaded, i) = if increments {
+                ([0, 0], 0x306)
+            } else {
+                ([1, 2], 0x300)
+            };
+            assert_eq!(loaded, c8.registers.v[..2]);
+            assert_eq!(i, c8.registers.i);
+        }
     }
 
-    /// Cxkk - RND Vx, byte - Set Vx := random byte AND kk
-    fn rnd(&mut self, x: usize, kk: u8) {
-        let mut rng = rand::thread_rng();
-        self.registers.v[x] = rng.gen::<u8>() & kk;
+    #[test]
+    fn test_shift_uses_vy() {
+        let mut c8 = Cpu::with_seed(0);
+        // Vx in place: Vy is ignored
+        c8.registers.v[1] = 0b0000_0101;
+        c8.registers.v[2] = 0b1000_0010;
+        c8.shr(1, 2);
+        assert_eq!((0b0000_0010, 1), (c8.registers.v[1], c8.registers.v[0xF]));
+        c8.registers.v[1] = 0b0100_0001;
+        c8.shl(1, 2);
+        assert_eq!((0b1000_0010, 0), (c8.registers.v[1], c8.registers.v[0xF]));
+
+        // Vy into Vx: the result and the flag come from Vy, which is unchanged
+        c8.quirks.shift_uses_vy = true;
+        c8.registers.v[1] = 0b0000_0101;
+        c8.shr(1, 2);
+        assert_eq!((0b0100_0001, 0), (c8.registers.v[1], c8.registers.v[0xF]));
+        c8.shl(1, 2);
+        assert_eq!((0b0000_0100, 1), (c8.registers.v[1], c8.registers.v[0xF]));
+        assert_eq!(0b1000_0010, c8.registers.v[2]);
     }
 
-    /// Dxyn - DRW Vx, Vy, nibble - Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision
-    fn drw(&mut self, x: usize, y: usize, n: u8) {
-        self.registers.v[0xF] = 0;
-        for i in 0..n as usize {
-            let i_offset = (self.registers.v[y] as usize + i) % C8_HEIGHT;
-            let sprite = self.memory[self.registers.i as usize + i];
-            for j in 0..8 {
-                let j_offset = (self.registers.v[x] as usize + j) % C8_WIDTH;
-                let pixel = (sprite >> (7 - j)) & 0x1;
-
-                if pixel == 0x1 {
-                    if self.display[i_offset][j_offset] {
-                        self.registers.v[0xF] = 1;
-                    }
-                    self.display[i_offset][j_offset] ^= true;
-                }
-            }
-        }
-        self.has_disp_update = true;
+    #[test]
+    fn test_shift_into_vf() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.registers.v[0xF] = 0b1000_0011;
+        c8.shr(0xF, 0);
+        assert_eq!(1, c8.registers.v[0xF]);
+        c8.registers.v[0xF] = 0b0100_0010;
+        c8.shr(0xF, 0);
+        assert_eq!(0, c8.registers.v[0xF]);
+
+        c8.registers.v[0xF] = 0b1100_0000;
+        c8.shl(0xF, 0);
+        assert_eq!(1, c8.registers.v[0xF]);
+        c8.registers.v[0xF] = 0b0100_0001;
+        c8.shl(0xF, 0);
+        assert_eq!(0, c8.registers.v[0xF]);
+
+        // With shift_uses_vy the flag comes from Vy
+        c8.quirks.shift_uses_vy = true;
+        c8.registers.v[0x1] = 0b0000_0001;
+        c8.registers.v[0xF] = 0;
+        c8.shr(0xF, 0x1);
+        assert_eq!(1, c8.registers.v[0xF]);
     }
 
-    /// Ex9E - SKP Vx - Skip next instruction if key with the value of Vx is pressed
-    fn skp(&mut self, x: usize) {
-        if self.key_state[self.registers.v[x] as usize] {
-            self.registers.pc += 2;
-        }
+    #[test]
+    fn test_add_vf_destination() {
+        let mut c8 = Cpu::with_seed(0);
+        // 0xF0 + 0x20 = 0x110, the carry replaces the sum 0x10
+        c8.registers.v[0xF] = 0xF0;
+        c8.registers.v[0x1] = 0x20;
+        c8.add(0xF, 0x1);
+        assert_eq!(1, c8.registers.v[0xF]);
+
+        // 0x10 + 0x20 = 0x30, no carry replaces the sum 0x30
+        c8.registers.v[0xF] = 0x10;
+        c8.add(0xF, 0x1);
+        assert_eq!(0, c8.registers.v[0xF]);
     }
 
-    /// ExA1 - SKNP Vx - Skip next instruction if key with value of Vx is not pressed
-    fn sknp(&mut self, x: usize) {
-        if !self.key_state[self.registers.v[x] as usize] {
-            self.registers.pc += 2;
-        }
+    #[test]
+    fn test_bcd_at_memory_end() {
+        // LD B, V0 with I = 0xFFF
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[512..514].copy_from_slice(&[0xF0, 0x33]);
+        c8.registers.v[0] = 123;
+        c8.registers.i = 0xFFF;
+        let font = c8.memory[0];
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
+        assert_eq!(1, c8.memory[0xFFF]);
+        assert_eq!(font, c8.memory[0]);
+
+        c8.set_memory_model(MemoryModel::Wrap);
+        c8.registers.pc = 512;
+        c8.tick().unwrap();
+        assert_eq!([1, 2, 3], [c8.memory[0xFFF], c8.memory[0], c8.memory[1]]);
     }
 
-    /// Fx07 - LD Vx, DT - Set Vx := Delay timer value
-    fn ldxdt(&mut self, x: usize) {
-        self.registers.v[x] = self.registers.delay_timer;
+    #[test]
+    fn test_pc_wrap() {
+        // SE V0, 0x00 at 0xFFE skips the instruction at 0x000
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_memory_model(MemoryModel::Wrap);
+        c8.memory[0xFFE..].copy_from_slice(&[0x30, 0x00]);
+        c8.registers.pc = 0xFFE;
+        c8.tick().unwrap();
+        assert_eq!(0x002, c8.registers.pc);
+
+        // LD V1, 0x05 at 0xFFE continues at 0x000
+        c8.memory[0xFFE..].copy_from_slice(&[0x61, 0x05]);
+        c8.registers.pc = 0xFFE;
+        c8.tick().unwrap();
+        assert_eq!(0x000, c8.registers.pc);
+        assert_eq!(5, c8.registers.v[1]);
+
+        // Under MemoryModel::Error the next fetch past the end faults instead
+        c8.set_memory_model(MemoryModel::Error);
+        c8.registers.pc = 0xFFE;
+        c8.tick().unwrap();
+        assert_eq!(0x1000, c8.registers.pc);
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
     }
 
-    /// Fx0A - LD Vx, K - Wait for a key press, store the value of the key in Vx
-    fn ldxk(&mut self, x: usize) {
-        self.waiting = Some(x)
+    #[test]
+    fn test_instruction_log() {
+        // LD V0, 0x01; ADD V0, 0x01; JP 0x202
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[512..518].copy_from_slice(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02]);
+        c8.tick().unwrap();
+        assert!(c8.recent_instructions().is_empty());
+
+        c8.set_instruction_log_len(4);
+        for _ in 0..5 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(
+            vec![
+                (0x204, 0x1202),
+                (0x202, 0x7001),
+                (0x204, 0x1202),
+                (0x202, 0x7001)
+            ],
+            c8.recent_instructions()
+        );
+
+        c8.set_instruction_log_len(1);
+        assert_eq!(vec![(0x202, 0x7001)], c8.recent_instructions());
     }
 
-    /// Fx15 - LD DT, Vx - Set delay timer := Vx
-    fn lddtx(&mut self, x: usize) {
-        self.registers.delay_timer = self.registers.v[x];
+    #[test]
+    fn test_with_seed() {
+        let rnd_sequence = |c8: &mut Cpu| -> Vec<u8> {
+            (0..32)
+                .map(|_| {
+                    c8.rnd(0, 0xFF);
+                    c8.registers.v[0]
+                })
+                .collect()
+        };
+        let mut a = Cpu::with_seed(42);
+        let mut b = Cpu::with_seed(42);
+        let mut c = Cpu::with_seed(43);
+        let sequence = rnd_sequence(&mut a);
+        assert_eq!(sequence, rnd_sequence(&mut b));
+        assert_ne!(sequence, rnd_sequence(&mut c));
     }
 
-    /// Fx18 - LD ST, Vx - Set sound timer := Vx
-    fn ldstx(&mut self, x: usize) {
-        self.registers.sound_timer = self.registers.v[x];
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_try_initialize() {
+        let c8 = Cpu::try_initialize().unwrap();
+        assert_eq!(FONTSET[..], c8.memory[..FONTSET.len()]);
+        assert_eq!(Some(7), Cpu::try_with_seed(7).unwrap().seed);
+
+        let mut c8 = Cpu::with_seed(7);
+        c8.memory[12] ^= 0xFF;
+        assert_eq!(Some(InitError::FontsetMismatch(12)), c8.validate().err());
     }
 
-    /// Fx1E - ADD I, Vx - Set I := I + Vx
-    fn addi(&mut self, x: usize) {
-        //self.registers.i += self.registers.v[x] as u16;
-        let (val, carry) = self.registers.i.overflowing_add(self.registers.v[x] as u16);
-        self.registers.i = val;
-        self.registers.v[0xf] = carry as u8;
+    #[test]
+    fn test_step_frame() {
+        // LD V0, 0x05; LD DT, V0; ADD V1, 0x01; JP 0x204
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0x60, 0x05, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04])
+            .unwrap();
+        assert_eq!(Ok(2), c8.step_frame(2));
+        assert_eq!((4, 0), c8.timers());
+        assert_eq!(Ok(10), c8.step_frame(10));
+        assert_eq!((3, 0), c8.timers());
+        assert_eq!(5, c8.v(1));
+
+        // Stops before a breakpoint, and continues from it on the next frame
+        c8.add_breakpoint(0x206);
+        assert_eq!(Ok(1), c8.step_frame(10));
+        assert_eq!(0x206, c8.pc());
+        assert_eq!(Some(0x206), c8.breakpoint_hit());
+        assert_eq!((2, 0), c8.timers());
+        assert_eq!(Ok(2), c8.step_frame(10));
+        assert_eq!(0x206, c8.pc());
+
+        // Waiting for a key still runs the timers
+        c8.load_rom_bytes(&[0xF2, 0x0A]).unwrap();
+        c8.reset();
+        c8.registers.delay_timer = 2;
+        assert_eq!(Ok(1), c8.step_frame(10));
+        assert_eq!(Ok(0), c8.step_frame(10));
+        assert_eq!((0, 0), c8.timers());
     }
 
-    /// Fx29 - LD F, Vx - Set I := location of sprite for digit Vx
-    fn ldf(&mut self, x: usize) {
-        self.registers.i = self.registers.v[x] as u16 * 5;
+    #[test]
+    fn test_run_cycles() {
+        // LD V0, 0x00; ADD V0, 0x01; SE V0, 0x0A; JP 0x202; LD V1, K; JP 0x20A
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[
+            0x60, 0x00, 0x70, 0x01, 0x30, 0x0A, 0x12, 0x02, 0xF1, 0x0A, 0x12, 0x0A,
+        ])
+        .unwrap();
+        assert_eq!(Ok(0), c8.run_cycles(0));
+        assert_eq!(Ok(4), c8.run_cycles(4));
+        assert_eq!(1, c8.v(0));
+        assert_eq!(4, c8.instructions_executed());
+
+        // Stops before a breakpoint, and continues from it
+        c8.add_breakpoint(0x204);
+        assert_eq!(Ok(1), c8.run_cycles(100));
+        assert_eq!(Some(0x204), c8.breakpoint_hit());
+        assert_eq!(0x204, c8.pc());
+        assert_eq!(Ok(3), c8.run_cycles(100));
+        assert_eq!(0x204, c8.pc());
+        c8.remove_breakpoint(0x204);
+
+        // Stops while waiting for a key, and once the ROM halts
+        assert_eq!(Ok(23), c8.run_cycles(100));
+        assert!(c8.is_waiting_for_key());
+        assert_eq!(Ok(0), c8.run_cycles(100));
+        c8.set_key_pressed(0x7);
+        assert_eq!(Ok(1), c8.run_cycles(100));
+        assert!(c8.is_halted());
+        assert_eq!(Ok(0), c8.run_cycles(100));
+        assert_eq!(7, c8.v(1));
+
+        // Errors end execution
+        c8.load_rom_bytes(&[0x60, 0x01, 0x80, 0x08]).unwrap();
+        c8.reset();
+        assert_eq!(Err(CpuError::UnknownOpcode(0x8008)), c8.run_cycles(10));
+        assert_eq!(1, c8.v(0));
     }
 
-    /// Fx33 - LD B, Vx - Store BCD representation of Vx in memory locations I, I+1, and I+2
-    fn ldb(&mut self, x: usize) {
-        let val = self.registers.v[x];
-        let addr = self.registers.i as usize;
-        self.memory[addr] = val / 100;
-        self.memory[addr + 1] = val / 10 % 10;
-        self.memory[addr + 2] = val % 10;
+    #[test]
+    fn test_waiting_register() {
+        let mut c8 = Cpu::with_seed(0);
+        assert_eq!(None, c8.waiting_register());
+        c8.ldxk(3);
+        assert!(c8.is_waiting_for_key());
+        assert_eq!(Some(3), c8.waiting_register());
+        c8.set_key_pressed(0xC);
+        assert_eq!(None, c8.waiting_register());
+        assert_eq!(0xC, c8.v(3));
     }
 
-    /// Fx55 - LD [I], Vx - Store registers V0 through Vx, in memory starting at location I
-    fn ldix(&mut self, x: usize) {
-        for i in 0..=x {
-            self.memory[self.registers.i as usize + i] = self.registers.v[i];
+    #[test]
+    fn test_queued_key_wait() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_key_wait_model(KeyWaitModel::Queued);
+        // LD V2, K; LD V3, K; JP 0x204
+        c8.load_rom_bytes(&[0xF2, 0x0A, 0xF3, 0x0A, 0x12, 0x04])
+            .unwrap();
+
+        // A buffered key is taken without waiting
+        c8.queue_key(0xA);
+        c8.tick().unwrap();
+        assert_eq!(None, c8.waiting_register());
+        assert_eq!(0xA, c8.v(2));
+
+        // With nothing buffered a request is posted, the timers keep running
+        c8.registers.delay_timer = 5;
+        c8.tick().unwrap();
+        assert_eq!(Some(3), c8.waiting_register());
+        for _ in 0..8 {
+            c8.tick().unwrap();
         }
+        c8.update_timers();
+        assert_eq!(0x204, c8.pc());
+        assert_eq!(4, c8.timers().0);
+
+        // Fulfilled from the frontend's queue without an intervening tick
+        c8.queue_key(0x7);
+        assert_eq!(None, c8.waiting_register());
+        assert_eq!(0x7, c8.v(3));
+        assert!(!c8.fulfill_key_request(0x1));
+
+        // The blocking model does not buffer keys
+        c8.set_key_wait_model(KeyWaitModel::Blocking);
+        c8.reset();
+        c8.queue_key(0xB);
+        c8.tick().unwrap();
+        assert_eq!(Some(2), c8.waiting_register());
     }
 
-    /// Fx65 - LD Vx, [I] - Read registers V0 through Vx from memory starting at location I
-    fn ldxi(&mut self, x: usize) {
-        for i in 0..=x {
-            self.registers.v[i] = self.memory[self.registers.i as usize + i];
+    #[test]
+    fn test_delay_timer_wait_loop() {
+        // LD V0, 0x03; LD DT, V0; LD V1, DT; SE V1, 0x00; JP 0x204; JP 0x20A
+        let rom = [
+            0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0A,
+        ];
+        // The timer is set in the first frame and reaches zero at the end of the fourth, where the loop exits
+        for &ipf in [8, 15, 100].iter() {
+            let mut c8 = Cpu::with_seed(0);
+            c8.load_rom_bytes(&rom).unwrap();
+            let frames = (1..=10)
+                .find(|_| {
+                    c8.step_frame(ipf).unwrap();
+                    c8.pc() == 0x20A
+                })
+                .unwrap();
+            assert_eq!(4, frames, "{} instructions per frame", ipf);
         }
+
+        // The same with tick and a timer update after every eight instructions
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&rom).unwrap();
+        let frames = (1..=10)
+            .find(|_| {
+                for _ in 0..8 {
+                    c8.tick().unwrap();
+                }
+                c8.update_timers();
+                c8.pc() == 0x20A
+            })
+            .unwrap();
+        assert_eq!(4, frames);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_at_self_jump() {
+        // LD V0, 0x01; JP 0x202
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[512..516].copy_from_slice(&[0x60, 0x01, 0x12, 0x02]);
+        assert!(!c8.at_self_jump());
+        c8.tick().unwrap();
+        assert!(c8.at_self_jump());
+        c8.tick().unwrap();
+        assert!(c8.at_self_jump());
+        assert_eq!(0x202, c8.registers.pc);
+    }
 
     #[test]
-    fn test_add() {
-        // 8xy4 - ADD Vx, Vy - Set Vx := Vx + Vy, set VF := carry
-        let mut c8 = Cpu::initialize();
+    fn test_odd_pc() {
+        // 0x200  JP 0x203
+        // 0x202  0x00, 0x60, 0x2A - LD V0, 0x2A at the odd address 0x203
+        // 0x205  0x12, 0x05 - JP 0x205
+        let mut c8 = Cpu::with_seed(0);
+        c8.memory[512..519].copy_from_slice(&[0x12, 0x03, 0x00, 0x60, 0x2A, 0x12, 0x05]);
+        c8.tick().unwrap();
+        assert_eq!(0x203, c8.registers.pc);
+        c8.tick().unwrap();
+        assert_eq!(0x2A, c8.registers.v[0]);
+        assert_eq!(0x205, c8.registers.pc);
+        assert!(c8.at_self_jump());
 
-        c8.registers.v[0] = 10;
-        c8.registers.v[1] = 15;
-        c8.add(0, 1);
-        assert_eq!(25, c8.registers.v[0]);
-        assert_eq!(0, c8.registers.v[0xf]);
+        // Rewriting the straddling instruction takes effect on the next fetch
+        c8.memory[0x204] = 0x55;
+        c8.registers.pc = 0x203;
+        c8.tick().unwrap();
+        assert_eq!(0x55, c8.registers.v[0]);
+    }
 
-        c8.registers.v[0] = 254;
-        c8.registers.v[1] = 1;
-        c8.add(0, 1);
-        assert_eq!(255, c8.registers.v[0]);
-        assert_eq!(0, c8.registers.v[0xf]);
+    #[test]
+    fn test_draw_throttle() {
+        // Returns the number of instructions executed in the first frame
+        let run_frame = |program: &[u8], costs: Option<CycleCosts>| -> usize {
+            let mut c8 = Cpu::with_seed(0);
+            c8.memory[512..512 + program.len()].copy_from_slice(program);
+            c8.set_draw_throttle(costs);
+            c8.set_instruction_log_len(64);
+            for _ in 0..8 {
+                c8.tick().unwrap();
+            }
+            c8.recent_instructions().len()
+        };
+        // ADD V0, 0x01; JP 0x200
+        let compute = [0x70, 0x01, 0x12, 0x00];
+        // DRW V0, V0, 15; JP 0x200
+        let draw = [0xD0, 0x0F, 0x12, 0x00];
 
-        c8.registers.v[0] = 255;
-        c8.registers.v[1] = 1;
-        c8.add(0, 1);
-        assert_eq!(0, c8.registers.v[0]);
-        assert_eq!(1, c8.registers.v[0xf]);
+        assert_eq!(8, run_frame(&compute, None));
+        assert_eq!(8, run_frame(&draw, None));
+        assert_eq!(8, run_frame(&compute, Some(CycleCosts::default())));
+        // Each DRW costs 23 cycles and each JP 4, so the budget of 32 runs out after DRW, JP, DRW
+        assert_eq!(3, run_frame(&draw, Some(CycleCosts::default())));
     }
 
+    #[cfg(feature = "std")]
     #[test]
-    fn test_addi() {
-        // Fx1E - ADD I, Vx - Set I := I + Vx
-        let mut c8 = Cpu::initialize();
+    fn test_disassemble_window() {
+        use std::fs::File;
 
-        c8.registers.i = 15;
-        c8.registers.v[0] = 10;
-        c8.addi(0);
-        assert_eq!(25, c8.registers.i);
-        assert_eq!(0, c8.registers.v[0xf]);
+        let path = std::env::temp_dir().join("chip8-test-disassemble-window.ch8");
+        // CALL 0x206; JP 0x202; data; LD I, 0x204; RET
+        File::create(&path)
+            .unwrap()
+            .write_all(&[0x22, 0x06, 0x12, 0x02, 0xFF, 0x18, 0xA2, 0x04, 0x00, 0xEE])
+            .unwrap();
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom(path.to_str().unwrap()).unwrap();
+        c8.tick().unwrap();
 
-        c8.registers.i = 65534;
-        c8.registers.v[0] = 1;
-        c8.addi(0);
-        assert_eq!(65535, c8.registers.i);
-        assert_eq!(0, c8.registers.v[0xf]);
+        let window = c8.disassemble_window(2);
+        assert_eq!(
+            vec![
+                (0x202, "JP 0x202".to_owned(), false),
+                (0x204, "DB 0xFF, 0x18".to_owned(), false),
+                (0x206, "LD I, 0x204".to_owned(), true),
+                (0x208, "RET".to_owned(), false),
+                (0x20A, "SYS 0x000".to_owned(), false),
+            ],
+            window
+        );
 
-        c8.registers.i = 65535;
-        c8.registers.v[0] = 1;
-        c8.addi(0);
-        assert_eq!(0, c8.registers.i);
-        assert_eq!(1, c8.registers.v[0xf]);
+        // Lines before the start of memory are left out
+        c8.registers.pc = 0x002;
+        let window = c8.disassemble_window(2);
+        assert_eq!(4, window.len());
+        assert_eq!((0x002, true), (window[1].0, window[1].2));
     }
 
     #[test]
-    fn test_bcd() {
-        let mut c8 = Cpu::initialize();
-        c8.registers.i = 0;
-        c8.registers.v[0] = 123;
-        c8.registers.v[1] = 1;
-        c8.registers.v[2] = 45;
+    fn test_load_rom_bytes_swapped() {
+        // LD V0, 0x2A; ADD V0, 0x01 written as little endian words, plus a trailing odd byte
+        let blob = [0x2A, 0x60, 0x01, 0x70, 0xEE];
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes_with(
+            &blob,
+            LoadOptions {
+                swap_bytes: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(&[0x60, 0x2A, 0x70, 0x01, 0xEE], &c8.memory[512..517]);
+        assert_eq!(5, c8.rom_len);
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(0x2B, c8.registers.v[0]);
 
-        c8.ldb(0);
-        assert_eq!(1, c8.memory[0]);
-        assert_eq!(2, c8.memory[1]);
-        assert_eq!(3, c8.memory[2]);
+        // Bytes are loaded as is by default
+        c8.load_rom_bytes(&blob).unwrap();
+        assert_eq!(&blob, &c8.memory[512..517]);
+    }
 
-        c8.ldb(1);
-        assert_eq!(0, c8.memory[0]);
-        assert_eq!(0, c8.memory[1]);
-        assert_eq!(1, c8.memory[2]);
+    #[test]
+    fn test_load_asm() {
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_asm("LD V0, 0x2A\nADD V0, 1\nloop: JP loop")
+            .unwrap();
+        assert_eq!(&[0x60, 0x2A, 0x70, 0x01, 0x12, 0x04], &c8.memory[512..518]);
+        assert_eq!(6, c8.rom_len);
+        for _ in 0..3 {
+            c8.tick().unwrap();
+        }
+        assert_eq!(0x2B, c8.registers.v[0]);
+        assert_eq!(0x204, c8.registers.pc);
 
-        c8.ldb(2);
-        assert_eq!(0, c8.memory[0]);
-        assert_eq!(4, c8.memory[1]);
-        assert_eq!(5, c8.memory[2]);
+        // A program that does not assemble leaves the loaded one in place
+        assert_eq!(
+            Err(AsmError::UnknownMnemonic(1, "NOP".to_string())),
+            c8.load_asm("NOP")
+        );
+        assert_eq!(0x60, c8.memory[512]);
+        assert_eq!(6, c8.rom_len);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_load_rom_errors() {
+        use std::fs::File;
+
+        let dir = std::env::temp_dir();
+        let mut c8 = Cpu::with_seed(0);
+        let missing = dir.join("chip8-test-missing.ch8");
+        match c8.load_rom(missing.to_str().unwrap()) {
+            Err(RomError::NotFound) => {}
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+
+        let oversized = dir.join("chip8-test-oversized.ch8");
+        File::create(&oversized)
+            .unwrap()
+            .write_all(&[0xAA; 4000])
+            .unwrap();
+        match c8.load_rom(oversized.to_str().unwrap()) {
+            Err(RomError::TooLarge(4000)) => {}
+            other => panic!("Expected TooLarge, got {:?}", other),
+        }
+        assert_eq!(0, c8.memory[4095]);
+
+        // Exactly filling memory is fine
+        let full = dir.join("chip8-test-full.ch8");
+        File::create(&full)
+            .unwrap()
+            .write_all(&[0xAA; 3584])
+            .unwrap();
+        c8.load_rom(full.to_str().unwrap()).unwrap();
+        assert_eq!(0xAA, c8.memory[4095]);
+    }
+
+    #[test]
+    fn test_load_rom_truncated() {
+        let mut c8 = Cpu::with_seed(0);
+        match c8.load_rom_bytes(&[0xAA; 4000]) {
+            Err(RomError::TooLarge(4000)) => {}
+            other => panic!("Expected TooLarge, got {:?}", other),
+        }
+        assert_eq!(0, c8.memory[4095]);
+
+        let report = c8.load_rom_bytes_with(&[0xAA; 4000], LoadOptions::default());
+        assert_eq!(
+            LoadReport {
+                loaded: 3584,
+                size: 4000,
+                truncated: true
+            },
+            report
+        );
+        assert_eq!(0xAA, c8.memory[4095]);
+
+        let options = LoadOptions {
+            max_size: Some(4),
+            ..Default::default()
+        };
+        let report = c8.load_rom_bytes_with(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x00], options);
+        assert_eq!((4, 6, true), (report.loaded, report.size, report.truncated));
+        assert_eq!(4, c8.rom_len);
+
+        c8.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        assert_eq!(2, c8.rom_len);
+    }
+
+    #[test]
+    fn test_instruction_decode() {
+        for &(opcode, instruction) in &[
+            (0x00E0, Instruction::Cls),
+            (0x00EE, Instruction::Ret),
+            (0x0123, Instruction::Sys),
+            (0x00C3, Instruction::Scd),
+            (0x00FB, Instruction::Scr),
+            (0x00FC, Instruction::Scl),
+            (0x00FE, Instruction::Low),
+            (0x00FF, Instruction::High),
+            (0x8AB6, Instruction::Shr),
+            (0x8AB8, Instruction::Unknown),
+            (0xD125, Instruction::Drw),
+            (0xE39E, Instruction::Skp),
+            (0xE3A1, Instruction::Sknp),
+            (0xE3A2, Instruction::Unknown),
+            (0xF565, Instruction::Ldxi),
+            (0xF301, Instruction::Plane),
+            (0xF130, Instruction::Ldhf),
+            (0xF775, Instruction::Ldrx),
+            (0xF785, Instruction::Ldxr),
+            (0x5121, Instruction::Unknown),
+        ] {
+            assert_eq!(instruction, Instruction::decode(opcode), "{:04X}", opcode);
+            assert_eq!(instruction, Opcode::from_op(opcode).instruction);
+        }
+    }
+
+    #[test]
+    fn test_load_store_at_memory_end() {
+        // LD [I], V3; LD V3, [I] with I = 0xFFE
+        let program = [0xF3, 0x55, 0xF3, 0x65];
+        let registers = [0x11, 0x22, 0x33, 0x44];
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_memory_model(MemoryModel::Wrap);
+        c8.load_rom_bytes(&program).unwrap();
+        c8.registers.v[..4].copy_from_slice(&registers);
+        c8.registers.i = 0xFFE;
+        c8.tick().unwrap();
+        assert_eq!([0x11, 0x22], c8.memory[0xFFE..]);
+        assert_eq!([0x33, 0x44], c8.memory[..2]);
+        c8.registers.v[..4].copy_from_slice(&[0; 4]);
+        c8.tick().unwrap();
+        assert_eq!(registers, c8.registers.v[..4]);
+
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&program).unwrap();
+        c8.registers.v[..4].copy_from_slice(&registers);
+        c8.registers.i = 0xFFE;
+        let font = [c8.memory[0], c8.memory[1]];
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
+        assert_eq!([0x11, 0x22], c8.memory[0xFFE..]);
+        assert_eq!(font, c8.memory[..2]);
+        c8.registers.v[..4].copy_from_slice(&[0; 4]);
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
+        assert_eq!([0x11, 0x22, 0, 0], c8.registers.v[..4]);
+    }
+
+    #[test]
+    fn test_drw_at_memory_end() {
+        // DRW V0, V0, 8 with I = 0xFFE, reading past the end of memory
+        let mut c8 = Cpu::with_seed(0);
+        c8.set_memory_model(MemoryModel::Wrap);
+        c8.load_rom_bytes(&[0xD0, 0x08]).unwrap();
+        c8.memory[0xFFE] = 0xFF;
+        c8.memory[0xFFF] = 0x81;
+        c8.registers.i = 0xFFE;
+        c8.tick().unwrap();
+        // The last six rows wrap around to the start of memory, the "0" glyph and the first row of "1"
+        let rows: Vec<u8> = c8.view_display()[..8]
+            .iter()
+            .map(|row| row[..8].iter().fold(0, |bits, &lit| bits << 1 | lit as u8))
+            .collect();
+        assert_eq!(vec![0xFF, 0x81, 0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20], rows);
+
+        // Under the default memory model the rows past the end are not drawn and the draw faults
+        let mut c8 = Cpu::with_seed(0);
+        c8.load_rom_bytes(&[0xD0, 0x08]).unwrap();
+        c8.memory[0xFFE] = 0xFF;
+        c8.memory[0xFFF] = 0x81;
+        c8.registers.i = 0xFFE;
+        assert_eq!(Err(CpuError::MemoryOutOfBounds(0x1000)), c8.tick());
+        assert_eq!(10, lit_pixels(&c8).len());
     }
 
     #[test]
     fn test_sub() {
         // 8xy5 - SUB Vx, Vy - Set Vx := Vx - Vy, set VF := NOT borrow
-        let mut c8 = Cpu::initialize();
+        let mut c8 = Cpu::with_seed(0);
 
         c8.registers.v[0] = 15;
         c8.registers.v[1] = 10;
@@ -591,7 +4784,7 @@ mod tests {
     #[test]
     fn test_subn() {
         // 8xy7 - SUBN Vx, Vy - Set Vx := Vy - Vx, set VF := NOT borrow
-        let mut c8 = Cpu::initialize();
+        let mut c8 = Cpu::with_seed(0);
 
         c8.registers.v[0] = 10;
         c8.registers.v[1] = 15;
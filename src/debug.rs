@@ -0,0 +1,13 @@
+/// Execution breakpoint. Execution stops before the instruction at `addr` is executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// Address of the instruction to stop at
+    pub addr: u16,
+}
+
+/// Memory watchpoint. Execution stops after an instruction writes to `addr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// Memory address to watch for writes
+    pub addr: u16,
+}
@@ -0,0 +1,284 @@
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use cpu::{Instruction, Opcode};
+
+/// Size of the Chip-8 address space
+const MEMORY_SIZE: usize = 4096;
+/// Address programs are loaded at and start executing from
+const PROGRAM_START: usize = 512;
+
+/// Disassemble a single opcode into the mnemonics of Cowgod's technical reference, e.g. `LD V0, 0x01`. Opcodes that
+/// do not decode to an instruction are shown as a data word, e.g. `DW 0x8008`.
+///
+/// # Arguments
+///
+/// * `opcode` - Opcode to disassemble
+pub fn disassemble(opcode: u16) -> String {
+    let op = Opcode::from_op(opcode);
+    let (x, y, kk, nnn) = (op.x, op.y, op.kk, op.nnn);
+    match op.instruction {
+        Instruction::Cls => "CLS".to_owned(),
+        Instruction::Ret => "RET".to_owned(),
+        Instruction::Scd => format!("SCD {}", op.n),
+        Instruction::Scr => "SCR".to_owned(),
+        Instruction::Scl => "SCL".to_owned(),
+        Instruction::Low => "LOW".to_owned(),
+        Instruction::High => "HIGH".to_owned(),
+        Instruction::Sys => format!("SYS {:#05X}", nnn),
+        Instruction::Jp => format!("JP {:#05X}", nnn),
+        Instruction::Call => format!("CALL {:#05X}", nnn),
+        Instruction::Sec => format!("SE V{:X}, {:#04X}", x, kk),
+        Instruction::Snec => format!("SNE V{:X}, {:#04X}", x, kk),
+        Instruction::Se => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::Ldc => format!("LD V{:X}, {:#04X}", x, kk),
+        Instruction::Addc => format!("ADD V{:X}, {:#04X}", x, kk),
+        Instruction::Ld => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::Add => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::Shr => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::Subn => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::Shl => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::Sne => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::Ldi => format!("LD I, {:#05X}", nnn),
+        Instruction::Jp0 => format!("JP V0, {:#05X}", nnn),
+        Instruction::Rnd => format!("RND V{:X}, {:#04X}", x, kk),
+        Instruction::Drw => format!("DRW V{:X}, V{:X}, {}", x, y, op.n),
+        Instruction::Skp => format!("SKP V{:X}", x),
+        Instruction::Sknp => format!("SKNP V{:X}", x),
+        Instruction::Ldxdt => format!("LD V{:X}, DT", x),
+        Instruction::Ldxk => format!("LD V{:X}, K", x),
+        Instruction::Lddtx => format!("LD DT, V{:X}", x),
+        Instruction::Ldstx => format!("LD ST, V{:X}", x),
+        Instruction::Addi => format!("ADD I, V{:X}", x),
+        Instruction::Plane => format!("PLANE {}", x),
+        Instruction::Ldf => format!("LD F, V{:X}", x),
+        Instruction::Ldhf => format!("LD HF, V{:X}", x),
+        Instruction::Ldb => format!("LD B, V{:X}", x),
+        Instruction::Ldix => format!("LD [I], V{:X}", x),
+        Instruction::Ldxi => format!("LD V{:X}, [I]", x),
+        Instruction::Ldrx => format!("LD R, V{:X}", x),
+        Instruction::Ldxr => format!("LD V{:X}, R", x),
+        Instruction::Unknown => format!("DW {:#06X}", opcode),
+    }
+}
+
+/// Disassemble a whole ROM without running it, two bytes per line, e.g. `6A02  LD VA, 0x02`. Every line shows the
+/// raw opcode followed by its disassembly, so opcodes that do not decode to an instruction become data words (see
+/// `disassemble`). A trailing odd byte is shown as `DB`.
+///
+/// # Arguments
+///
+/// * `bytes` - Program bytes as loaded at 512
+///
+/// # Returns
+///
+/// * `lines` - (address, text) per line, in address order
+pub fn disassemble_rom(bytes: &[u8]) -> Vec<(u16, String)> {
+    bytes
+        .chunks(2)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let addr = (PROGRAM_START + 2 * i) as u16;
+            let text = match *chunk {
+                [hi, lo] => {
+                    let opcode = (hi as u16) << 8 | lo as u16;
+                    format!("{:04X}  {}", opcode, disassemble(opcode))
+                }
+                [byte] => format!("{:02X}    DB {:#04X}", byte, byte),
+                _ => unreachable!(),
+            };
+            (addr, text)
+        })
+        .collect()
+}
+
+/// Classification of a memory address for disassembly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    /// Nothing is known about the address
+    Unknown,
+    /// The address holds (part of) an instruction and should be decoded
+    Code,
+    /// The address holds data and should be shown as raw bytes
+    Data,
+}
+
+/// Per-address code/data annotations over the whole address space. Regions can be marked manually or derived from a
+/// reachability analysis of a ROM.
+pub struct MemoryMap {
+    regions: Vec<Region>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap::new()
+    }
+}
+
+impl MemoryMap {
+    /// Returns a map with every address `Region::Unknown`
+    pub fn new() -> MemoryMap {
+        MemoryMap {
+            regions: vec![Region::Unknown; MEMORY_SIZE],
+        }
+    }
+
+    /// Returns a map of a ROM loaded at 512, built by following every path of execution from the entry point. Bytes of
+    /// reachable instructions are marked `Code`, all other ROM bytes are marked `Data`.
+    ///
+    /// Jumps and calls are followed, skips continue at both the next and the following instruction, and `RET` and
+    /// `JP V0, addr` end a path since their targets are only known at runtime. Code only reached through `JP V0, addr`
+    /// (e.g. jump tables) is therefore marked as data and may be marked manually.
+    ///
+    /// # Arguments
+    ///
+    /// * `rom` - Program bytes as loaded at 512
+    pub fn analyze(rom: &[u8]) -> MemoryMap {
+        let mut map = MemoryMap::new();
+        let end = (PROGRAM_START + rom.len()).min(MEMORY_SIZE);
+        for region in &mut map.regions[PROGRAM_START..end] {
+            *region = Region::Data;
+        }
+
+        let fetch = |addr: usize| rom[addr - PROGRAM_START];
+        let mut visited = vec![false; MEMORY_SIZE];
+        let mut pending = vec![PROGRAM_START];
+        while let Some(addr) = pending.pop() {
+            if addr < PROGRAM_START || addr + 1 >= end || visited[addr] {
+                continue;
+            }
+            visited[addr] = true;
+            map.regions[addr] = Region::Code;
+            map.regions[addr + 1] = Region::Code;
+
+            let opcode = (fetch(addr) as u16) << 8 | fetch(addr + 1) as u16;
+            let op = Opcode::from_op(opcode);
+            match op.instruction {
+                Instruction::Ret | Instruction::Jp0 => {}
+                Instruction::Jp => pending.push(op.nnn as usize),
+                Instruction::Call => {
+                    pending.push(op.nnn as usize);
+                    pending.push(addr + 2);
+                }
+                Instruction::Sec
+                | Instruction::Snec
+                | Instruction::Se
+                | Instruction::Sne
+                | Instruction::Skp
+                | Instruction::Sknp => {
+                    pending.push(addr + 2);
+                    pending.push(addr + 4);
+                }
+                _ => pending.push(addr + 2),
+            }
+        }
+        map
+    }
+
+    /// Get the region of an address. Addresses outside of memory are `Region::Unknown`.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to look up
+    pub fn region(&self, addr: u16) -> Region {
+        self.regions
+            .get(addr as usize)
+            .cloned()
+            .unwrap_or(Region::Unknown)
+    }
+
+    /// Mark a range of addresses as the given region, clamped to the address space
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - First address to mark
+    /// * `len` - Number of addresses to mark
+    /// * `region` - Region to assign
+    pub fn mark(&mut self, start: u16, len: usize, region: Region) {
+        let start = (start as usize).min(MEMORY_SIZE);
+        let end = (start + len).min(MEMORY_SIZE);
+        for r in &mut self.regions[start..end] {
+            *r = region;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::FONTSET;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!("CLS", disassemble(0x00E0));
+        assert_eq!("RET", disassemble(0x00EE));
+        assert_eq!("HIGH", disassemble(0x00FF));
+        assert_eq!("SCD 4", disassemble(0x00C4));
+        assert_eq!("PLANE 3", disassemble(0xF301));
+        assert_eq!("JP 0x202", disassemble(0x1202));
+        assert_eq!("LD V0, 0x01", disassemble(0x6001));
+        assert_eq!("SHR V3, V0", disassemble(0x8306));
+        assert_eq!("DRW V0, V1, 5", disassemble(0xD015));
+        assert_eq!("LD VA, [I]", disassemble(0xFA65));
+        assert_eq!("DW 0x8008", disassemble(0x8008));
+    }
+
+    #[test]
+    fn test_disassemble_rom() {
+        // LD VA, 0x02; <unknown>; DRW V0, V1, 5; trailing byte
+        let lines = disassemble_rom(&[0x6A, 0x02, 0x80, 0x08, 0xD0, 0x15, 0xFF]);
+        assert_eq!(
+            vec![
+                (0x200, "6A02  LD VA, 0x02".to_owned()),
+                (0x202, "8008  DW 0x8008".to_owned()),
+                (0x204, "D015  DRW V0, V1, 5".to_owned()),
+                (0x206, "FF    DB 0xFF".to_owned()),
+            ],
+            lines
+        );
+
+        // The font is data, but every byte pair still disassembles and the raw bytes round trip
+        let lines = disassemble_rom(&FONTSET);
+        assert_eq!(FONTSET.len() / 2, lines.len());
+        let bytes: Vec<u8> = lines
+            .iter()
+            .flat_map(|(_, text)| {
+                let opcode = u16::from_str_radix(&text[..4], 16).unwrap();
+                vec![(opcode >> 8) as u8, opcode as u8]
+            })
+            .collect();
+        assert_eq!(&FONTSET[..], &bytes[..]);
+    }
+
+    #[test]
+    fn test_analyze_embedded_data() {
+        let rom = [
+            0x22, 0x06, // 0x200 CALL 0x206
+            0x12, 0x02, // 0x202 JP 0x202
+            0xFF, 0x18, // 0x204 data table
+            0xA2, 0x04, // 0x206 LD I, 0x204
+            0x34, 0x00, // 0x208 SE V4, 0x00
+            0x00, 0xEE, // 0x20A RET
+            0x00, 0xEE, // 0x20C RET
+            0x80, 0x08, // 0x20E data
+        ];
+        let mut map = MemoryMap::analyze(&rom);
+
+        for addr in (0x200..0x204).chain(0x206..0x20E) {
+            assert_eq!(Region::Code, map.region(addr), "{:03X}", addr);
+        }
+        for addr in (0x204..0x206).chain(0x20E..0x210) {
+            assert_eq!(Region::Data, map.region(addr), "{:03X}", addr);
+        }
+        assert_eq!(Region::Unknown, map.region(0x1FF));
+        assert_eq!(Region::Unknown, map.region(0x210));
+
+        map.mark(0x20E, 2, Region::Code);
+        assert_eq!(Region::Code, map.region(0x20F));
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Destination for the Chip-8 framebuffer. Implemented by frontends (ggez, terminal, headless, ...) so the `Cpu` can
+/// deliver display output without knowing anything about the renderer.
+pub trait DisplaySink {
+    /// Receive the current framebuffer. When used with `Cpu::present_display` this is only
+    /// called when the display has changed since the last presentation; a frame sink is called once per frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - The framebuffer at the active resolution, 64x32 or 128x64, indexed as `display[y][x]`
+    fn present(&mut self, display: &[&[bool]]);
+}
+
+/// Count the pixels that differ between two framebuffers, for fuzzy comparisons that tolerate a few pixels of
+/// difference. See `display_diff_pixels`.
+///
+/// # Arguments
+///
+/// * `a` - First framebuffer, indexed as `a[y][x]`
+/// * `b` - Second framebuffer, indexed as `b[y][x]`
+pub fn display_diff<R: AsRef<[bool]>>(a: &[R], b: &[R]) -> usize {
+    display_diff_pixels(a, b).len()
+}
+
+/// List the pixels that differ between two framebuffers of any resolution. When the resolutions differ, pixels that
+/// only exist in one of the framebuffers count as differing.
+///
+/// # Arguments
+///
+/// * `a` - First framebuffer, indexed as `a[y][x]`
+/// * `b` - Second framebuffer, indexed as `b[y][x]`
+///
+/// # Returns
+///
+/// * `pixels` - (x, y) of every differing pixel, in row major order
+pub fn display_diff_pixels<R: AsRef<[bool]>>(a: &[R], b: &[R]) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for y in 0..a.len().max(b.len()) {
+        let row_a = a.get(y).map_or(&[][..], |row| row.as_ref());
+        let row_b = b.get(y).map_or(&[][..], |row| row.as_ref());
+        for x in 0..row_a.len().max(row_b.len()) {
+            if row_a.get(x) != row_b.get(x) {
+                pixels.push((x, y));
+            }
+        }
+    }
+    pixels
+}
+
+/// Render a framebuffer of any resolution as text, one line per row with `#` for lit and `.` for dark pixels. Each
+/// line ends with a newline.
+///
+/// # Arguments
+///
+/// * `display` - Framebuffer, indexed as `display[y][x]`
+pub fn display_to_ascii<R: AsRef<[bool]>>(display: &[R]) -> String {
+    let mut text = String::new();
+    for row in display {
+        text.extend(row.as_ref().iter().map(|&lit| if lit { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::{C8_HEIGHT, C8_WIDTH};
+
+    #[test]
+    fn test_display_diff() {
+        let a = [[false; C8_WIDTH]; C8_HEIGHT];
+        let mut b = a;
+        assert_eq!(0, display_diff(&a, &b));
+
+        b[0][3] = true;
+        b[31][63] = true;
+        assert_eq!(2, display_diff(&a, &b));
+        assert_eq!(vec![(3, 0), (63, 31)], display_diff_pixels(&a, &b));
+
+        // Works for any resolution, pixels outside the smaller framebuffer differ
+        let hires = [[false; 128]; 64];
+        assert_eq!(0, display_diff(&hires, &hires));
+        let small: Vec<&[bool]> = a.iter().map(|row| &row[..]).collect();
+        let large: Vec<&[bool]> = hires.iter().map(|row| &row[..]).collect();
+        assert_eq!(128 * 64 - 64 * 32, display_diff(&small, &large));
+    }
+
+    #[test]
+    fn test_display_to_ascii() {
+        let display = [[false, true, false], [true, false, true]];
+        assert_eq!(".#.\n#.#\n", display_to_ascii(&display));
+
+        let text = display_to_ascii(&[[false; C8_WIDTH]; C8_HEIGHT]);
+        assert_eq!(C8_HEIGHT, text.lines().count());
+        assert!(text.lines().all(|line| line.len() == C8_WIDTH));
+    }
+}
@@ -0,0 +1,181 @@
+use alloc::string::String;
+use core::fmt;
+
+/// Errors raised while executing instructions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuError {
+    /// Strict mode only. The program counter left the loaded program, at the contained address
+    RanPastProgram(u16),
+    /// An instruction accessed memory past the end of the address space, at the contained address. Only raised with
+    /// `MemoryModel::Error`
+    MemoryOutOfBounds(usize),
+    /// `CALL` with a full stack, at the contained address. Ignored in safe mode unless quiet mode reports it
+    StackOverflow(u16),
+    /// `RET` with an empty stack, at the contained address. Ignored in safe mode unless quiet mode reports it
+    StackUnderflow(u16),
+    /// The contained opcode is not a Chip-8 instruction. Ignored in safe mode unless quiet mode reports it
+    UnknownOpcode(u16),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CpuError::RanPastProgram(pc) => write!(f, "ran past end of program at {:#05X}", pc),
+            CpuError::MemoryOutOfBounds(addr) => {
+                write!(f, "memory access out of bounds at {:#05X}", addr)
+            }
+            CpuError::StackOverflow(pc) => write!(f, "stack overflow at {:#05X}", pc),
+            CpuError::StackUnderflow(pc) => write!(f, "return with an empty stack at {:#05X}", pc),
+            CpuError::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04X}", opcode),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CpuError {}
+
+/// Errors raised while initializing a `Cpu`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitError {
+    /// The fontset in memory differs from the built-in fontset, at the contained address
+    FontsetMismatch(usize),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InitError::FontsetMismatch(addr) => write!(f, "fontset mismatch at {:#05X}", addr),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InitError {}
+
+/// Errors raised while loading a ROM
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM file does not exist
+    NotFound,
+    /// The ROM file could not be read
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// The ROM, of the contained size in bytes, does not fit in the 3584 bytes of memory from 0x200 to 0xFFF
+    TooLarge(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::NotFound => write!(f, "file not found"),
+            #[cfg(feature = "std")]
+            RomError::Io(ref e) => write!(f, "unable to read the file: {}", e),
+            RomError::TooLarge(size) => write!(
+                f,
+                "ROM is {} bytes, larger than the 3584 bytes available",
+                size
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            RomError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for RomError {
+    fn from(e: std::io::Error) -> RomError {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            RomError::NotFound
+        } else {
+            RomError::Io(e)
+        }
+    }
+}
+
+/// Errors raised while assembling a program. Each contains the line of source it was raised on, starting at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    /// The contained mnemonic is not an instruction or directive
+    UnknownMnemonic(usize, String),
+    /// The operands do not fit the contained mnemonic, e.g. `LD DT, 5`
+    InvalidOperands(usize, String),
+    /// The contained number does not fit its operand, e.g. a byte over 0xFF
+    OutOfRange(usize, String),
+    /// The contained label is not a letter or underscore followed by letters, digits and underscores
+    InvalidLabel(usize, String),
+    /// The contained label was already defined
+    DuplicateLabel(usize, String),
+    /// The contained label is used but never defined
+    UndefinedLabel(usize, String),
+    /// The program runs past the end of memory
+    TooLarge(usize),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsmError::UnknownMnemonic(line, ref mnemonic) => {
+                write!(f, "line {}: unknown mnemonic {}", line, mnemonic)
+            }
+            AsmError::InvalidOperands(line, ref mnemonic) => {
+                write!(f, "line {}: invalid operands for {}", line, mnemonic)
+            }
+            AsmError::OutOfRange(line, ref value) => {
+                write!(f, "line {}: {} is out of range", line, value)
+            }
+            AsmError::InvalidLabel(line, ref label) => {
+                write!(f, "line {}: invalid label {:?}", line, label)
+            }
+            AsmError::DuplicateLabel(line, ref label) => {
+                write!(f, "line {}: label {} is already defined", line, label)
+            }
+            AsmError::UndefinedLabel(line, ref label) => {
+                write!(f, "line {}: undefined label {}", line, label)
+            }
+            AsmError::TooLarge(line) => write!(
+                f,
+                "line {}: program is larger than the 3584 bytes available",
+                line
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AsmError {}
+
+/// Errors raised while loading a save state
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data is not a save state, e.g. it was truncated. Contains a description of the decoding error
+    Corrupt(String),
+    /// The save state was written in the contained, incompatible format version
+    Version(u8),
+    /// The save state decoded, but describes an impossible machine, e.g. a stack pointer past the end of the stack
+    Invalid,
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::Corrupt(ref e) => write!(f, "corrupt save state: {}", e),
+            StateError::Version(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::Invalid => write!(f, "invalid save state"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for StateError {}
@@ -0,0 +1,220 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use chip8::{C8_HEIGHT, C8_WIDTH};
+
+/// Size of a low resolution Chip-8 pixel in GIF pixels. High resolution pixels are half as large, so both modes fill
+/// the same image
+const SCALE: usize = 4;
+/// Colors of the recording, the same as the window: dark pixels are black, lit pixels white
+const PALETTE: [u8; 6] = [0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF];
+/// Number of video frames per second the recording is synchronized to
+const FRAMES_PER_SECOND: u64 = 60;
+/// Shortest frame delay written, in centiseconds. Most viewers play shorter delays far slower, so a display that
+/// changes faster only records its latest frame
+const MIN_DELAY: u64 = 2;
+
+/// Records the display into an animated GIF file. One frame is passed per call to `record_frame`, at 60hz. Frames are
+/// streamed to the file as they end, so the memory use does not grow with the length of the recording, and a display
+/// that does not change is written once with a longer delay.
+pub struct GifRecorder {
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    /// Last distinct frame as palette indices, written once the display changes or the recording stops
+    pending: Option<Vec<u8>>,
+    /// Number of 60hz frames recorded since the recording started
+    frames: u64,
+    /// Total delay of the frames written so far, in centiseconds since the recording started
+    written: u64,
+}
+
+impl GifRecorder {
+    /// Returns an idle recorder
+    pub fn new() -> GifRecorder {
+        GifRecorder {
+            encoder: None,
+            pending: None,
+            frames: 0,
+            written: 0,
+        }
+    }
+
+    /// Check whether a recording is in progress
+    pub fn is_recording(&self) -> bool {
+        self.encoder.is_some()
+    }
+
+    /// Start recording to a new GIF file, finishing any recording already in progress. The animation loops forever.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the GIF file to create
+    pub fn start<P: AsRef<Path>>(&mut self, path: P) -> Result<(), gif::EncodingError> {
+        self.stop()?;
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(
+            file,
+            (C8_WIDTH * SCALE) as u16,
+            (C8_HEIGHT * SCALE) as u16,
+            &PALETTE,
+        )?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        self.encoder = Some(encoder);
+        self.pending = None;
+        self.frames = 0;
+        self.written = 0;
+        Ok(())
+    }
+
+    /// Write the last frame and finish the GIF file. Does nothing if no recording is in progress.
+    pub fn stop(&mut self) -> Result<(), gif::EncodingError> {
+        self.write_pending()?;
+        match self.encoder.take() {
+            Some(encoder) => Ok(encoder.into_inner()?.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Append one 60hz frame of the display. Does nothing if no recording is in progress. The recording is abandoned
+    /// if writing fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `display` - Display to record, at any resolution
+    pub fn record_frame<R: AsRef<[bool]>>(
+        &mut self,
+        display: &[R],
+    ) -> Result<(), gif::EncodingError> {
+        if self.encoder.is_none() {
+            return Ok(());
+        }
+        let image = indices(display);
+        if self.pending.as_ref() != Some(&image) {
+            if self.elapsed() - self.written >= MIN_DELAY {
+                if let Err(e) = self.write_pending() {
+                    self.encoder = None;
+                    return Err(e);
+                }
+            }
+            self.pending = Some(image);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Returns the time since the recording started, in centiseconds
+    fn elapsed(&self) -> u64 {
+        self.frames * 100 / FRAMES_PER_SECOND
+    }
+
+    /// Write the pending frame, lasting until now
+    fn write_pending(&mut self) -> Result<(), gif::EncodingError> {
+        let elapsed = self.elapsed();
+        let (encoder, image) = match (self.encoder.as_mut(), self.pending.take()) {
+            (Some(encoder), Some(image)) => (encoder, image),
+            _ => return Ok(()),
+        };
+        let frame = gif::Frame {
+            width: (C8_WIDTH * SCALE) as u16,
+            height: (C8_HEIGHT * SCALE) as u16,
+            delay: (elapsed - self.written).min(u16::MAX as u64) as u16,
+            buffer: Cow::Owned(image),
+            ..gif::Frame::default()
+        };
+        self.written = elapsed;
+        encoder.write_frame(&frame)
+    }
+}
+
+/// Scale a Chip-8 display up to the size of the recording, one palette index per pixel in row major order
+///
+/// # Arguments
+///
+/// * `display` - Display to scale, 64x32 or 128x64
+fn indices<R: AsRef<[bool]>>(display: &[R]) -> Vec<u8> {
+    let width = display.first().map_or(C8_WIDTH, |row| row.as_ref().len());
+    let scale = SCALE * C8_WIDTH / width.max(1);
+    let mut image = Vec::with_capacity(C8_WIDTH * C8_HEIGHT * SCALE * SCALE);
+    for row in display.iter() {
+        let line: Vec<u8> = row
+            .as_ref()
+            .iter()
+            .flat_map(|&lit| std::iter::repeat_n(lit as u8, scale))
+            .collect();
+        for _ in 0..scale {
+            image.extend_from_slice(&line);
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{HIRES_HEIGHT, HIRES_WIDTH};
+
+    /// Decode a GIF file into its frames as (delay, palette indices)
+    fn decode<P: AsRef<Path>>(path: P) -> Vec<(u16, Vec<u8>)> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        assert_eq!(
+            (C8_WIDTH * SCALE, C8_HEIGHT * SCALE),
+            (decoder.width() as usize, decoder.height() as usize)
+        );
+        assert_eq!(Some(&PALETTE[..]), decoder.global_palette());
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        frames
+    }
+
+    #[test]
+    fn test_record_gif() {
+        let path = std::env::temp_dir().join("chip8-test-recording.gif");
+        let dark = [[false; C8_WIDTH]; C8_HEIGHT];
+        let mut lit = dark;
+        lit[0][1] = true;
+        let mut hires = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        for row in hires.iter_mut().take(2) {
+            row[2..4].copy_from_slice(&[true, true]);
+        }
+
+        let mut recorder = GifRecorder::new();
+        recorder.record_frame(&dark).unwrap();
+        assert!(!recorder.is_recording());
+        recorder.start(&path).unwrap();
+        assert!(recorder.is_recording());
+        // Half a second each of the dark and the lit display
+        for _ in 0..30 {
+            recorder.record_frame(&dark).unwrap();
+        }
+        for _ in 0..30 {
+            recorder.record_frame(&lit).unwrap();
+        }
+        // Flicker faster than the shortest delay only keeps the last frame
+        recorder.record_frame(&dark).unwrap();
+        recorder.record_frame(&hires).unwrap();
+        recorder.stop().unwrap();
+        assert!(!recorder.is_recording());
+
+        let frames = decode(&path);
+        assert_eq!(
+            vec![50, 50, 3],
+            frames.iter().map(|f| f.0).collect::<Vec<u16>>()
+        );
+        assert_eq!(vec![0; C8_WIDTH * C8_HEIGHT * SCALE * SCALE], frames[0].1);
+        // The lit pixel covers columns 4 to 7 of the first 4 rows
+        let row = C8_WIDTH * SCALE;
+        assert_eq!([0, 0, 0, 0, 1, 1, 1, 1, 0], frames[1].1[0..9]);
+        assert_eq!(
+            [0, 0, 0, 0, 1, 1, 1, 1, 0],
+            frames[1].1[3 * row..3 * row + 9]
+        );
+        assert_eq!(0, frames[1].1[4 * row + 4]);
+        // High resolution pixels are half as large, so two by two of them cover the same area
+        assert_eq!(frames[1].1, frames[2].1);
+    }
+}
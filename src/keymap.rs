@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use ggez::event::KeyCode;
+
+/// Mapping from keyboard keys to the index of the Chip-8 key they press
+pub type KeyMap = HashMap<KeyCode, usize>;
+
+/// Default layout, the left side of a QWERTY keyboard standing in for the hex keypad
+///
+///  1 2 3 4    1 2 3 C
+///  q w e r -> 4 5 6 D
+///  a s d f    7 8 9 E
+///  z x c v    A 0 B F
+const DEFAULT_LAYOUT: [(KeyCode, usize); 16] = [
+    (KeyCode::Key1, 1),
+    (KeyCode::Key2, 2),
+    (KeyCode::Key3, 3),
+    (KeyCode::Key4, 0xC),
+    (KeyCode::Q, 4),
+    (KeyCode::W, 5),
+    (KeyCode::E, 6),
+    (KeyCode::R, 0xD),
+    (KeyCode::A, 7),
+    (KeyCode::S, 8),
+    (KeyCode::D, 9),
+    (KeyCode::F, 0xE),
+    (KeyCode::Z, 0xA),
+    (KeyCode::X, 0),
+    (KeyCode::C, 0xB),
+    (KeyCode::V, 0xF),
+];
+
+/// Letter keys, in alphabetical order
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+/// Digit keys of the main keyboard, from 0 to 9
+const DIGITS: [KeyCode; 10] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// Digit keys of the numeric keypad, from 0 to 9
+const NUMPAD: [KeyCode; 10] = [
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+];
+
+/// Returns the default key map, see `DEFAULT_LAYOUT`
+pub fn default_keymap() -> KeyMap {
+    DEFAULT_LAYOUT.iter().copied().collect()
+}
+
+/// Parse the name of a keyboard key, case insensitive: a letter, a digit, `numpad0` to `numpad9`, an arrow (`up`,
+/// `down`, `left`, `right`), `space` or one of `comma`, `period`, `semicolon`, `slash`, `minus`, `equals`,
+/// `apostrophe`, `backslash` and `grave`
+///
+/// # Arguments
+///
+/// * `name` - Name of the key
+fn parse_keycode(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return match c {
+            'a'..='z' => Some(LETTERS[c as usize - 'a' as usize]),
+            '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+            _ => None,
+        };
+    }
+    if let Some(digit) = name.strip_prefix("numpad") {
+        return digit
+            .parse::<usize>()
+            .ok()
+            .and_then(|digit| NUMPAD.get(digit).copied());
+    }
+    let keycode = match name.as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "space" => KeyCode::Space,
+        "comma" => KeyCode::Comma,
+        "period" => KeyCode::Period,
+        "semicolon" => KeyCode::Semicolon,
+        "slash" => KeyCode::Slash,
+        "minus" => KeyCode::Minus,
+        "equals" => KeyCode::Equals,
+        "apostrophe" => KeyCode::Apostrophe,
+        "backslash" => KeyCode::Backslash,
+        "grave" => KeyCode::Grave,
+        _ => return None,
+    };
+    Some(keycode)
+}
+
+/// Parse a single mapping of the form `<key>=<chip-8 key>`, e.g. `i=5`, where the Chip-8 key is a hex digit. The
+/// Chip-8 key `none` unmaps the keyboard key instead.
+///
+/// # Arguments
+///
+/// * `mapping` - Mapping to parse, spaces around the `=` are allowed
+///
+/// # Returns
+///
+/// * `mapping` - The keyboard key, and the Chip-8 key it presses or None to unmap it
+pub fn parse_mapping(mapping: &str) -> Result<(KeyCode, Option<usize>), String> {
+    let (key, chip8_key) = mapping
+        .split_once('=')
+        .ok_or(format!("expected <key>=<chip-8 key>, got {}", mapping))?;
+    let (key, chip8_key) = (key.trim(), chip8_key.trim());
+    let keycode = parse_keycode(key).ok_or(format!("unknown key: {}", key))?;
+    if chip8_key.eq_ignore_ascii_case("none") {
+        return Ok((keycode, None));
+    }
+    match usize::from_str_radix(chip8_key, 16) {
+        Ok(idx) if idx < 16 && chip8_key.len() == 1 => Ok((keycode, Some(idx))),
+        _ => Err(format!(
+            "Chip-8 keys are hex digits from 0 to F, got {}",
+            chip8_key
+        )),
+    }
+}
+
+/// Apply a mapping returned by `parse_mapping` to a key map
+///
+/// # Arguments
+///
+/// * `keymap` - Key map to change
+/// * `mapping` - Keyboard key and the Chip-8 key it presses, or None to unmap it
+pub fn apply_mapping(keymap: &mut KeyMap, (keycode, idx): (KeyCode, Option<usize>)) {
+    match idx {
+        Some(idx) => keymap.insert(keycode, idx),
+        None => keymap.remove(&keycode),
+    };
+}
+
+/// Apply a key map file on top of a key map. The file holds one mapping per line in the format of `parse_mapping`.
+/// Blank lines and lines starting with `#` are ignored. Keys the file does not mention keep their mapping, e.g. an
+/// AZERTY layout only swaps a few keys:
+///
+/// ```text
+/// # AZERTY
+/// a=4
+/// z=5
+/// q=7
+/// w=A
+/// ```
+///
+/// # Arguments
+///
+/// * `keymap` - Key map to change
+/// * `text` - Contents of the file
+pub fn apply_file(keymap: &mut KeyMap, text: &str) -> Result<(), String> {
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mapping = parse_mapping(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        apply_mapping(keymap, mapping);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = default_keymap();
+        assert_eq!(16, keymap.len());
+        let mut indices: Vec<usize> = keymap.values().copied().collect();
+        indices.sort_unstable();
+        assert_eq!((0..16).collect::<Vec<usize>>(), indices);
+        assert_eq!(Some(&0xC), keymap.get(&KeyCode::Key4));
+        assert_eq!(Some(&0), keymap.get(&KeyCode::X));
+    }
+
+    #[test]
+    fn test_parse_mapping() {
+        assert_eq!(Ok((KeyCode::I, Some(5))), parse_mapping("i=5"));
+        assert_eq!(Ok((KeyCode::Up, Some(0xA))), parse_mapping(" UP = a "));
+        assert_eq!(Ok((KeyCode::Numpad7, Some(1))), parse_mapping("numpad7=1"));
+        assert_eq!(Ok((KeyCode::Key0, None)), parse_mapping("0=none"));
+        assert!(parse_mapping("i").is_err());
+        assert!(parse_mapping("i=10").is_err());
+        assert!(parse_mapping("i=g").is_err());
+        assert!(parse_mapping("numpad10=1").is_err());
+        assert!(parse_mapping("f13=1").is_err());
+    }
+
+    #[test]
+    fn test_apply_file() {
+        let mut keymap = default_keymap();
+        apply_file(&mut keymap, "# AZERTY\n\na=4\nz = 5\nq=7\nw=A\nx=none\n").unwrap();
+        assert_eq!(Some(&4), keymap.get(&KeyCode::A));
+        assert_eq!(Some(&5), keymap.get(&KeyCode::Z));
+        assert_eq!(Some(&7), keymap.get(&KeyCode::Q));
+        assert_eq!(Some(&0xA), keymap.get(&KeyCode::W));
+        assert_eq!(None, keymap.get(&KeyCode::X));
+        assert_eq!(Some(&0xF), keymap.get(&KeyCode::V));
+
+        assert_eq!(
+            Err("line 2: unknown key: kp".to_owned()),
+            apply_file(&mut keymap, "a=4\nkp=1\n")
+        );
+    }
+}
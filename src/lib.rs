@@ -0,0 +1,33 @@
+#![warn(clippy::all)]
+#![cfg_attr(not(feature = "std"), no_std)]
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
+#[cfg(feature = "serde")]
+extern crate ciborium;
+#[cfg(feature = "std")]
+extern crate core;
+extern crate rand;
+extern crate rand_chacha;
+#[cfg(feature = "serde")]
+extern crate serde;
+extern crate sha1_smol;
+
+pub mod asm;
+pub mod audio;
+pub mod cpu;
+pub mod debug;
+pub mod disasm;
+pub mod display;
+pub mod error;
+
+pub use audio::AudioSink;
+pub use cpu::{
+    ClipMode, Cpu, CpuState, CycleCosts, Instruction, KeyWaitModel, LoadOptions, LoadReport,
+    MemoryModel, Profile, QuietPolicy, Quirk, Quirks, StepResult, C8_HEIGHT, C8_WIDTH,
+    HIRES_HEIGHT, HIRES_WIDTH,
+};
+pub use debug::{Breakpoint, Watchpoint};
+pub use display::{display_diff, display_diff_pixels, display_to_ascii, DisplaySink};
+#[cfg(feature = "serde")]
+pub use error::StateError;
+pub use error::{AsmError, CpuError, InitError, RomError};
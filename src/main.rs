@@ -1,88 +1,1600 @@
 #![warn(clippy::all)]
-mod cpu;
-
+extern crate chip8;
 extern crate ggez;
-extern crate rand;
+extern crate gif;
+extern crate hound;
+extern crate png;
+extern crate serde_json;
+
+mod beep;
+mod compat;
+mod gif_recorder;
+mod keymap;
+mod repl;
+mod screenshot;
+mod wav;
 
-use std::time::{Duration, Instant};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use ggez::event::{self, KeyCode, KeyMods};
+use ggez::graphics::spritebatch::SpriteBatch;
 use ggez::graphics::{self, Color};
+use ggez::mint::Point2;
 use ggez::{Context, GameResult};
 
+use chip8::{asm, cpu};
+use chip8::{CpuError, CycleCosts, DisplaySink, Profile, Quirk, RomError, StateError};
+
+use compat::CompatDb;
+use keymap::KeyMap;
+
+/// Default size of a Chip-8 pixel, in window pixels
 const PIXEL_SIZE: usize = 10;
-const SCREEN_WIDTH: usize = cpu::C8_WIDTH * PIXEL_SIZE;
-const SCREEN_HEIGHT: usize = cpu::C8_HEIGHT * PIXEL_SIZE;
 
-const MS_PER_UPDATE: u64 = 2_u64; // 500hz suggested cycle rate
+/// Default number of instructions per second, the suggested cycle rate
+const DEFAULT_CYCLE_HZ: u32 = 500;
+/// Slowest cycle rate the speed keys go down to
+const MIN_CYCLE_HZ: u32 = 60;
+/// Fastest cycle rate the speed keys go up to
+const MAX_CYCLE_HZ: u32 = 4000;
+
+/// Time without a display update after which an indicator is shown, so ROMs that never draw don't look hung
+const IDLE_INDICATOR_DELAY: Duration = Duration::from_secs(3);
+
+/// How long a notice, e.g. after changing a display setting, stays on screen
+const NOTICE_DURATION: Duration = Duration::from_millis(1500);
+
+/// Number of CPU ticks per 60hz frame when running headless
+const TICKS_PER_FRAME: u32 = 8;
+
+/// Default color of the pixel grid overlay, faint enough to leave the display readable
+const DEFAULT_GRID_COLOR: Color = Color::new(0.19, 0.19, 0.19, 1.0);
+
+/// Duration of a single 60hz video frame
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Most 60hz frames the display bookkeeping catches up on in one draw
+const MAX_CATCH_UP_FRAMES: u32 = 60;
+/// Number of instructions kept for the post-mortem dump when the CPU stops with an error
+const INSTRUCTION_LOG_LEN: usize = 256;
+/// Sample rate used for audio output and recordings
+const SAMPLE_RATE: u32 = 44_100;
+/// Number of 60hz frames of history kept for rewinding, ten seconds
+const REWIND_FRAMES: usize = 600;
+
+/// Command line usage summary
+const USAGE: &str =
+    "Usage: chip-8 <rom | --rom <path>> [--speed <hz>] [--render-hz <n>] [--border <px>] [--border-color <RRGGBB>] \
+[--scale <n>] [--renderer <batch|pixels>] [--bench-render <frames>] [--seed <n>] \
+[--persistence <off|blend[:frames]|decay[:frames]>] [--no-ghosting] [--draw-throttle] [--watchdog <instructions>] \
+[--frames <n>] [--png <path>] [--debug] [--profile|--quirks <vip|schip|xo-chip>] [--compat-db <path>] [--grid] \
+[--grid-color <RRGGBB>] [--keymap <path>] [--key <key>=<chip-8 key>]...";
+
+/// How the lit pixels are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Renderer {
+    /// All pixels are collected into a single sprite batch, drawn with one draw call. This is the default
+    Batch,
+    /// One draw call per lit pixel. Kept for comparison with the batch renderer
+    Pixels,
+}
+
+/// Frontend options parsed from the command line
+struct Options {
+    /// Path to the ROM file
+    rom: String,
+    /// Number of instructions per second in the window
+    speed: u32,
+    /// Rate at which frames are presented, independent of the CPU rate. Every draw callback presents when None
+    render_hz: Option<u32>,
+    /// Width of the border around the display area, in window pixels
+    border: u32,
+    /// Color of the border
+    border_color: Color,
+    /// Size of a Chip-8 pixel, in window pixels
+    scale: usize,
+    /// How the lit pixels are drawn
+    renderer: Renderer,
+    /// Number of frames to render in the render benchmark. The ROM is optional and not run when set
+    bench_frames: Option<u32>,
+    /// Seed for the random number generator. Seeded from entropy when None
+    seed: Option<u64>,
+    /// How long pixels linger after being switched off
+    persistence: Persistence,
+    /// Whether to run fewer instructions in frames with a lot of drawing
+    draw_throttle: bool,
+    /// Number of instructions without a display update after which the ROM is reset. Disabled when None
+    watchdog: Option<u64>,
+    /// Number of 60hz frames to run headless before saving a screenshot and exiting. Runs interactively when None
+    frames: Option<u32>,
+    /// Path of the screenshot saved after running headless
+    png: Option<String>,
+    /// Whether to run the command line debugger on stdin instead of opening a window
+    debug: bool,
+    /// Quirks profile to run the ROM with. Looked up in the compatibility database when None
+    profile: Option<Profile>,
+    /// Path of a compatibility database with entries taking priority over the bundled ones
+    compat_db: Option<String>,
+    /// Whether the pixel grid overlay is shown from the start
+    grid: bool,
+    /// Color of the pixel grid overlay
+    grid_color: Color,
+    /// Path of a key map file applied on top of the default layout
+    keymap: Option<String>,
+    /// Key mappings given with `--key`, applied after the key map file
+    keys: Vec<(KeyCode, Option<usize>)>,
+}
+
+impl Options {
+    /// Parse the command line arguments, excluding the program name. See `USAGE` for the accepted arguments.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Command line arguments following the program name
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut rom = None;
+        let mut speed = DEFAULT_CYCLE_HZ;
+        let mut render_hz = None;
+        let mut border = 0;
+        let mut border_color = Color::BLACK;
+        let mut scale = PIXEL_SIZE;
+        let mut renderer = Renderer::Batch;
+        let mut bench_frames = None;
+        let mut seed = None;
+        let mut persistence = Persistence::Blend(DEFAULT_BLEND_DEPTH);
+        let mut draw_throttle = false;
+        let mut watchdog = None;
+        let mut frames = None;
+        let mut png = None;
+        let mut debug = false;
+        let mut profile = None;
+        let mut compat_db = None;
+        let mut grid = false;
+        let mut grid_color = DEFAULT_GRID_COLOR;
+        let mut keymap = None;
+        let mut keys = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rom" => {
+                    if rom.is_some() {
+                        return Err("Only one ROM can be given".to_owned());
+                    }
+                    rom = Some(args.next().ok_or("--rom expects a path")?);
+                }
+                "--speed" => {
+                    speed = args
+                        .next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .filter(|hz| (MIN_CYCLE_HZ..=MAX_CYCLE_HZ).contains(hz))
+                        .ok_or(format!(
+                            "--speed expects a rate between {} and {} instructions per second",
+                            MIN_CYCLE_HZ, MAX_CYCLE_HZ
+                        ))?;
+                }
+                "--render-hz" => {
+                    let hz = args
+                        .next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .filter(|&hz| hz > 0)
+                        .ok_or("--render-hz expects a positive number")?;
+                    render_hz = Some(hz);
+                }
+                "--border" => {
+                    border = args
+                        .next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .ok_or("--border expects a width in pixels")?;
+                }
+                "--border-color" => {
+                    border_color = args
+                        .next()
+                        .and_then(|v| parse_color(&v))
+                        .ok_or("--border-color expects a hex color such as 202020")?;
+                }
+                "--scale" => {
+                    scale = args
+                        .next()
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .filter(|&scale| scale > 0)
+                        .ok_or("--scale expects a positive number")?;
+                }
+                "--renderer" => {
+                    renderer = match args.next().as_deref() {
+                        Some("batch") => Renderer::Batch,
+                        Some("pixels") => Renderer::Pixels,
+                        _ => return Err("--renderer expects batch or pixels".to_owned()),
+                    };
+                }
+                "--bench-render" => {
+                    let frames = args
+                        .next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .filter(|&frames| frames > 0)
+                        .ok_or("--bench-render expects a positive number of frames")?;
+                    bench_frames = Some(frames);
+                }
+                "--seed" => {
+                    let value = args
+                        .next()
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .ok_or("--seed expects a number")?;
+                    seed = Some(value);
+                }
+                "--persistence" => {
+                    persistence = args.next().and_then(|v| parse_persistence(&v)).ok_or(
+                        "--persistence expects off, blend, blend:<frames>, decay or decay:<frames>",
+                    )?;
+                }
+                "--no-ghosting" => persistence = Persistence::Off,
+                "--draw-throttle" => draw_throttle = true,
+                "--watchdog" => {
+                    let threshold = args
+                        .next()
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .filter(|&threshold| threshold > 0)
+                        .ok_or("--watchdog expects a positive number of instructions")?;
+                    watchdog = Some(threshold);
+                }
+                "--frames" => {
+                    let count = args
+                        .next()
+                        .and_then(|v| v.parse::<u32>().ok())
+                        .ok_or("--frames expects a number of frames")?;
+                    frames = Some(count);
+                }
+                "--png" => png = Some(args.next().ok_or("--png expects a path")?),
+                "--debug" => debug = true,
+                "--profile" | "--quirks" => {
+                    let value = args
+                        .next()
+                        .and_then(|v| Profile::from_name(&v))
+                        .ok_or(format!("{} expects vip, schip or xo-chip", arg))?;
+                    profile = Some(value);
+                }
+                "--grid" => grid = true,
+                "--grid-color" => {
+                    grid_color = args
+                        .next()
+                        .and_then(|v| parse_color(&v))
+                        .ok_or("--grid-color expects a hex color such as 303030")?;
+                }
+                "--keymap" => keymap = Some(args.next().ok_or("--keymap expects a path")?),
+                "--key" => {
+                    let mapping = args
+                        .next()
+                        .ok_or("--key expects <key>=<chip-8 key>")
+                        .map_err(str::to_owned)
+                        .and_then(|v| keymap::parse_mapping(&v))
+                        .map_err(|e| format!("--key: {}", e))?;
+                    keys.push(mapping);
+                }
+                "--compat-db" => {
+                    compat_db = Some(args.next().ok_or("--compat-db expects a path")?);
+                }
+                _ if rom.is_none() && !arg.starts_with("--") => rom = Some(arg),
+                _ => return Err(format!("Unexpected argument: {}\n{}", arg, USAGE)),
+            }
+        }
+        let rom = match (rom, bench_frames) {
+            (Some(rom), _) => rom,
+            (None, Some(_)) => String::new(),
+            (None, None) => return Err(format!("No ROM given\n{}", USAGE)),
+        };
+        Ok(Options {
+            rom,
+            speed,
+            render_hz,
+            border,
+            border_color,
+            scale,
+            renderer,
+            bench_frames,
+            seed,
+            persistence,
+            draw_throttle,
+            watchdog,
+            frames,
+            png,
+            debug,
+            profile,
+            compat_db,
+            grid,
+            grid_color,
+            keymap,
+            keys,
+        })
+    }
+
+    /// Build the key map: the default layout, changed by the key map file and then by the `--key` mappings. Hotkeys
+    /// cannot be mapped to Chip-8 keys.
+    fn keymap(&self) -> Result<KeyMap, String> {
+        let mut keymap = keymap::default_keymap();
+        if let Some(path) = self.keymap.as_ref() {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            keymap::apply_file(&mut keymap, &text).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        for &mapping in self.keys.iter() {
+            keymap::apply_mapping(&mut keymap, mapping);
+        }
+        if let Some(keycode) = keymap
+            .keys()
+            .find(|&&keycode| hotkey_action(keycode, KeyMods::NONE).is_some())
+        {
+            return Err(format!(
+                "{:?} is a hotkey and cannot press a Chip-8 key",
+                keycode
+            ));
+        }
+        Ok(keymap)
+    }
+}
+
+/// Parse a color in `RRGGBB` hex notation, with an optional leading `#`
+///
+/// # Arguments
+///
+/// * `hex` - Color to parse
+fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::from_rgb(
+        (rgb >> 16) as u8,
+        (rgb >> 8) as u8,
+        rgb as u8,
+    ))
+}
+
+/// How long pixels linger after being switched off. Chip-8 games erase and redraw sprites with XOR, so moving sprites
+/// flicker unless the display keeps them visible for a while, like the phosphor of the original CRT did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Persistence {
+    /// Only the current frame is shown. Sharpest image, but all flicker is visible
+    Off,
+    /// Logical or of the contained number of most recent frames. Hides most flicker, but leaves full brightness
+    /// trails behind moving sprites. This is the default, over three frames
+    Blend(usize),
+    /// Pixels fade out exponentially over the contained number of frames. Smoothest motion, at the cost of
+    /// rendering every frame while anything is fading
+    Decay(u32),
+}
+
+/// Brightness below which a fading pixel is switched off
+const FADE_CUTOFF: f32 = 0.05;
+/// Number of frames a pixel fades over when `--persistence decay` is given without a count
+const DEFAULT_DECAY_FRAMES: u32 = 6;
+/// Number of frames blended when `--persistence blend` is given without a count
+const DEFAULT_BLEND_DEPTH: usize = 3;
+/// Largest number of frames that can be blended
+const MAX_BLEND_DEPTH: usize = 8;
+
+/// Parse a persistence mode: `off`, `blend`, `blend:<frames>`, `decay` or `decay:<frames>`
+///
+/// # Arguments
+///
+/// * `mode` - Mode to parse
+fn parse_persistence(mode: &str) -> Option<Persistence> {
+    match mode {
+        "off" => Some(Persistence::Off),
+        "blend" => Some(Persistence::Blend(DEFAULT_BLEND_DEPTH)),
+        "decay" => Some(Persistence::Decay(DEFAULT_DECAY_FRAMES)),
+        _ => {
+            if let Some(depth) = mode.strip_prefix("blend:") {
+                return depth
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|depth| (1..=MAX_BLEND_DEPTH).contains(depth))
+                    .map(Persistence::Blend);
+            }
+            mode.strip_prefix("decay:")
+                .and_then(|frames| frames.parse::<u32>().ok())
+                .filter(|&frames| frames > 0)
+                .map(Persistence::Decay)
+        }
+    }
+}
+
+/// Plane mask of `FrameHistory::visible_planes` showing both display planes, the default
+const BOTH_PLANES: u8 = 0b11;
+
+/// A single Chip-8 frame, large enough for the high resolution mode. Low resolution frames use the top left corner
+type Frame = [[bool; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT];
+
+/// Display sink for the ggez frontend. Keeps the frames blended in the blend mode and a per pixel brightness for the
+/// decay persistence mode
+struct FrameHistory {
+    persistence: Persistence,
+    /// Most recent frames, oldest first. Holds as many frames as are blended, and only the current frame in the other
+    /// modes
+    last_frames: Vec<Frame>,
+    /// Brightness of each pixel in the decay mode, from 0 (off) to 1 (lit)
+    brightness: [[f32; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT],
+    /// Resolution of the stored frames as (width, height)
+    resolution: (usize, usize),
+    /// Number of times the frames were aged since the last presented frame. Once every older frame is a copy of the
+    /// current one the blend is settled and aging changes nothing
+    aged: usize,
+    /// Display planes shown by `present_planes`, bit 0 for the first plane and bit 1 for the second
+    visible_planes: u8,
+}
+
+impl FrameHistory {
+    fn new(persistence: Persistence) -> FrameHistory {
+        let depth = match persistence {
+            Persistence::Blend(depth) => depth,
+            _ => 1,
+        };
+        FrameHistory {
+            persistence,
+            last_frames: vec![[[false; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT]; depth],
+            brightness: [[0.0; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT],
+            resolution: (cpu::C8_WIDTH, cpu::C8_HEIGHT),
+            aged: 0,
+            visible_planes: BOTH_PLANES,
+        }
+    }
+
+    /// Returns the size of a pixel of the stored frames in window pixels. High resolution pixels are half the size of
+    /// low resolution ones, so the display area stays the same.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - Size of a low resolution Chip-8 pixel, in window pixels
+    fn pixel_size(&self, scale: usize) -> f32 {
+        (cpu::C8_WIDTH * scale) as f32 / self.resolution.0 as f32
+    }
+
+    /// Returns the number of frames blended, or None outside the blend mode
+    fn blend_depth(&self) -> Option<usize> {
+        match self.persistence {
+            Persistence::Blend(depth) => Some(depth),
+            _ => None,
+        }
+    }
+
+    /// Change the number of blended frames, clamped to 1 through `MAX_BLEND_DEPTH`. Added frames are dark, removed
+    /// frames are the oldest. Does nothing outside the blend mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `depth` - Requested number of frames to blend
+    ///
+    /// # Returns
+    ///
+    /// * `depth` - The new number of blended frames, or None outside the blend mode
+    fn set_blend_depth(&mut self, depth: usize) -> Option<usize> {
+        self.blend_depth()?;
+        let depth = depth.clamp(1, MAX_BLEND_DEPTH);
+        let current = self.last_frames.len();
+        if depth < current {
+            self.last_frames.drain(..current - depth);
+        } else {
+            let dark = [[false; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT];
+            self.last_frames
+                .splice(0..0, std::iter::repeat_n(dark, depth - current));
+            self.aged = 0;
+        }
+        self.persistence = Persistence::Blend(depth);
+        Some(depth)
+    }
+
+    /// Switch to another persistence mode, keeping the current frame. Earlier frames and the fading brightness are
+    /// dropped, so pixels that are not lit in the current frame disappear immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `persistence` - New persistence mode
+    fn set_persistence(&mut self, persistence: Persistence) {
+        let current = *self.current();
+        *self = FrameHistory {
+            resolution: self.resolution,
+            visible_planes: self.visible_planes,
+            ..FrameHistory::new(persistence)
+        };
+        let len = self.last_frames.len();
+        self.last_frames[len - 1] = current;
+    }
+
+    /// Present the display from the colors of both planes, as given by `Cpu::view_planes`. A pixel is lit if it is lit
+    /// in any visible plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `planes` - Color of every pixel at the active resolution, indexed as `planes[y][x]`
+    fn present_planes(&mut self, planes: &[Vec<u8>]) {
+        let rows: Vec<Vec<bool>> = planes
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&color| color & self.visible_planes != 0)
+                    .collect()
+            })
+            .collect();
+        self.present(&rows.iter().map(|row| &row[..]).collect::<Vec<&[bool]>>());
+    }
+
+    /// Returns the current frame
+    fn current(&self) -> &Frame {
+        &self.last_frames[self.last_frames.len() - 1]
+    }
+
+    /// Age the stored frames by one draw when no new frame was presented
+    ///
+    /// # Returns
+    ///
+    /// * `changed` - False if the frames had already settled, in which case they are left alone
+    fn advance(&mut self) -> bool {
+        if self.aged + 1 >= self.last_frames.len() {
+            return false;
+        }
+        self.aged += 1;
+        let current = *self.current();
+        self.last_frames.remove(0);
+        self.last_frames.push(current);
+        true
+    }
+
+    /// Update the decay brightness for one rendered frame. Lit pixels are at full brightness, all others fade.
+    ///
+    /// # Returns
+    ///
+    /// * `fading` - True if any pixel is still fading and the frame has to be rendered again. Always false outside
+    ///   the decay mode
+    fn fade(&mut self) -> bool {
+        let frames = match self.persistence {
+            Persistence::Decay(frames) => frames,
+            _ => return false,
+        };
+        let factor = FADE_CUTOFF.powf(1.0 / frames as f32);
+        let mut fading = false;
+        let current = *self.current();
+        for (row, lit_row) in self.brightness.iter_mut().zip(current.iter()) {
+            for (pixel, &lit) in row.iter_mut().zip(lit_row.iter()) {
+                if lit {
+                    *pixel = 1.0;
+                } else if *pixel > 0.0 {
+                    *pixel *= factor;
+                    if *pixel < FADE_CUTOFF {
+                        *pixel = 0.0;
+                    }
+                    fading = true;
+                }
+            }
+        }
+        fading
+    }
+
+    /// Returns the brightness of the pixel at row `i`, column `j`, from 0 (off) to 1 (lit)
+    fn brightness(&self, i: usize, j: usize) -> f32 {
+        let lit = self.current()[i][j];
+        match self.persistence {
+            Persistence::Off => lit as u8 as f32,
+            Persistence::Blend(_) => self.last_frames.iter().any(|frame| frame[i][j]) as u8 as f32,
+            Persistence::Decay(_) if lit => 1.0,
+            Persistence::Decay(_) => self.brightness[i][j],
+        }
+    }
+}
+
+impl DisplaySink for FrameHistory {
+    fn present(&mut self, display: &[&[bool]]) {
+        let resolution = (display.first().map_or(0, |row| row.len()), display.len());
+        if resolution != self.resolution {
+            // Frames of the other resolution would blend or fade into the wrong pixels
+            *self = FrameHistory {
+                resolution,
+                visible_planes: self.visible_planes,
+                ..FrameHistory::new(self.persistence)
+            };
+        }
+        let len = self.last_frames.len();
+        for (row, pixels) in self.last_frames[len - 1].iter_mut().zip(display) {
+            row[..pixels.len()].copy_from_slice(pixels);
+        }
+        self.aged = 0;
+    }
+}
+
+/// Returns the window position of the top left corner and the brightness of every visible pixel, in row major order
+///
+/// # Arguments
+///
+/// * `frames` - Frame history to render
+/// * `scale` - Size of a low resolution Chip-8 pixel, in window pixels
+/// * `border` - Width of the border around the display area, in window pixels
+fn visible_pixels(frames: &FrameHistory, scale: usize, border: f32) -> Vec<(Point2<f32>, f32)> {
+    let size = frames.pixel_size(scale);
+    let (width, height) = frames.resolution;
+    let mut pixels = Vec::new();
+    for i in 0..height {
+        for j in 0..width {
+            let brightness = frames.brightness(i, j);
+            if brightness > 0.0 {
+                let dest = Point2 {
+                    x: j as f32 * size + border,
+                    y: i as f32 * size + border,
+                };
+                pixels.push((dest, brightness));
+            }
+        }
+    }
+    pixels
+}
+
+/// Returns the end points of the pixel grid overlay lines, at every boundary between two Chip-8 pixels. The outer
+/// edges of the display are left out.
+///
+/// # Arguments
+///
+/// * `resolution` - Resolution of the displayed frame as (width, height)
+/// * `scale` - Size of a low resolution Chip-8 pixel, in window pixels
+/// * `border` - Width of the border around the display area, in window pixels
+fn grid_lines(resolution: (usize, usize), scale: usize, border: f32) -> Vec<[Point2<f32>; 2]> {
+    let width = (cpu::C8_WIDTH * scale) as f32;
+    let height = (cpu::C8_HEIGHT * scale) as f32;
+    let size = width / resolution.0 as f32;
+    let vertical = (1..resolution.0).map(move |j| {
+        let x = j as f32 * size + border;
+        [
+            Point2 { x, y: border },
+            Point2 {
+                x,
+                y: border + height,
+            },
+        ]
+    });
+    let horizontal = (1..resolution.1).map(move |i| {
+        let y = i as f32 * size + border;
+        [
+            Point2 { x: border, y },
+            Point2 {
+                x: border + width,
+                y,
+            },
+        ]
+    });
+    vertical.chain(horizontal).collect()
+}
+
+/// Fixed 60hz clock for the timers and the display bookkeeping. The draw callback runs at the refresh rate of the
+/// monitor, so blending and fading per draw would leave shorter trails on a 144hz display than on a 60hz one.
+struct FrameClock {
+    /// Start of the next 60hz frame
+    next: Instant,
+}
+
+impl FrameClock {
+    /// Returns a clock whose first frame starts at `now`
+    fn new(now: Instant) -> FrameClock {
+        FrameClock { next: now }
+    }
+
+    /// Returns the number of 60hz frames that started since the last call, at most `MAX_CATCH_UP_FRAMES`. Frames
+    /// further behind, e.g. while the window was stalled, are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time
+    fn elapsed_frames(&mut self, now: Instant) -> u32 {
+        let mut frames = 0;
+        while now >= self.next {
+            self.next += FRAME_DURATION;
+            frames += 1;
+            if frames == MAX_CATCH_UP_FRAMES {
+                self.next = self.next.max(now + FRAME_DURATION);
+                break;
+            }
+        }
+        frames
+    }
+}
+
+/// Clock scheduling CPU ticks at the cycle rate. With vsync ggez calls `update` once per displayed frame, so every
+/// update runs all the ticks that came due since the previous one.
+struct CycleClock {
+    /// Time the next tick is due
+    next: Instant,
+    /// Time between two ticks
+    interval: Duration,
+}
+
+impl CycleClock {
+    /// Returns a clock running at `hz` ticks per second, with the first tick due at `now`
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time
+    /// * `hz` - Cycle rate
+    fn new(now: Instant, hz: u32) -> CycleClock {
+        CycleClock {
+            next: now,
+            interval: Duration::from_secs(1) / hz,
+        }
+    }
+
+    /// Returns the number of ticks that came due since the last call, at most a second worth. Ticks further behind,
+    /// e.g. while the window was stalled, are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time
+    fn due_ticks(&mut self, now: Instant) -> u32 {
+        let mut ticks = 0;
+        while now >= self.next {
+            self.next += self.interval;
+            ticks += 1;
+            if self.interval * ticks >= Duration::from_secs(1) {
+                self.next = self.next.max(now + self.interval);
+                break;
+            }
+        }
+        ticks
+    }
+}
+
+/// Returns the cycle rate after halving or doubling it with the speed keys, within `MIN_CYCLE_HZ` and
+/// `MAX_CYCLE_HZ`
+///
+/// # Arguments
+///
+/// * `hz` - Current cycle rate
+/// * `faster` - True to double the rate, false to halve it
+fn adjust_speed(hz: u32, faster: bool) -> u32 {
+    let hz = if faster { hz * 2 } else { hz / 2 };
+    hz.clamp(MIN_CYCLE_HZ, MAX_CYCLE_HZ)
+}
+
+/// Resets unattended ROMs that got stuck, by counting instructions since the last display update
+struct Watchdog {
+    /// Number of instructions without a display update that trigger a reset
+    threshold: u64,
+    /// Instructions executed since the last display update
+    since_update: u64,
+}
+
+impl Watchdog {
+    /// Returns a watchdog firing after `threshold` instructions without a display update
+    fn new(threshold: u64) -> Watchdog {
+        Watchdog {
+            threshold,
+            since_update: 0,
+        }
+    }
+
+    /// Count one executed instruction
+    ///
+    /// # Arguments
+    ///
+    /// * `displayed` - True if the instruction updated the display
+    ///
+    /// # Returns
+    ///
+    /// * `fired` - True if the threshold was reached and the ROM should be reset. The count starts over.
+    fn observe(&mut self, displayed: bool) -> bool {
+        if displayed {
+            self.since_update = 0;
+            return false;
+        }
+        self.since_update += 1;
+        if self.since_update >= self.threshold {
+            self.since_update = 0;
+            return true;
+        }
+        false
+    }
+}
+
+/// Save states of the most recent 60hz frames, restored newest first to step back in time
+struct RewindBuffer {
+    /// Saved states, oldest first
+    states: VecDeque<Vec<u8>>,
+    /// Maximum number of states kept. The oldest is dropped to make room for a new one
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    /// Returns an empty buffer keeping up to `capacity` states
+    fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Save the state of the CPU at the end of a frame
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - CPU to save
+    fn record(&mut self, cpu: &cpu::Cpu) {
+        if self.states.len() >= self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(cpu.save_state());
+    }
+
+    /// Restore the most recent saved state and drop it, so the next call goes one frame further back. The keys stay
+    /// as they are held now rather than as they were, so a restored wait for a key press (Fx0A) keeps waiting for a
+    /// new press and no key is stuck once rewinding stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - CPU to restore the state into
+    ///
+    /// # Returns
+    ///
+    /// * `rewound` - False if no states are left, in which case the CPU is unchanged
+    fn rewind(&mut self, cpu: &mut cpu::Cpu) -> Result<bool, StateError> {
+        let state = match self.states.pop_back() {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+        let keys = cpu.pressed_keys();
+        cpu.load_state(&state)?;
+        cpu.set_pressed_keys(keys);
+        Ok(true)
+    }
+}
+
+/// Measures the time spent rendering a fixed number of frames
+struct RenderBench {
+    /// Number of frames still to render
+    remaining: u32,
+    /// Number of frames rendered so far
+    rendered: u32,
+    /// Total time spent rendering
+    total: Duration,
+}
+
+impl RenderBench {
+    /// Returns a benchmark that completes after `frames` frames
+    fn new(frames: u32) -> RenderBench {
+        RenderBench {
+            remaining: frames,
+            rendered: 0,
+            total: Duration::from_secs(0),
+        }
+    }
+
+    /// Record the time taken by one frame
+    ///
+    /// # Returns
+    ///
+    /// * `done` - True once all frames have been recorded
+    fn record(&mut self, elapsed: Duration) -> bool {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.rendered += 1;
+        self.total += elapsed;
+        self.remaining == 0
+    }
+
+    /// Average time per rendered frame
+    fn average(&self) -> Duration {
+        self.total / self.rendered.max(1)
+    }
+}
+
+struct MainState {
+    cpu: cpu::Cpu,
+    /// Number of instructions executed per second
+    cycle_hz: u32,
+    /// Clock scheduling the instructions at `cycle_hz`
+    cycle_clock: CycleClock,
+    frames: FrameHistory,
+    /// Time of the last display update from the CPU
+    last_disp_update: Instant,
+    /// Whether any frame has been presented yet. The first draw always presents, even without a display update
+    presented: bool,
+    /// Whether the "no display output" indicator is currently shown
+    idle_indicator: bool,
+    /// Beep output, following the sound timer. None if no audio device could be opened
+    beeper: Option<beep::Beeper>,
+    /// Audio recorder, shared with the audio sink installed in the CPU
+    recorder: Rc<RefCell<wav::WavRecorder>>,
+    /// Animated GIF recording of the display
+    gif_recorder: gif_recorder::GifRecorder,
+    /// States of the last seconds, for rewinding
+    rewind: RewindBuffer,
+    /// Whether the rewind key is held. Instead of running the ROM, every 60hz frame restores an older state
+    rewinding: bool,
+    /// Start of the current 60hz recording frame
+    last_record_frame: Instant,
+    /// Error that stopped the CPU, if any. No further instructions are executed once set
+    error: Option<CpuError>,
+    /// Whether the CPU produced a frame that has not been rendered yet
+    frame_pending: bool,
+    /// Minimum time between rendered frames. Used to slow rendering down independently of the CPU
+    render_interval: Option<Duration>,
+    /// Time the last frame was rendered
+    last_render: Instant,
+    /// Width of the border around the display area, in window pixels
+    border: f32,
+    /// Color of the border
+    border_color: Color,
+    /// Size of a Chip-8 pixel, in window pixels
+    scale: usize,
+    /// Batch holding one sprite per lit pixel. None when drawing each pixel separately
+    pixel_batch: Option<SpriteBatch>,
+    /// Pixels of the last presented frame and their size, see `visible_pixels`. The batch is only rebuilt and the
+    /// window only presented again when they change
+    pixels: (f32, Vec<(Point2<f32>, f32)>),
+    /// Whether an overlay changed and the window has to be presented again, even if no pixel did
+    redraw: bool,
+    /// Render benchmark in progress. The CPU does not run while set
+    bench: Option<RenderBench>,
+    /// Message overlaid on the display once the CPU has stopped with an error or halted
+    halt_message: Option<String>,
+    /// Whether the halt message was dismissed. It is not shown again until the next reset
+    halt_dismissed: bool,
+    /// Watchdog resetting the ROM when it stops updating the display, if enabled
+    watchdog: Option<Watchdog>,
+    /// Short notice overlaid on the display and the time it was shown, e.g. the new blend depth after changing it
+    notice: Option<(String, Instant)>,
+    /// Clock advancing the frame history at 60hz, independent of the draw rate
+    frame_clock: FrameClock,
+    /// Clock decrementing the delay and sound timers at 60hz, independent of the instruction rate
+    timer_clock: FrameClock,
+    /// Whether the pixel grid overlay is shown
+    grid: bool,
+    /// Color of the pixel grid overlay
+    grid_color: Color,
+    /// Whether execution is paused. The window keeps redrawing, but no instructions run and the timers stand still
+    paused: bool,
+    /// Keyboard keys pressing Chip-8 keys
+    keymap: KeyMap,
+    /// Persistence mode F6 switches back to when turning ghosting on again
+    ghosting: Persistence,
+}
+
+impl MainState {
+    /// Creates a new MainState, initializes the CPU and loads a ROM based on the passed options
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - ggez context, used to create graphics resources
+    /// * `options` - Frontend options
+    /// * `program` - Program bytes, see `read_program`. Exits the process if they do not fit in memory
+    /// * `keymap` - Keyboard keys pressing Chip-8 keys
+    fn new(
+        ctx: &mut Context,
+        options: &Options,
+        program: &[u8],
+        keymap: KeyMap,
+    ) -> GameResult<MainState> {
+        let pixel_batch = match options.renderer {
+            Renderer::Batch => {
+                let mut batch = SpriteBatch::new(graphics::Image::solid(ctx, 1, Color::WHITE)?);
+                batch.set_filter(graphics::FilterMode::Nearest);
+                Some(batch)
+            }
+            Renderer::Pixels => None,
+        };
+        let mut s = MainState {
+            cpu: match options.seed {
+                Some(seed) => cpu::Cpu::with_seed(seed),
+                None => cpu::Cpu::initialize(),
+            },
+            cycle_hz: options.speed,
+            cycle_clock: CycleClock::new(Instant::now(), options.speed),
+            frames: FrameHistory::new(options.persistence),
+            ghosting: match options.persistence {
+                Persistence::Off => Persistence::Blend(DEFAULT_BLEND_DEPTH),
+                persistence => persistence,
+            },
+            last_disp_update: Instant::now(),
+            presented: false,
+            idle_indicator: false,
+            beeper: match beep::Beeper::new(ctx, SAMPLE_RATE) {
+                Ok(beeper) => Some(beeper),
+                Err(e) => {
+                    eprintln!("Audio disabled: {}", e);
+                    None
+                }
+            },
+            recorder: Rc::new(RefCell::new(wav::WavRecorder::new(SAMPLE_RATE))),
+            gif_recorder: gif_recorder::GifRecorder::new(),
+            rewind: RewindBuffer::new(REWIND_FRAMES),
+            rewinding: false,
+            last_record_frame: Instant::now(),
+            error: None,
+            frame_pending: false,
+            render_interval: options
+                .render_hz
+                .map(|hz| Duration::from_nanos(1_000_000_000 / hz as u64)),
+            last_render: Instant::now(),
+            border: options.border as f32,
+            border_color: options.border_color,
+            scale: options.scale,
+            pixel_batch,
+            pixels: (0.0, Vec::new()),
+            redraw: false,
+            bench: options.bench_frames.map(RenderBench::new),
+            halt_message: None,
+            halt_dismissed: false,
+            watchdog: options.watchdog.map(Watchdog::new),
+            notice: None,
+            frame_clock: FrameClock::new(Instant::now()),
+            timer_clock: FrameClock::new(Instant::now()),
+            grid: options.grid,
+            grid_color: options.grid_color,
+            paused: false,
+            keymap,
+        };
+        s.cpu.set_instruction_log_len(INSTRUCTION_LOG_LEN);
+        if options.draw_throttle {
+            s.cpu.set_draw_throttle(Some(CycleCosts::default()));
+        }
+        s.cpu
+            .set_audio_sink(Box::new(wav::WavTee::new(None, s.recorder.clone())));
+        if s.bench.is_some() {
+            // Worst case for the renderer, every pixel lit
+            let lit = [true; cpu::C8_WIDTH];
+            s.frames.present(&[&lit[..]; cpu::C8_HEIGHT]);
+        } else {
+            load_program(&mut s.cpu, program, options);
+        }
+        Ok(s)
+    }
+
+    /// Start the ROM over, clearing any error or halt message
+    ///
+    /// # Arguments
+    ///
+    /// * `warm` - True to keep the last frame on screen instead of clearing the display
+    fn reset(&mut self, warm: bool) {
+        if warm {
+            self.cpu.warm_reset();
+        } else {
+            self.cpu.reset();
+            self.frames = FrameHistory {
+                visible_planes: self.frames.visible_planes,
+                ..FrameHistory::new(self.frames.persistence)
+            };
+        }
+        self.frame_pending = true;
+        self.redraw = true;
+        self.error = None;
+        self.halt_message = None;
+        self.halt_dismissed = false;
+    }
+
+    /// Pause execution, or resume it exactly where it stopped
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.cpu.set_paused(self.paused);
+        if !self.paused {
+            // Start the clocks over so the time spent paused is not caught up in a burst of instructions
+            self.cycle_clock = CycleClock::new(Instant::now(), self.cycle_hz);
+            self.timer_clock = FrameClock::new(Instant::now());
+        }
+        self.show_notice(if self.paused { "Paused" } else { "Resumed" }.to_owned());
+    }
+
+    /// Turn ghosting off, showing only the current frame, or back on in the persistence mode it had before
+    fn toggle_ghosting(&mut self) {
+        let notice = if self.frames.persistence == Persistence::Off {
+            self.frames.set_persistence(self.ghosting);
+            "Ghosting on"
+        } else {
+            self.ghosting = self.frames.persistence;
+            self.frames.set_persistence(Persistence::Off);
+            "Ghosting off"
+        };
+        self.update_pixels();
+        self.show_notice(notice.to_owned());
+    }
+
+    /// Change which display planes are shown and render the current display with them
+    ///
+    /// # Arguments
+    ///
+    /// * `planes` - Planes to show, bit 0 for the first plane and bit 1 for the second
+    fn show_planes(&mut self, planes: u8) {
+        self.frames.visible_planes = planes;
+        self.frames.present_planes(&self.cpu.view_planes());
+        self.frame_pending = true;
+        let notice = match planes {
+            BOTH_PLANES => "Showing both planes",
+            1 => "Showing plane 1 only",
+            2 => "Showing plane 2 only",
+            _ => "Showing no planes",
+        };
+        self.show_notice(notice.to_owned());
+    }
+
+    /// Hand the display to the frame history if it changed since the last presentation, with the hidden planes masked
+    /// out
+    ///
+    /// # Returns
+    ///
+    /// * `presented` - True if a new frame was presented
+    fn present_display(&mut self) -> bool {
+        if !self.cpu.has_disp_update() {
+            return false;
+        }
+        self.frames.present_planes(&self.cpu.view_planes());
+        true
+    }
+
+    /// Execute instructions while paused, then redraw right away so their effect is visible. Does nothing unless
+    /// paused.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - True to advance a full 60hz frame worth of instructions at the current speed and decrement the
+    ///   timers, false to execute a single instruction and print it
+    fn step_paused(&mut self, frame: bool) {
+        if !self.paused || self.error.is_some() {
+            return;
+        }
+        let count = if frame {
+            (self.cycle_hz / 60).max(1)
+        } else {
+            1
+        };
+        for _ in 0..count {
+            match self.cpu.step() {
+                Ok(Some(step)) => {
+                    if !frame {
+                        println!("{:03X}: {:04X}  {}", step.pc, step.opcode, step.mnemonic);
+                    }
+                    self.present_display();
+                }
+                Ok(None) => {
+                    match self.cpu.breakpoint_hit() {
+                        Some(addr) => println!("Breakpoint at {:#05X}", addr),
+                        None => println!("Waiting for a key press"),
+                    }
+                    break;
+                }
+                Err(e) => {
+                    self.stop(e);
+                    break;
+                }
+            }
+        }
+        if frame && self.error.is_none() {
+            self.cpu.update_timers();
+        }
+        self.frame_pending = true;
+    }
+
+    /// Step back one 60hz frame while the rewind key is held. Rewinding to before an error or halt clears it, so the
+    /// ROM continues from the restored state once the key is released.
+    fn rewind_frame(&mut self) {
+        match self.rewind.rewind(&mut self.cpu) {
+            Ok(true) => {
+                self.error = None;
+                self.halt_dismissed = false;
+                if self.halt_message.take().is_some() {
+                    self.redraw = true;
+                }
+                self.present_display();
+                self.frame_pending = true;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("Unable to rewind: {}", e);
+                self.rewinding = false;
+            }
+        }
+    }
+
+    /// Stop the CPU after an error, printing the instructions that led up to it
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - Error returned by the CPU
+    fn stop(&mut self, error: CpuError) {
+        eprintln!("CPU stopped: {}", error);
+        eprintln!("Last instructions, oldest first:");
+        for (pc, opcode) in self.cpu.recent_instructions() {
+            eprintln!("  {:03X}: {:04X}", pc, opcode);
+        }
+        self.error = Some(error);
+    }
+
+    /// Halve or double the cycle rate. The timers keep running at 60hz whatever the speed.
+    ///
+    /// # Arguments
+    ///
+    /// * `faster` - True to double the rate, false to halve it
+    fn adjust_speed(&mut self, faster: bool) {
+        self.cycle_hz = adjust_speed(self.cycle_hz, faster);
+        self.cycle_clock = CycleClock::new(Instant::now(), self.cycle_hz);
+        self.show_notice(format!("Speed: {} Hz", self.cycle_hz));
+    }
+
+    /// Start a new recording, or stop the one in progress
+    fn toggle_recording(&mut self) {
+        let mut recorder = self.recorder.borrow_mut();
+        if recorder.is_recording() {
+            match recorder.stop() {
+                Ok(()) => println!("Recording stopped"),
+                Err(e) => eprintln!("Unable to finish recording: {}", e),
+            }
+        } else {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = format!("recording-{}.wav", secs);
+            match recorder.start(&path) {
+                Ok(()) => println!("Recording to {}", path),
+                Err(e) => eprintln!("Unable to start recording: {}", e),
+            }
+            self.last_record_frame = Instant::now();
+        }
+    }
+
+    /// Collect the visible pixels of the frame history, and rebuild the sprite batch if they differ from the last
+    /// presented frame
+    ///
+    /// # Returns
+    ///
+    /// * `changed` - True if any pixel changed and the window has to be presented again
+    fn update_pixels(&mut self) -> bool {
+        let pixels = (
+            self.frames.pixel_size(self.scale),
+            visible_pixels(&self.frames, self.scale, self.border),
+        );
+        if pixels == self.pixels {
+            return false;
+        }
+        self.pixels = pixels;
+        if let Some(batch) = self.pixel_batch.as_mut() {
+            // The batch image is a single white pixel, scaled up to the Chip-8 pixel size
+            let (size, pixels) = &self.pixels;
+            batch.clear();
+            for &(dest, brightness) in pixels {
+                batch.add(
+                    graphics::DrawParam::new()
+                        .dest(dest)
+                        .scale([*size, *size])
+                        .color(Color::new(1.0, 1.0, 1.0, brightness)),
+                );
+            }
+        }
+        true
+    }
+
+    /// Start recording the display to a new GIF file, or stop the recording in progress
+    fn toggle_gif_recording(&mut self) {
+        if self.gif_recorder.is_recording() {
+            match self.gif_recorder.stop() {
+                Ok(()) => println!("GIF recording stopped"),
+                Err(e) => eprintln!("Unable to finish GIF recording: {}", e),
+            }
+        } else {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = format!("recording-{}.gif", secs);
+            match self.gif_recorder.start(&path) {
+                Ok(()) => println!("Recording GIF to {}", path),
+                Err(e) => eprintln!("Unable to start GIF recording: {}", e),
+            }
+        }
+    }
+
+    /// Render the blended frames and present them. If the idle indicator is active, a notice is drawn over the frame.
+    fn present_frame(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, self.border_color);
+        if self.border > 0.0 {
+            let display_area = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(
+                    self.border,
+                    self.border,
+                    (cpu::C8_WIDTH * self.scale) as f32,
+                    (cpu::C8_HEIGHT * self.scale) as f32,
+                ),
+                Color::BLACK,
+            )?;
+            graphics::draw(ctx, &display_area, graphics::DrawParam::default())?;
+        }
+        // Fading pixels are drawn translucent over the black display area
+        let (size, pixels) = &self.pixels;
+        match self.pixel_batch.as_ref() {
+            Some(batch) => graphics::draw(ctx, batch, graphics::DrawParam::default())?,
+            None => {
+                let size = *size;
+                let rect_bounds = graphics::Rect::new(0.0, 0.0, size, size);
+                let filled_rect = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect_bounds,
+                    Color::WHITE,
+                )?;
+                for &(dest, brightness) in pixels {
+                    graphics::draw(
+                        ctx,
+                        &filled_rect,
+                        (dest, Color::new(1.0, 1.0, 1.0, brightness)),
+                    )?;
+                }
+            }
+        }
+
+        if self.grid {
+            let mut grid = graphics::MeshBuilder::new();
+            for line in grid_lines(self.frames.resolution, self.scale, self.border) {
+                grid.line(&line, 1.0, self.grid_color)?;
+            }
+            let grid = grid.build(ctx)?;
+            graphics::draw(ctx, &grid, graphics::DrawParam::default())?;
+        }
+
+        if self.idle_indicator {
+            let text = graphics::Text::new("running, no display output");
+            graphics::draw(
+                ctx,
+                &text,
+                (
+                    Point2 {
+                        x: self.border + 4.0,
+                        y: self.border + 4.0,
+                    },
+                    Color::new(0.6, 0.6, 0.6, 1.0),
+                ),
+            )?;
+        }
+
+        if let Some((notice, _)) = self.notice.as_ref() {
+            let text = graphics::Text::new(notice.as_str());
+            graphics::draw(
+                ctx,
+                &text,
+                (
+                    Point2 {
+                        x: self.border + 4.0,
+                        y: self.border + (cpu::C8_HEIGHT * self.scale) as f32 - 20.0,
+                    },
+                    Color::new(0.3, 0.8, 1.0, 1.0),
+                ),
+            )?;
+        }
+
+        if let Some(message) = self.halt_message.as_ref() {
+            // Vertically centered banner over the last frame
+            let y = self.border + (cpu::C8_HEIGHT * self.scale) as f32 / 2.0 - 12.0;
+            let banner = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(self.border, y, (cpu::C8_WIDTH * self.scale) as f32, 24.0),
+                Color::new(0.0, 0.0, 0.0, 0.8),
+            )?;
+            graphics::draw(ctx, &banner, graphics::DrawParam::default())?;
+            let text = graphics::Text::new(message.as_str());
+            graphics::draw(
+                ctx,
+                &text,
+                (
+                    Point2 {
+                        x: self.border + 4.0,
+                        y: y + 4.0,
+                    },
+                    Color::new(1.0, 0.3, 0.3, 1.0),
+                ),
+            )?;
+        }
+
+        graphics::present(ctx)?;
+        self.presented = true;
+        Ok(())
+    }
 
-struct MainState {
-    cpu: cpu::Cpu,
-    last_update: Instant,
-    // Keep last three frames to smooth animation by taking the logical or of each pixel
-    last_frames: [[[bool; cpu::C8_WIDTH]; cpu::C8_HEIGHT]; 3],
-}
+    /// Show a notice over the display for `NOTICE_DURATION`
+    ///
+    /// # Arguments
+    ///
+    /// * `notice` - Text to show
+    fn show_notice(&mut self, notice: String) {
+        println!("{}", notice);
+        self.notice = Some((notice, Instant::now()));
+        self.redraw = true;
+    }
 
-impl MainState {
-    /// Creates a new MainState, initializes the CPU and loads a ROM based on the passed filepath
+    /// Carry out a key action, handing the actions that only involve the CPU to `dispatch_cpu_action`
     ///
     /// # Arguments
     ///
-    /// * `rom` - Path to ROM file. Will panic if file does not exist
-    fn new(rom: &str) -> GameResult<MainState> {
-        let mut s = MainState {
-            cpu: cpu::Cpu::initialize(),
-            last_update: Instant::now(),
-            last_frames: [[[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT]; 3],
-        };
-        s.cpu.load_rom(rom);
-        Ok(s)
+    /// * `action` - Action to carry out, if any
+    fn handle_key(&mut self, action: Option<KeyAction>) {
+        match action.and_then(|action| dispatch_cpu_action(&mut self.cpu, action)) {
+            Some(KeyAction::ToggleRecording) => self.toggle_recording(),
+            Some(KeyAction::ToggleGifRecording) => self.toggle_gif_recording(),
+            Some(KeyAction::Rewind { active }) => {
+                self.rewinding = active;
+                if active {
+                    self.show_notice("Rewinding".to_owned());
+                }
+            }
+            Some(KeyAction::TogglePause) => self.toggle_pause(),
+            Some(KeyAction::AdjustSpeed { faster }) => self.adjust_speed(faster),
+            Some(KeyAction::Step) => self.step_paused(false),
+            Some(KeyAction::AdvanceFrame) => self.step_paused(true),
+            Some(KeyAction::Reset { warm }) => self.reset(warm),
+            Some(KeyAction::AdjustBlendDepth { deeper }) => {
+                let notice = match self.frames.blend_depth() {
+                    Some(depth) => {
+                        let depth = if deeper { depth + 1 } else { depth - 1 };
+                        match self.frames.set_blend_depth(depth) {
+                            Some(depth) => {
+                                self.update_pixels();
+                                format!("Blend depth: {}", depth)
+                            }
+                            None => return,
+                        }
+                    }
+                    None => "Blend depth only applies to --persistence blend".to_owned(),
+                };
+                self.show_notice(notice);
+            }
+            Some(KeyAction::ToggleGhosting) => self.toggle_ghosting(),
+            Some(KeyAction::ToggleGrid) => {
+                self.grid = !self.grid;
+                self.redraw = true;
+            }
+            Some(KeyAction::TogglePlane(plane)) => {
+                self.show_planes(self.frames.visible_planes ^ plane)
+            }
+            Some(KeyAction::ShowBothPlanes) => self.show_planes(BOTH_PLANES),
+            Some(KeyAction::DismissHalt) if self.halt_message.is_some() => {
+                self.halt_message = None;
+                self.halt_dismissed = true;
+                self.redraw = true;
+            }
+            _ => {}
+        }
     }
 }
 
 impl event::EventHandler<ggez::GameError> for MainState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        if Instant::now() - self.last_update >= Duration::from_millis(MS_PER_UPDATE) {
-            self.last_update = Instant::now();
-            self.cpu.tick();
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.bench.is_some() {
+            return Ok(());
+        }
+        let ticks = self.cycle_clock.due_ticks(Instant::now());
+        for _ in 0..ticks {
+            if self.error.is_some() || self.paused || self.rewinding {
+                break;
+            }
+            // A halted ROM would only execute its jump to itself again, the watchdog still counts the ticks
+            if !self.cpu.is_halted() {
+                if let Err(e) = self.cpu.tick() {
+                    self.stop(e);
+                }
+            }
+            // Hand every change to the frame history so nothing is lost while rendering is throttled
+            let displayed = self.present_display();
+            if displayed {
+                self.frame_pending = true;
+            }
+            if let Some(watchdog) = self.watchdog.as_mut() {
+                if watchdog.observe(displayed) {
+                    println!(
+                        "Watchdog: no display update for {} instructions, resetting",
+                        watchdog.threshold
+                    );
+                    self.reset(false);
+                }
+            }
+        }
+
+        let frames = self.timer_clock.elapsed_frames(Instant::now());
+        if self.rewinding {
+            for _ in 0..frames {
+                self.rewind_frame();
+            }
+        } else if self.error.is_none() && !self.paused {
+            for _ in 0..frames {
+                self.cpu.update_timers();
+                self.rewind.record(&self.cpu);
+            }
+        }
+
+        if self.halt_message.is_none() && !self.halt_dismissed {
+            self.halt_message = halt_message(self.error, &self.cpu);
+            if self.halt_message.is_some() {
+                // Present again so the message shows over the last frame
+                self.redraw = true;
+            }
+        }
+
+        // A stopped CPU no longer decrements the sound timer, so it must not keep beeping
+        let sounding = self.error.is_none() && self.cpu.is_sound_active();
+        if let Some(beeper) = self.beeper.as_mut() {
+            if let Err(e) = beeper.set_active(ctx, sounding) {
+                eprintln!("Audio disabled: {}", e);
+                self.beeper = None;
+            }
+        }
+
+        while Instant::now() - self.last_record_frame >= FRAME_DURATION {
+            self.last_record_frame += FRAME_DURATION;
+            if let Err(e) = self.recorder.borrow_mut().record_frame() {
+                eprintln!("Recording failed: {}", e);
+            }
+            if let Err(e) = self.gif_recorder.record_frame(&self.cpu.view_display()) {
+                eprintln!("GIF recording failed: {}", e);
+            }
         }
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        if self.cpu.has_disp_update() {
-            graphics::clear(ctx, [0.0, 0.0, 0.0, 0.0].into());
-            self.last_frames[2].copy_from_slice(self.cpu.view_display());
-            let rect_bounds = graphics::Rect::new_i32(0, 0, PIXEL_SIZE as i32, PIXEL_SIZE as i32);
-            let filled_rect = graphics::Mesh::new_rectangle(
-                ctx,
-                graphics::DrawMode::fill(),
-                rect_bounds,
-                Color::WHITE,
-            )?;
+        if self.bench.is_some() {
+            let start = Instant::now();
+            // Rebuild the batch every frame, as if every pixel changed
+            self.pixels = (0.0, Vec::new());
+            self.update_pixels();
+            self.present_frame(ctx)?;
+            let elapsed = start.elapsed();
+            if let Some(bench) = self.bench.as_mut() {
+                if bench.record(elapsed) {
+                    println!(
+                        "{} frames at {}x scale: {:.3} ms per frame",
+                        bench.rendered,
+                        self.scale,
+                        bench.average().as_secs_f64() * 1000.0
+                    );
+                    event::quit(ctx);
+                }
+            }
+            return Ok(());
+        }
 
-            for i in 0..cpu::C8_HEIGHT {
-                for j in 0..cpu::C8_WIDTH {
-                    if self.last_frames[0][i][j]
-                        | self.last_frames[1][i][j]
-                        | self.last_frames[2][i][j]
-                    {
-                        graphics::draw(
-                            ctx,
-                            &filled_rect,
-                            (ggez::mint::Point2 {
-                                x: (j * PIXEL_SIZE) as f32,
-                                y: (i * PIXEL_SIZE) as f32,
-                            },),
-                        )?;
-                    }
+        if let Some(interval) = self.render_interval {
+            if Instant::now() - self.last_render < interval {
+                return Ok(());
+            }
+            self.last_render = Instant::now();
+        }
+
+        if let Some((_, shown)) = self.notice {
+            if shown.elapsed() >= NOTICE_DURATION {
+                // Present again to remove the notice
+                self.notice = None;
+                self.redraw = true;
+            }
+        }
+
+        // Blending and fading advance once per 60hz frame, however often ggez calls draw
+        let mut changed = false;
+        for _ in 0..self.frame_clock.elapsed_frames(Instant::now()) {
+            let fading = self.frames.fade();
+            if self.frame_pending {
+                self.frame_pending = false;
+                self.last_disp_update = Instant::now();
+                if self.idle_indicator {
+                    self.idle_indicator = false;
+                    self.redraw = true;
                 }
+                changed = true;
+            } else {
+                changed |= self.frames.advance() || fading;
             }
+        }
+        // Once the blend has settled and nothing fades, the window keeps showing the last presented frame
+        let mut present = !self.presented || std::mem::take(&mut self.redraw);
+        if changed {
+            present |= self.update_pixels();
+        }
 
-            graphics::present(ctx)?;
-        } else {
-            self.last_frames[0] = self.last_frames[1];
-            self.last_frames[1] = self.last_frames[2];
+        if present {
+            self.present_frame(ctx)?;
+        } else if !self.idle_indicator
+            && Instant::now() - self.last_disp_update >= IDLE_INDICATOR_DELAY
+        {
+            self.idle_indicator = true;
+            self.present_frame(ctx)?;
         }
         Ok(())
     }
@@ -91,65 +1603,1067 @@ impl event::EventHandler<ggez::GameError> for MainState {
         &mut self,
         _ctx: &mut Context,
         keycode: KeyCode,
-        _keymod: KeyMods,
-        _repeat: bool,
+        keymod: KeyMods,
+        repeat: bool,
     ) {
-        if let Some(idx) = get_idx_from_keycode(keycode) {
-            self.cpu.set_key_pressed(idx);
-        }
-    }
-
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
-        if let Some(idx) = get_idx_from_keycode(keycode) {
-            self.cpu.set_key_released(idx)
-        }
-    }
-}
-
-/// Map keyboard keys to Chip-8 keys
-///
-///  1 2 3 4    1 2 3 C
-///  q w e r -> 4 5 6 D
-///  a s d f    7 8 9 E
-///  z x c v    A 0 B F
-fn get_idx_from_keycode(keycode: KeyCode) -> Option<usize> {
-    let key = match keycode {
-        KeyCode::Key1 => 1,
-        KeyCode::Key2 => 2,
-        KeyCode::Key3 => 3,
-        KeyCode::Key4 => 0xC,
-        KeyCode::Q => 4,
-        KeyCode::W => 5,
-        KeyCode::E => 6,
-        KeyCode::R => 0xD,
-        KeyCode::A => 7,
-        KeyCode::S => 8,
-        KeyCode::D => 9,
-        KeyCode::F => 0xE,
-        KeyCode::Z => 0xA,
-        KeyCode::X => 0,
-        KeyCode::C => 0xB,
-        KeyCode::V => 0xF,
-        _ => return None,
+        self.handle_key(key_action(&self.keymap, keycode, keymod, true, repeat));
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        self.handle_key(key_action(&self.keymap, keycode, keymod, false, false));
+    }
+}
+
+/// Frontend reaction to a keyboard event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyAction {
+    /// Press the Chip-8 key with the contained index
+    Press(usize),
+    /// Release the Chip-8 key with the contained index
+    Release(usize),
+    /// Flip the contained quirk
+    ToggleQuirk(Quirk),
+    /// Start or stop recording
+    ToggleRecording,
+    /// Start or stop recording the display as an animated GIF
+    ToggleGifRecording,
+    /// Start stepping back in time while the rewind key is held, or stop when it is released
+    Rewind { active: bool },
+    /// Pause or resume execution
+    TogglePause,
+    /// Execute a single instruction while paused
+    Step,
+    /// Run a full 60hz frame while paused
+    AdvanceFrame,
+    /// Double or halve the cycle rate
+    AdjustSpeed { faster: bool },
+    /// Start the ROM over, optionally keeping the last frame on screen
+    Reset { warm: bool },
+    /// Dismiss the halted banner
+    DismissHalt,
+    /// Blend one frame more or less in the blend persistence mode
+    AdjustBlendDepth { deeper: bool },
+    /// Switch between showing only the current frame and the configured persistence mode
+    ToggleGhosting,
+    /// Show or hide the pixel grid overlay
+    ToggleGrid,
+    /// Show or hide the display plane with the contained mask, 1 for the first plane and 2 for the second
+    TogglePlane(u8),
+    /// Show both display planes again
+    ShowBothPlanes,
+}
+
+/// Map a keyboard event to the action it triggers. Game keys act on both press and release, including presses
+/// repeated by the OS while the key is held. All other keys are hotkeys, which act once per physical press and ignore
+/// repeats and releases, except for the rewind key which acts for as long as it is held.
+///
+/// # Arguments
+///
+/// * `keymap` - Keyboard keys pressing Chip-8 keys
+/// * `keycode` - Key of the event
+/// * `keymod` - Modifiers held during the event
+/// * `down` - True for a key press, false for a release
+/// * `repeat` - True if the press was repeated by the OS because the key is held down
+fn key_action(
+    keymap: &KeyMap,
+    keycode: KeyCode,
+    keymod: KeyMods,
+    down: bool,
+    repeat: bool,
+) -> Option<KeyAction> {
+    if let Some(idx) = get_idx_from_keycode(keymap, keycode) {
+        return Some(if down {
+            KeyAction::Press(idx)
+        } else {
+            KeyAction::Release(idx)
+        });
+    }
+    if repeat {
+        return None;
+    }
+    match hotkey_action(keycode, keymod) {
+        Some(KeyAction::Rewind { .. }) => Some(KeyAction::Rewind { active: down }),
+        action if down => action,
+        _ => None,
+    }
+}
+
+/// Map a hotkey press to its action
+///
+/// # Arguments
+///
+/// * `keycode` - Key pressed
+/// * `keymod` - Modifiers held during the press
+fn hotkey_action(keycode: KeyCode, keymod: KeyMods) -> Option<KeyAction> {
+    if let Some(quirk) = get_quirk_from_keycode(keycode) {
+        return Some(KeyAction::ToggleQuirk(quirk));
+    }
+    match keycode {
+        KeyCode::F9 => Some(KeyAction::ToggleRecording),
+        KeyCode::F10 => Some(KeyAction::ToggleGifRecording),
+        KeyCode::Back => Some(KeyAction::Rewind { active: true }),
+        KeyCode::P => Some(KeyAction::TogglePause),
+        KeyCode::N => Some(KeyAction::Step),
+        KeyCode::M => Some(KeyAction::AdvanceFrame),
+        KeyCode::LBracket => Some(KeyAction::AdjustSpeed { faster: false }),
+        KeyCode::RBracket => Some(KeyAction::AdjustSpeed { faster: true }),
+        KeyCode::F5 => Some(KeyAction::Reset {
+            warm: keymod.contains(KeyMods::SHIFT),
+        }),
+        KeyCode::Escape => Some(KeyAction::DismissHalt),
+        KeyCode::F2 => Some(KeyAction::ToggleGrid),
+        KeyCode::F6 => Some(KeyAction::ToggleGhosting),
+        KeyCode::F7 => Some(KeyAction::AdjustBlendDepth { deeper: false }),
+        KeyCode::F8 => Some(KeyAction::AdjustBlendDepth { deeper: true }),
+        KeyCode::F11 | KeyCode::F12 if keymod.contains(KeyMods::SHIFT) => {
+            Some(KeyAction::ShowBothPlanes)
+        }
+        KeyCode::F11 => Some(KeyAction::TogglePlane(1)),
+        KeyCode::F12 => Some(KeyAction::TogglePlane(2)),
+        _ => None,
+    }
+}
+
+/// Apply a key action to the CPU. Actions that need frontend state are returned for the caller to handle.
+///
+/// # Arguments
+///
+/// * `cpu` - CPU to act on
+/// * `action` - Action to apply
+fn dispatch_cpu_action(cpu: &mut cpu::Cpu, action: KeyAction) -> Option<KeyAction> {
+    match action {
+        KeyAction::Press(idx) => cpu.set_key_pressed(idx),
+        KeyAction::Release(idx) => cpu.set_key_released(idx),
+        KeyAction::ToggleQuirk(quirk) => {
+            let enabled = !cpu.quirks().get(quirk);
+            cpu.set_quirk(quirk, enabled);
+            println!("Quirk {:?}: {}", quirk, if enabled { "on" } else { "off" });
+        }
+        _ => return Some(action),
+    }
+    None
+}
+
+/// Returns the message to overlay on the display if the CPU has stopped, either with an error or by jumping to itself
+///
+/// # Arguments
+///
+/// * `error` - Error that stopped the CPU, if any
+/// * `cpu` - The CPU, checked for a self jump
+fn halt_message(error: Option<CpuError>, cpu: &cpu::Cpu) -> Option<String> {
+    match error {
+        Some(e) => Some(format!("HALTED: {}", e)),
+        None if cpu.at_self_jump() => Some(format!("HALTED: jump to self at {:#05X}", cpu.pc())),
+        None => None,
+    }
+}
+
+/// Map a keyboard key to the Chip-8 key it presses in the configured key map
+///
+/// # Arguments
+///
+/// * `keymap` - Key map in use, `keymap::default_keymap` unless configured otherwise
+/// * `keycode` - Keyboard key
+fn get_idx_from_keycode(keymap: &KeyMap, keycode: KeyCode) -> Option<usize> {
+    keymap.get(&keycode).copied()
+}
+
+/// Debug menu mapping function keys to the quirk they toggle
+///
+///  F1 -> shift uses Vy
+///  F3 -> load/store increments I
+///  F4 -> jump uses Vx
+fn get_quirk_from_keycode(keycode: KeyCode) -> Option<Quirk> {
+    match keycode {
+        KeyCode::F1 => Some(Quirk::ShiftUsesVy),
+        KeyCode::F3 => Some(Quirk::LoadStoreIncrementsI),
+        KeyCode::F4 => Some(Quirk::JumpUsesVx),
+        _ => None,
+    }
+}
+
+/// Kind of program passed on the command line, detected from the file extension
+#[derive(Debug, PartialEq)]
+enum ProgramKind {
+    /// Binary ROM, loaded as is
+    Rom,
+    /// Assembly source (`.asm`), which has to be assembled before running
+    Assembly,
+}
+
+impl ProgramKind {
+    /// Detect the kind of program from its path. Anything without a case-insensitive `.asm` extension is a ROM.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the program file
+    fn from_path(path: &str) -> ProgramKind {
+        match std::path::Path::new(path).extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("asm") => ProgramKind::Assembly,
+            _ => ProgramKind::Rom,
+        }
+    }
+}
+
+/// Read the program given on the command line, assembling it first if it is an assembly source. Exits the process
+/// with an error status if the file cannot be read or does not assemble, so errors are reported before a window opens.
+///
+/// # Arguments
+///
+/// * `options` - Frontend options
+///
+/// # Returns
+///
+/// * `program` - Program bytes to load at 512. Empty when benchmarking the renderer without a ROM
+fn read_program(options: &Options) -> Vec<u8> {
+    if options.rom.is_empty() {
+        return Vec::new();
+    }
+    let program = match ProgramKind::from_path(&options.rom) {
+        ProgramKind::Rom => std::fs::read(&options.rom).map_err(|e| RomError::from(e).to_string()),
+        ProgramKind::Assembly => std::fs::read_to_string(&options.rom)
+            .map_err(|e| RomError::from(e).to_string())
+            .and_then(|source| asm::assemble(&source).map_err(|e| e.to_string())),
+    };
+    match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Unable to load {}: {}", options.rom, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load the program and apply the quirks profile given on the command line or, failing that, the one recommended by
+/// the compatibility database for the program. Exits the process with an error status if the program does not fit in
+/// memory.
+///
+/// # Arguments
+///
+/// * `cpu` - CPU to load the program into
+/// * `program` - Program bytes, see `read_program`
+/// * `options` - Frontend options
+fn load_program(cpu: &mut cpu::Cpu, program: &[u8], options: &Options) {
+    if let Err(e) = cpu.load_rom_bytes(program) {
+        eprintln!("Unable to load {}: {}", options.rom, e);
+        std::process::exit(1);
+    }
+    if let Some(profile) = options.profile {
+        cpu.set_profile(profile);
+        return;
+    }
+    let mut db = CompatDb::bundled();
+    if let Some(path) = options.compat_db.as_ref() {
+        match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| CompatDb::parse(&json))
+        {
+            Ok(user) => db.extend(user),
+            Err(e) => eprintln!("Ignoring compatibility database {}: {}", path, e),
+        }
+    }
+    if let Some(entry) = db.lookup(&cpu.rom_sha1()) {
+        println!(
+            "Recognized {}, using the {} profile",
+            entry.title,
+            entry.profile.name()
+        );
+        cpu.set_profile(entry.profile);
+    }
+}
+
+/// Run the CPU for a number of 60hz frames without a window or input. Waits for a key press are skipped, making
+/// `LD Vx, K` (Fx0A) a no-op, so ROMs waiting for input keep running.
+///
+/// # Arguments
+///
+/// * `cpu` - CPU with a ROM loaded
+/// * `frames` - Number of frames to run
+fn run_frames(cpu: &mut cpu::Cpu, frames: u32) -> Result<(), CpuError> {
+    for _ in 0..frames {
+        for _ in 0..TICKS_PER_FRAME {
+            cpu.tick()?;
+            cpu.skip_key_wait();
+        }
+        cpu.update_timers();
+    }
+    Ok(())
+}
+
+/// Run a ROM headless for `frames` frames, then save the display as a PNG. Exits the process with an error status if
+/// the CPU stopped with an error or the screenshot could not be saved.
+///
+/// # Arguments
+///
+/// * `options` - Frontend options
+/// * `program` - Program bytes, see `read_program`
+/// * `frames` - Number of frames to run
+fn run_headless(options: &Options, program: &[u8], frames: u32) -> ! {
+    let mut cpu = match options.seed {
+        Some(seed) => cpu::Cpu::with_seed(seed),
+        None => cpu::Cpu::initialize(),
+    };
+    if options.draw_throttle {
+        cpu.set_draw_throttle(Some(CycleCosts::default()));
+    }
+    load_program(&mut cpu, program, options);
+    let mut status = 0;
+    if let Err(e) = run_frames(&mut cpu, frames) {
+        // Still save the last frame, it usually shows how far the ROM got
+        eprintln!("CPU stopped: {}", e);
+        status = 1;
+    }
+    let path = match options.png.as_ref() {
+        Some(path) => path.clone(),
+        None => format!("{}.png", options.rom),
+    };
+    match screenshot::save_png(&path, &cpu.view_display(), options.scale) {
+        Ok(()) => println!("Saved {} after {} frames", path, frames),
+        Err(e) => {
+            eprintln!("Unable to save {}: {}", path, e);
+            status = 1;
+        }
+    }
+    std::process::exit(status);
+}
+
+/// Run a ROM in the command line debugger, reading commands from stdin, then exit
+///
+/// # Arguments
+///
+/// * `options` - Frontend options
+/// * `program` - Program bytes, see `read_program`
+fn run_debugger(options: &Options, program: &[u8]) -> ! {
+    let mut cpu = match options.seed {
+        Some(seed) => cpu::Cpu::with_seed(seed),
+        None => cpu::Cpu::initialize(),
     };
-    Some(key)
+    load_program(&mut cpu, program, options);
+    let stdin = std::io::stdin();
+    if let Err(e) = repl::run(&mut cpu, stdin.lock(), std::io::stdout()) {
+        eprintln!("Debugger failed: {}", e);
+        std::process::exit(1);
+    }
+    std::process::exit(0);
 }
 
 fn main() -> GameResult {
     use ggez::conf::{WindowMode, WindowSetup};
 
-    let args: Vec<String> = std::env::args().collect();
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let program = read_program(&options);
+    if let Some(frames) = options.frames {
+        run_headless(&options, &program, frames);
+    }
+    if options.debug {
+        run_debugger(&options, &program);
+    }
+    let keymap = match options.keymap() {
+        Ok(keymap) => keymap,
+        Err(e) => {
+            eprintln!("Invalid key map: {}", e);
+            std::process::exit(1);
+        }
+    };
     let cb = ggez::ContextBuilder::new("Chip8", "ratschance")
         .window_setup(WindowSetup {
             title: "Chip8".to_owned(),
+            // Frame times would be capped at the refresh rate while benchmarking otherwise
+            vsync: options.bench_frames.is_none(),
             ..Default::default()
         })
         .window_mode(WindowMode {
-            width: SCREEN_WIDTH as f32,
-            height: SCREEN_HEIGHT as f32,
+            width: (cpu::C8_WIDTH * options.scale + 2 * options.border as usize) as f32,
+            height: (cpu::C8_HEIGHT * options.scale + 2 * options.border as usize) as f32,
             ..Default::default()
         });
-    let (ctx, event_loop) = cb.build()?;
-    let state = MainState::new(&args[1])?;
+    let (mut ctx, event_loop) = cb.build()?;
+    let state = MainState::new(&mut ctx, &options, &program, keymap)?;
     event::run(ctx, event_loop, state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the rows of a low resolution frame, as handed to a display sink
+    fn rows(frame: &[[bool; cpu::C8_WIDTH]]) -> Vec<&[bool]> {
+        frame.iter().map(|row| &row[..]).collect()
+    }
+
+    #[test]
+    fn test_program_kind_from_path() {
+        assert_eq!(ProgramKind::Assembly, ProgramKind::from_path("test.asm"));
+        assert_eq!(
+            ProgramKind::Assembly,
+            ProgramKind::from_path("roms/TEST.ASM")
+        );
+        assert_eq!(ProgramKind::Rom, ProgramKind::from_path("roms/INVADERS"));
+        assert_eq!(ProgramKind::Rom, ProgramKind::from_path("pong.ch8"));
+        assert_eq!(ProgramKind::Rom, ProgramKind::from_path("asm"));
+    }
+
+    #[test]
+    fn test_read_program() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+
+        // Assembly sources are assembled, anything else is loaded as is
+        let dir = std::env::temp_dir();
+        let source = dir.join("chip8-test-program.asm");
+        std::fs::write(&source, "loop: JP loop\n").unwrap();
+        let options = parse(&[source.to_str().unwrap()]).unwrap();
+        assert_eq!(vec![0x12, 0x00], read_program(&options));
+
+        let rom = dir.join("chip8-test-program.ch8");
+        std::fs::write(&rom, "JP").unwrap();
+        let options = parse(&[rom.to_str().unwrap()]).unwrap();
+        assert_eq!(b"JP".to_vec(), read_program(&options));
+
+        // Nothing to read when benchmarking the renderer
+        let options = parse(&["--bench-render", "600"]).unwrap();
+        assert!(read_program(&options).is_empty());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(
+            Some(Color::from_rgb(0x20, 0x40, 0xFF)),
+            parse_color("2040ff")
+        );
+        assert_eq!(
+            Some(Color::from_rgb(0x20, 0x40, 0xFF)),
+            parse_color("#2040FF")
+        );
+        assert_eq!(None, parse_color("2040f"));
+        assert_eq!(None, parse_color("20g0ff"));
+    }
+
+    #[test]
+    fn test_parse_renderer_options() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+
+        let options = parse(&["pong.ch8"]).unwrap();
+        assert_eq!(Renderer::Batch, options.renderer);
+        assert_eq!(PIXEL_SIZE, options.scale);
+        assert_eq!(None, options.bench_frames);
+        assert_eq!(None, options.seed);
+        assert!(!options.draw_throttle);
+        assert!(
+            parse(&["pong.ch8", "--draw-throttle"])
+                .unwrap()
+                .draw_throttle
+        );
+        assert_eq!(Some(7), parse(&["pong.ch8", "--seed", "7"]).unwrap().seed);
+        assert!(parse(&["pong.ch8", "--seed", "-1"]).is_err());
+
+        let options = parse(&["pong.ch8", "--renderer", "pixels", "--scale", "20"]).unwrap();
+        assert_eq!(Renderer::Pixels, options.renderer);
+        assert_eq!(20, options.scale);
+        assert_eq!(
+            Persistence::Blend(DEFAULT_BLEND_DEPTH),
+            parse(&["pong.ch8"]).unwrap().persistence
+        );
+        assert_eq!(
+            Persistence::Off,
+            parse(&["pong.ch8", "--no-ghosting"]).unwrap().persistence
+        );
+
+        // The ROM is optional when benchmarking the renderer
+        let options = parse(&["--bench-render", "600"]).unwrap();
+        assert_eq!(Some(600), options.bench_frames);
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["pong.ch8", "--renderer", "mesh"]).is_err());
+        assert!(parse(&["pong.ch8", "--scale", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_frames_options() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+
+        let options = parse(&["pong.ch8"]).unwrap();
+        assert_eq!(None, options.frames);
+        assert_eq!(None, options.png);
+
+        let options = parse(&["pong.ch8", "--frames", "120", "--png", "pong.png"]).unwrap();
+        assert_eq!(Some(120), options.frames);
+        assert_eq!(Some("pong.png".to_owned()), options.png);
+        assert!(parse(&["pong.ch8", "--frames", "x"]).is_err());
+        assert!(parse(&["pong.ch8", "--png"]).is_err());
+        assert!(!options.debug);
+        assert!(parse(&["pong.ch8", "--debug"]).unwrap().debug);
+        assert_eq!(None, options.profile);
+        assert_eq!(
+            Some(Profile::Schip),
+            parse(&["pong.ch8", "--profile", "schip"]).unwrap().profile
+        );
+        assert!(parse(&["pong.ch8", "--profile", "nes"]).is_err());
+        assert_eq!(
+            Some(Profile::XoChip),
+            parse(&["pong.ch8", "--quirks", "xo-chip"]).unwrap().profile
+        );
+    }
+
+    #[test]
+    fn test_parse_rom_options() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+
+        let options = parse(&["--rom", "pong.ch8", "--speed", "1000"]).unwrap();
+        assert_eq!("pong.ch8", options.rom);
+        assert_eq!(1000, options.speed);
+        assert_eq!(DEFAULT_CYCLE_HZ, parse(&["pong.ch8"]).unwrap().speed);
+
+        // A missing ROM is reported with the usage instead of a panic
+        let e = parse(&["--speed", "1000"]).err().unwrap();
+        assert!(e.starts_with("No ROM given\nUsage: "));
+        assert!(parse(&["--rom"]).is_err());
+        assert!(parse(&["pong.ch8", "--rom", "brix.ch8"]).is_err());
+        assert!(parse(&["pong.ch8", "--speed", "10000"]).is_err());
+        assert!(parse(&["pong.ch8", "--speed", "fast"]).is_err());
+    }
+
+    #[test]
+    fn test_run_frames() {
+        // LD V0, K; LD F, V0; DRW V1, V1, 5; JP 0x206
+        let rom = [0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x12, 0x06];
+        let mut c8 = cpu::Cpu::with_seed(1);
+        c8.load_rom_bytes(&rom).unwrap();
+        run_frames(&mut c8, 1).unwrap();
+        assert_eq!(0x206, c8.pc());
+        // The wait was skipped without storing a key, so the "0" glyph was drawn
+        let mut expected = cpu::Cpu::initialize();
+        expected.load_rom_bytes(&rom[2..]).unwrap();
+        for _ in 0..3 {
+            expected.tick().unwrap();
+        }
+        assert_eq!(expected.view_display(), c8.view_display());
+        assert!(c8.view_display()[0][0]);
+    }
+
+    #[test]
+    fn test_visible_pixels() {
+        let mut frames = FrameHistory::new(Persistence::Blend(DEFAULT_BLEND_DEPTH));
+        let mut display = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+        display[0][1] = true;
+        display[2][0] = true;
+        frames.present(&rows(&display));
+
+        let pixels = visible_pixels(&frames, 20, 5.0);
+        assert_eq!(
+            vec![
+                (Point2 { x: 25.0, y: 5.0 }, 1.0),
+                (Point2 { x: 5.0, y: 45.0 }, 1.0)
+            ],
+            pixels
+        );
+
+        frames.present(&rows(&[[true; cpu::C8_WIDTH]; cpu::C8_HEIGHT]));
+        assert_eq!(
+            cpu::C8_WIDTH * cpu::C8_HEIGHT,
+            visible_pixels(&frames, PIXEL_SIZE, 0.0).len()
+        );
+
+        // Switching to the high resolution mode drops the blended low resolution frames and halves the pixel size
+        let mut hires = [[false; cpu::HIRES_WIDTH]; cpu::HIRES_HEIGHT];
+        hires[1][1] = true;
+        let hires_rows: Vec<&[bool]> = hires.iter().map(|row| &row[..]).collect();
+        frames.present(&hires_rows);
+        assert_eq!((cpu::HIRES_WIDTH, cpu::HIRES_HEIGHT), frames.resolution);
+        assert_eq!(
+            vec![(Point2 { x: 15.0, y: 15.0 }, 1.0)],
+            visible_pixels(&frames, 20, 5.0)
+        );
+    }
+
+    #[test]
+    fn test_persistence_modes() {
+        let mut lit = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+        lit[0][0] = true;
+        let dark = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+
+        // Returns the brightness of the pixel over the draws following its switch off
+        let trail = |persistence: Persistence| -> Vec<f32> {
+            let mut frames = FrameHistory::new(persistence);
+            frames.present(&rows(&lit));
+            frames.fade();
+            frames.advance();
+            frames.present(&rows(&dark));
+            (0..4)
+                .map(|_| {
+                    frames.fade();
+                    let brightness = frames.brightness(0, 0);
+                    frames.advance();
+                    brightness
+                })
+                .collect()
+        };
+
+        assert_eq!(vec![0.0; 4], trail(Persistence::Off));
+        assert_eq!(vec![1.0, 1.0, 0.0, 0.0], trail(Persistence::Blend(3)));
+        assert_eq!(vec![1.0, 0.0, 0.0, 0.0], trail(Persistence::Blend(2)));
+
+        let decay = trail(Persistence::Decay(3));
+        assert!(decay[0] < 1.0 && decay[0] > decay[1] && decay[1] > 0.0);
+        assert_eq!(0.0, decay[3]);
+    }
+
+    #[test]
+    fn test_advance_settles() {
+        let mut lit = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+        lit[0][0] = true;
+        let dark = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+
+        // Aging stops once every stored frame is a copy of the current one
+        let mut frames = FrameHistory::new(Persistence::Blend(3));
+        frames.present(&rows(&lit));
+        assert!(frames.advance());
+        assert!(frames.advance());
+        assert!(!frames.advance());
+        frames.present(&rows(&dark));
+        assert_eq!(1.0, frames.brightness(0, 0));
+        assert!(frames.advance());
+        assert!(frames.advance());
+        assert_eq!(0.0, frames.brightness(0, 0));
+        assert!(!frames.advance());
+
+        // Growing the blend adds dark frames, which have to age out again
+        assert_eq!(Some(4), frames.set_blend_depth(4));
+        assert!(frames.advance());
+
+        // A single frame never changes by aging
+        let mut frames = FrameHistory::new(Persistence::Off);
+        frames.present(&rows(&lit));
+        assert!(!frames.advance());
+    }
+
+    #[test]
+    fn test_set_blend_depth() {
+        let mut lit = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+        lit[0][0] = true;
+        let dark = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+
+        let mut frames = FrameHistory::new(Persistence::Blend(1));
+        assert_eq!(Some(1), frames.set_blend_depth(0));
+        assert_eq!(Some(MAX_BLEND_DEPTH), frames.set_blend_depth(100));
+        assert_eq!(MAX_BLEND_DEPTH, frames.last_frames.len());
+
+        // Shrinking drops the oldest frames
+        frames.present(&rows(&lit));
+        frames.advance();
+        frames.present(&rows(&dark));
+        assert_eq!(1.0, frames.brightness(0, 0));
+        assert_eq!(Some(1), frames.set_blend_depth(1));
+        assert_eq!(0.0, frames.brightness(0, 0));
+        assert_eq!(Persistence::Blend(1), frames.persistence);
+
+        // Growing keeps the current frame
+        frames.present(&rows(&lit));
+        assert_eq!(Some(3), frames.set_blend_depth(3));
+        assert_eq!(1.0, frames.brightness(0, 0));
+
+        let mut frames = FrameHistory::new(Persistence::Off);
+        assert_eq!(None, frames.set_blend_depth(2));
+        assert_eq!(Persistence::Off, frames.persistence);
+    }
+
+    #[test]
+    fn test_set_persistence() {
+        let mut lit = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+        lit[0][0] = true;
+        let dark = [[false; cpu::C8_WIDTH]; cpu::C8_HEIGHT];
+
+        // A cleared pixel disappears as soon as ghosting is turned off
+        let mut frames = FrameHistory::new(Persistence::Blend(3));
+        frames.present(&rows(&lit));
+        frames.advance();
+        frames.present(&rows(&dark));
+        assert_eq!(1.0, frames.brightness(0, 0));
+        frames.set_persistence(Persistence::Off);
+        assert_eq!(0.0, frames.brightness(0, 0));
+        assert_eq!(1, frames.last_frames.len());
+
+        // Turning it back on keeps the current frame, and blends from there
+        frames.present(&rows(&lit));
+        frames.set_persistence(Persistence::Blend(3));
+        assert_eq!(3, frames.last_frames.len());
+        assert_eq!(1.0, frames.brightness(0, 0));
+        frames.advance();
+        frames.present(&rows(&dark));
+        assert_eq!(1.0, frames.brightness(0, 0));
+    }
+
+    #[test]
+    fn test_present_planes() {
+        let mut c8 = cpu::Cpu::initialize();
+        // With both planes selected, draw a pixel lit only in the first plane, one only in the second and one in both
+        // LD I, 0x208; PLANE 3; DRW V0, V0, 1; JP 0x206; 0xA0, 0x60
+        c8.load_rom_bytes(&[0xA2, 0x08, 0xF3, 0x01, 0xD0, 0x01, 0x12, 0x06, 0xA0, 0x60])
+            .unwrap();
+        c8.reset();
+        c8.run_cycles(3).unwrap();
+        let lit = |frames: &FrameHistory| {
+            (0..3)
+                .map(|j| frames.brightness(0, j) > 0.0)
+                .collect::<Vec<bool>>()
+        };
+
+        let mut frames = FrameHistory::new(Persistence::Off);
+        assert_eq!(BOTH_PLANES, frames.visible_planes);
+        frames.present_planes(&c8.view_planes());
+        assert_eq!(vec![true, true, true], lit(&frames));
+        frames.visible_planes = 1;
+        frames.present_planes(&c8.view_planes());
+        assert_eq!(vec![true, false, true], lit(&frames));
+        frames.visible_planes = 2;
+        frames.present_planes(&c8.view_planes());
+        assert_eq!(vec![false, true, true], lit(&frames));
+        frames.visible_planes = 0;
+        frames.present_planes(&c8.view_planes());
+        assert_eq!(vec![false, false, false], lit(&frames));
+
+        // The visible planes survive a change of persistence
+        frames.set_persistence(Persistence::Blend(3));
+        assert_eq!(0, frames.visible_planes);
+    }
+
+    #[test]
+    fn test_plane_keys() {
+        assert_eq!(
+            Some(KeyAction::TogglePlane(1)),
+            hotkey_action(KeyCode::F11, KeyMods::NONE)
+        );
+        assert_eq!(
+            Some(KeyAction::TogglePlane(2)),
+            hotkey_action(KeyCode::F12, KeyMods::NONE)
+        );
+        assert_eq!(
+            Some(KeyAction::ShowBothPlanes),
+            hotkey_action(KeyCode::F11, KeyMods::SHIFT)
+        );
+        assert_eq!(
+            Some(KeyAction::ShowBothPlanes),
+            hotkey_action(KeyCode::F12, KeyMods::SHIFT)
+        );
+    }
+
+    #[test]
+    fn test_parse_persistence() {
+        assert_eq!(Some(Persistence::Off), parse_persistence("off"));
+        assert_eq!(
+            Some(Persistence::Blend(DEFAULT_BLEND_DEPTH)),
+            parse_persistence("blend")
+        );
+        assert_eq!(Some(Persistence::Blend(5)), parse_persistence("blend:5"));
+        assert_eq!(None, parse_persistence("blend:0"));
+        assert_eq!(None, parse_persistence("blend:9"));
+        assert_eq!(
+            Some(Persistence::Decay(DEFAULT_DECAY_FRAMES)),
+            parse_persistence("decay")
+        );
+        assert_eq!(Some(Persistence::Decay(10)), parse_persistence("decay:10"));
+        assert_eq!(None, parse_persistence("decay:0"));
+        assert_eq!(None, parse_persistence("phosphor"));
+    }
+
+    #[test]
+    fn test_halt_message() {
+        let c8 = cpu::Cpu::initialize();
+        assert_eq!(None, halt_message(None, &c8));
+        assert_eq!(
+            Some("HALTED: ran past end of program at 0x2A6".to_owned()),
+            halt_message(Some(CpuError::RanPastProgram(0x2A6)), &c8)
+        );
+    }
+
+    #[test]
+    fn test_key_dispatch() {
+        let keymap = keymap::default_keymap();
+        let mut c8 = cpu::Cpu::initialize();
+        let events = [
+            (KeyCode::Key1, true),
+            (KeyCode::V, true),
+            (KeyCode::Q, true),
+            (KeyCode::Key1, false),
+            (KeyCode::L, true),
+            (KeyCode::F9, false),
+        ];
+        for &(keycode, down) in events.iter() {
+            if let Some(action) = key_action(&keymap, keycode, KeyMods::NONE, down, false) {
+                assert_eq!(None, dispatch_cpu_action(&mut c8, action));
+            }
+        }
+        let pressed: Vec<usize> = (0..16).filter(|&k| c8.is_key_pressed(k)).collect();
+        assert_eq!(vec![4, 0xF], pressed);
+
+        // Quirks are toggled on the CPU, frontend actions are handed back
+        let action = key_action(&keymap, KeyCode::F1, KeyMods::NONE, true, false).unwrap();
+        assert_eq!(None, dispatch_cpu_action(&mut c8, action));
+        assert!(c8.quirks().get(Quirk::ShiftUsesVy));
+        let action = key_action(&keymap, KeyCode::F5, KeyMods::SHIFT, true, false).unwrap();
+        assert_eq!(
+            Some(KeyAction::Reset { warm: true }),
+            dispatch_cpu_action(&mut c8, action)
+        );
+        let action = key_action(&keymap, KeyCode::P, KeyMods::NONE, true, false).unwrap();
+        assert_eq!(
+            Some(KeyAction::TogglePause),
+            dispatch_cpu_action(&mut c8, action)
+        );
+        let action = key_action(&keymap, KeyCode::N, KeyMods::NONE, true, false).unwrap();
+        assert_eq!(Some(KeyAction::Step), dispatch_cpu_action(&mut c8, action));
+        let action = key_action(&keymap, KeyCode::M, KeyMods::NONE, true, false).unwrap();
+        assert_eq!(
+            Some(KeyAction::AdvanceFrame),
+            dispatch_cpu_action(&mut c8, action)
+        );
+        let action = key_action(&keymap, KeyCode::RBracket, KeyMods::NONE, true, false).unwrap();
+        assert_eq!(
+            Some(KeyAction::AdjustSpeed { faster: true }),
+            dispatch_cpu_action(&mut c8, action)
+        );
+    }
+
+    #[test]
+    fn test_configured_keymap() {
+        let parse = |args: &[&str]| Options::parse(args.iter().map(|a| a.to_string()));
+
+        let path = std::env::temp_dir().join("chip8-test-keymap.txt");
+        std::fs::write(&path, "# AZERTY\na=4\nq=7\n").unwrap();
+        let options = parse(&[
+            "pong.ch8",
+            "--keymap",
+            path.to_str().unwrap(),
+            "--key",
+            "i=5",
+            "--key",
+            "w=none",
+        ])
+        .unwrap();
+        let keymap = options.keymap().unwrap();
+        assert_eq!(Some(4), get_idx_from_keycode(&keymap, KeyCode::A));
+        assert_eq!(Some(7), get_idx_from_keycode(&keymap, KeyCode::Q));
+        assert_eq!(Some(5), get_idx_from_keycode(&keymap, KeyCode::I));
+        assert_eq!(None, get_idx_from_keycode(&keymap, KeyCode::W));
+        assert_eq!(
+            Some(KeyAction::Release(5)),
+            key_action(&keymap, KeyCode::I, KeyMods::NONE, false, false)
+        );
+
+        // Hotkeys keep their function
+        assert!(parse(&["pong.ch8", "--key", "p=1"])
+            .unwrap()
+            .keymap()
+            .is_err());
+        assert!(parse(&["pong.ch8", "--key", "i=x"]).is_err());
+        assert!(parse(&["pong.ch8", "--keymap", "/nonexistent/keymap.txt"])
+            .unwrap()
+            .keymap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_key_repeat() {
+        let keymap = keymap::default_keymap();
+        // Held game keys keep pressing, held hotkeys fire once
+        assert_eq!(
+            Some(KeyAction::Press(4)),
+            key_action(&keymap, KeyCode::Q, KeyMods::NONE, true, true)
+        );
+        assert_eq!(
+            Some(KeyAction::ToggleQuirk(Quirk::ShiftUsesVy)),
+            key_action(&keymap, KeyCode::F1, KeyMods::NONE, true, false)
+        );
+        assert_eq!(
+            Some(KeyAction::ToggleQuirk(Quirk::JumpUsesVx)),
+            key_action(&keymap, KeyCode::F4, KeyMods::NONE, true, false)
+        );
+        for &keycode in [
+            KeyCode::F1,
+            KeyCode::F5,
+            KeyCode::F8,
+            KeyCode::F9,
+            KeyCode::F10,
+            KeyCode::P,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                None,
+                key_action(&keymap, keycode, KeyMods::NONE, true, true)
+            );
+            assert_eq!(
+                None,
+                key_action(&keymap, keycode, KeyMods::SHIFT, true, true)
+            );
+        }
+    }
+
+    #[test]
+    fn test_rewind() {
+        let keymap = keymap::default_keymap();
+        // The rewind key acts while held, ignoring the repeats in between
+        assert_eq!(
+            Some(KeyAction::Rewind { active: true }),
+            key_action(&keymap, KeyCode::Back, KeyMods::NONE, true, false)
+        );
+        assert_eq!(
+            None,
+            key_action(&keymap, KeyCode::Back, KeyMods::NONE, true, true)
+        );
+        assert_eq!(
+            Some(KeyAction::Rewind { active: false }),
+            key_action(&keymap, KeyCode::Back, KeyMods::NONE, false, false)
+        );
+
+        // ADD V0, 1; JP 0x200
+        let mut c8 = cpu::Cpu::initialize();
+        c8.load_rom_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = RewindBuffer::new(2);
+        for _ in 0..3 {
+            c8.tick().unwrap();
+            c8.tick().unwrap();
+            rewind.record(&c8);
+        }
+        // Only the newest two states are kept
+        assert_eq!(Ok(true), rewind.rewind(&mut c8));
+        assert_eq!(3, c8.dump_state().v[0]);
+        assert_eq!(Ok(true), rewind.rewind(&mut c8));
+        assert_eq!(2, c8.dump_state().v[0]);
+        assert_eq!(Ok(false), rewind.rewind(&mut c8));
+        assert_eq!(2, c8.dump_state().v[0]);
+
+        // LD V3, K: rewinding to the wait keeps waiting, with the keys as held now
+        c8.load_rom_bytes(&[0xF3, 0x0A]).unwrap();
+        c8.tick().unwrap();
+        rewind.record(&c8);
+        c8.set_key_pressed(0x5);
+        assert!(!c8.is_waiting_for_key());
+        assert_eq!(Ok(true), rewind.rewind(&mut c8));
+        assert_eq!(Some(3), c8.waiting_register());
+        assert!(c8.is_key_pressed(0x5));
+    }
+
+    #[test]
+    fn test_grid_lines() {
+        let lines = grid_lines((cpu::C8_WIDTH, cpu::C8_HEIGHT), 10, 5.0);
+        assert_eq!(63 + 31, lines.len());
+        assert_eq!(
+            [Point2 { x: 15.0, y: 5.0 }, Point2 { x: 15.0, y: 325.0 }],
+            lines[0]
+        );
+        assert_eq!(
+            [Point2 { x: 5.0, y: 315.0 }, Point2 { x: 645.0, y: 315.0 }],
+            lines[lines.len() - 1]
+        );
+        // High resolution pixels are half the size, within the same display area
+        let lines = grid_lines((cpu::HIRES_WIDTH, cpu::HIRES_HEIGHT), 10, 5.0);
+        assert_eq!(127 + 63, lines.len());
+        assert_eq!(
+            [Point2 { x: 10.0, y: 5.0 }, Point2 { x: 10.0, y: 325.0 }],
+            lines[0]
+        );
+
+        let options = Options::parse(
+            ["pong.ch8", "--grid-color", "ff0000"]
+                .iter()
+                .map(|a| a.to_string()),
+        )
+        .unwrap();
+        assert!(!options.grid);
+        assert_eq!(Color::from_rgb(0xFF, 0, 0), options.grid_color);
+    }
+
+    #[test]
+    fn test_frame_clock() {
+        let start = Instant::now();
+        let mut clock = FrameClock::new(start);
+        assert_eq!(1, clock.elapsed_frames(start));
+        assert_eq!(0, clock.elapsed_frames(start + FRAME_DURATION / 2));
+
+        // Draws at 144hz see a 60hz frame on every second or third call
+        let frames: u32 = (1..=144)
+            .map(|i| clock.elapsed_frames(start + Duration::from_secs(1) * i / 144))
+            .sum();
+        assert_eq!(60, frames);
+
+        // A long stall only catches up a bounded number of frames
+        assert_eq!(
+            MAX_CATCH_UP_FRAMES,
+            clock.elapsed_frames(start + Duration::from_secs(10))
+        );
+        assert_eq!(
+            0,
+            clock.elapsed_frames(start + Duration::from_secs(10) + FRAME_DURATION / 2)
+        );
+    }
+
+    #[test]
+    fn test_cycle_clock() {
+        let start = Instant::now();
+        let mut clock = CycleClock::new(start, 500);
+        assert_eq!(1, clock.due_ticks(start));
+        // Updates at 60hz run 8 or 9 ticks each, 500 per second
+        let ticks: u32 = (1..=60)
+            .map(|i| clock.due_ticks(start + Duration::from_secs(1) * i / 60))
+            .sum();
+        assert_eq!(500, ticks);
+
+        // A long stall only catches up a second worth of ticks
+        assert_eq!(500, clock.due_ticks(start + Duration::from_secs(10)));
+        assert_eq!(0, clock.due_ticks(start + Duration::from_secs(10)));
+
+        let mut clock = CycleClock::new(start, 4000);
+        assert_eq!(67, clock.due_ticks(start + FRAME_DURATION));
+    }
+
+    #[test]
+    fn test_adjust_speed() {
+        assert_eq!(1000, adjust_speed(DEFAULT_CYCLE_HZ, true));
+        assert_eq!(250, adjust_speed(DEFAULT_CYCLE_HZ, false));
+        assert_eq!(MIN_CYCLE_HZ, adjust_speed(62, false));
+        assert_eq!(MAX_CYCLE_HZ, adjust_speed(2000, true));
+        assert_eq!(MAX_CYCLE_HZ, adjust_speed(MAX_CYCLE_HZ, true));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        // Returns the tick on which the watchdog first fires, if any
+        let first_fire = |rom: &[u8]| -> Option<u64> {
+            let mut c8 = cpu::Cpu::initialize();
+            c8.load_rom_bytes(rom).unwrap();
+            let mut frames = FrameHistory::new(Persistence::Off);
+            let mut watchdog = Watchdog::new(100);
+            (1..=1000).find(|_| {
+                c8.tick().unwrap();
+                watchdog.observe(c8.present_display(&mut frames))
+            })
+        };
+
+        // JP 0x200 - stuck without ever drawing
+        assert_eq!(Some(100), first_fire(&[0x12, 0x00]));
+        // CLS; JP 0x200 - keeps updating the display
+        assert_eq!(None, first_fire(&[0x00, 0xE0, 0x12, 0x00]));
+
+        // The count starts over after firing
+        let mut watchdog = Watchdog::new(2);
+        assert!(!watchdog.observe(false));
+        assert!(watchdog.observe(false));
+        assert!(!watchdog.observe(false));
+        assert!(watchdog.observe(false));
+    }
+
+    #[test]
+    fn test_render_bench() {
+        let mut bench = RenderBench::new(3);
+        assert!(!bench.record(Duration::from_millis(1)));
+        assert!(!bench.record(Duration::from_millis(2)));
+        assert!(bench.record(Duration::from_millis(6)));
+        assert_eq!(3, bench.rendered);
+        assert_eq!(Duration::from_millis(3), bench.average());
+    }
+}
@@ -0,0 +1,295 @@
+use std::io::{self, BufRead, Write};
+
+use chip8::{disasm, Cpu, CpuError};
+
+/// Most instructions `continue` runs before giving up on reaching a breakpoint, so a ROM stuck waiting for a key
+/// does not hang the debugger
+const CONTINUE_LIMIT: u32 = 100_000;
+/// Number of lines `disasm` shows before and after the program counter
+const DISASM_RADIUS: usize = 5;
+/// Number of bytes `mem` shows when no length is given
+const MEM_DEFAULT_LEN: usize = 16;
+/// Number of instructions per 60hz frame, after which the timers are decremented
+const TICKS_PER_FRAME: u32 = 8;
+
+/// Command summary printed by `help`
+const HELP: &str = "Commands:
+  step [n]          execute n instructions (1 by default)
+  continue          run until a breakpoint is reached
+  break <addr>      set a breakpoint, e.g. break 2a0
+  regs              show the registers
+  mem <addr> [len]  dump len bytes of memory (16 by default)
+  disasm            disassemble around the program counter
+  key <k>           press and release key k (0-f)
+  reset             start the ROM over
+  quit              exit the debugger";
+
+/// Line based debugger reading commands from `input` and writing results to `output`, until `quit` or the end of
+/// the input. Addresses are hexadecimal, with or without a `0x` prefix.
+///
+/// # Arguments
+///
+/// * `cpu` - CPU with a ROM loaded
+/// * `input` - Source of commands, one per line
+/// * `output` - Destination for prompts and results
+pub fn run<R: BufRead, W: Write>(cpu: &mut Cpu, input: R, mut output: W) -> io::Result<()> {
+    let mut ticks = 0;
+    write!(output, "(chip-8) ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("quit") | Some("q") => break,
+            Some(command) => {
+                let args: Vec<&str> = words.collect();
+                execute(cpu, &mut ticks, command, &args, &mut output)?;
+            }
+            None => {}
+        }
+        write!(output, "(chip-8) ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+/// Carry out a single debugger command
+///
+/// # Arguments
+///
+/// * `cpu` - CPU to act on
+/// * `ticks` - Instructions executed so far, to decrement the timers every `TICKS_PER_FRAME` instructions
+/// * `command` - Name of the command
+/// * `args` - Arguments following the command name
+/// * `output` - Destination for results
+fn execute<W: Write>(
+    cpu: &mut Cpu,
+    ticks: &mut u32,
+    command: &str,
+    args: &[&str],
+    output: &mut W,
+) -> io::Result<()> {
+    match (command, args) {
+        ("step", _) | ("s", _) => {
+            let count = match args.first() {
+                Some(count) => match count.parse::<u32>() {
+                    Ok(count) => count,
+                    Err(_) => return writeln!(output, "step expects a number of instructions"),
+                },
+                None => 1,
+            };
+            for _ in 0..count {
+                if cpu.is_waiting_for_key() {
+                    return writeln!(output, "Waiting for a key press, use key <k>");
+                }
+                let (pc, opcode) = next_instruction(cpu);
+                let result = tick(cpu, ticks);
+                if let Some(addr) = cpu.breakpoint_hit() {
+                    return writeln!(output, "Breakpoint at {:#05X}", addr);
+                }
+                writeln!(
+                    output,
+                    "{:03X}: {:04X}  {}",
+                    pc,
+                    opcode,
+                    disasm::disassemble(opcode)
+                )?;
+                if let Err(e) = result {
+                    return writeln!(output, "CPU stopped: {}", e);
+                }
+            }
+            Ok(())
+        }
+        ("continue", []) | ("c", []) => {
+            for _ in 0..CONTINUE_LIMIT {
+                if let Err(e) = tick(cpu, ticks) {
+                    return writeln!(output, "CPU stopped: {}", e);
+                }
+                if let Some(addr) = cpu.breakpoint_hit() {
+                    return writeln!(output, "Breakpoint at {:#05X}", addr);
+                }
+                if let Some(addr) = cpu.watchpoint_hit() {
+                    return writeln!(output, "Watchpoint at {:#05X} written", addr);
+                }
+                if cpu.is_waiting_for_key() {
+                    return writeln!(output, "Waiting for a key press, use key <k>");
+                }
+            }
+            writeln!(
+                output,
+                "No breakpoint within {} instructions, stopped at {:#05X}",
+                CONTINUE_LIMIT,
+                cpu.pc()
+            )
+        }
+        ("break", [addr]) | ("b", [addr]) => match parse_addr(addr) {
+            Some(addr) => {
+                cpu.add_breakpoint(addr);
+                writeln!(output, "Breakpoint set at {:#05X}", addr)
+            }
+            None => writeln!(output, "Invalid address: {}", addr),
+        },
+        ("regs", []) | ("r", []) => {
+            let (delay, sound) = cpu.timers();
+            writeln!(
+                output,
+                "PC={:03X} I={:03X} SP={:X} DT={:02X} ST={:02X}",
+                cpu.pc(),
+                cpu.i(),
+                cpu.sp(),
+                delay,
+                sound
+            )?;
+            let v: Vec<String> = (0..16)
+                .map(|x| format!("V{:X}={:02X}", x, cpu.v(x)))
+                .collect();
+            writeln!(output, "{}", v.join(" "))
+        }
+        ("mem", [addr]) | ("mem", [addr, _]) | ("m", [addr]) | ("m", [addr, _]) => {
+            let len = match args.get(1).map(|len| len.parse::<usize>()) {
+                Some(Ok(len)) => len,
+                Some(Err(_)) => return writeln!(output, "Invalid length: {}", args[1]),
+                None => MEM_DEFAULT_LEN,
+            };
+            let start = match parse_addr(addr) {
+                Some(addr) => addr as usize,
+                None => return writeln!(output, "Invalid address: {}", addr),
+            };
+            let bytes: Vec<u8> = (start..start + len).filter_map(|a| cpu.peek(a)).collect();
+            for (row, chunk) in bytes.chunks(16).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+                writeln!(output, "{:03X}: {}", start + 16 * row, hex.join(" "))?;
+            }
+            Ok(())
+        }
+        ("disasm", []) | ("d", []) => {
+            for (addr, text, is_pc) in cpu.disassemble_window(DISASM_RADIUS) {
+                let marker = if is_pc { "=>" } else { "  " };
+                writeln!(output, "{} {:03X}: {}", marker, addr, text)?;
+            }
+            Ok(())
+        }
+        ("key", [key]) | ("k", [key]) => match u8::from_str_radix(key, 16) {
+            Ok(key) if key < 16 => {
+                cpu.set_key_pressed(key as usize);
+                cpu.set_key_released(key as usize);
+                writeln!(output, "Pressed key {:X}", key)
+            }
+            _ => writeln!(output, "Invalid key: {}", key),
+        },
+        ("reset", []) => {
+            cpu.reset();
+            writeln!(output, "Reset, PC={:03X}", cpu.pc())
+        }
+        ("help", _) | ("h", _) => writeln!(output, "{}", HELP),
+        _ => writeln!(output, "Unknown command: {} (try help)", command),
+    }
+}
+
+/// Execute one instruction, then decrement the timers if a 60hz frame worth of instructions has run
+///
+/// # Arguments
+///
+/// * `cpu` - CPU to run
+/// * `ticks` - Instructions executed so far
+fn tick(cpu: &mut Cpu, ticks: &mut u32) -> Result<(), CpuError> {
+    cpu.tick()?;
+    *ticks = ticks.wrapping_add(1);
+    if ticks.is_multiple_of(TICKS_PER_FRAME) {
+        cpu.update_timers();
+    }
+    Ok(())
+}
+
+/// Returns the (PC, opcode) of the instruction the next tick executes
+fn next_instruction(cpu: &Cpu) -> (u16, u16) {
+    let pc = cpu.pc() as usize;
+    let hi = cpu.peek(pc).unwrap_or(0) as u16;
+    let lo = cpu.peek(pc + 1).unwrap_or(0) as u16;
+    (pc as u16, hi << 8 | lo)
+}
+
+/// Parse a hexadecimal address within the 4KB address space, with an optional `0x` prefix
+///
+/// # Arguments
+///
+/// * `addr` - Address to parse
+fn parse_addr(addr: &str) -> Option<u16> {
+    let digits = addr
+        .strip_prefix("0x")
+        .or_else(|| addr.strip_prefix("0X"))
+        .unwrap_or(addr);
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&addr| addr < 0x1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a script on a CPU with `rom` loaded and return the output
+    fn run_script(rom: &[u8], script: &str) -> String {
+        let mut cpu = Cpu::with_seed(0);
+        cpu.load_rom_bytes(rom).unwrap();
+        let mut output = Vec::new();
+        run(&mut cpu, script.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_regs_after_steps() {
+        // LD V0, 0x05; LD V1, 0x07; ADD V0, V1; LD I, 0x2A0; JP 0x208
+        let rom = [0x60, 0x05, 0x61, 0x07, 0x80, 0x14, 0xA2, 0xA0, 0x12, 0x08];
+        let output = run_script(&rom, "step 3\nregs\nquit\nregs\n");
+        let expected = "(chip-8) 200: 6005  LD V0, 0x05
+202: 6107  LD V1, 0x07
+204: 8014  ADD V0, V1
+(chip-8) PC=206 I=000 SP=0 DT=00 ST=00
+V0=0C V1=07 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00
+(chip-8) \n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_break_continue() {
+        // LD V0, 0x01; ADD V0, 0x01; SE V0, 0x05; JP 0x202; JP 0x208
+        let rom = [0x60, 0x01, 0x70, 0x01, 0x30, 0x05, 0x12, 0x02, 0x12, 0x08];
+        let output = run_script(&rom, "break 0x208\ncontinue\nr\nmem 200 4\nreset\nfoo\n");
+        assert!(output.contains("Breakpoint set at 0x208\n"));
+        assert!(output.contains("Breakpoint at 0x208\n"));
+        assert!(output.contains("PC=208 "));
+        assert!(output.contains("V0=05 "));
+        assert!(output.contains("200: 60 01 70 01\n"));
+        assert!(output.contains("Reset, PC=200\n"));
+        assert!(output.contains("Unknown command: foo"));
+    }
+
+    #[test]
+    fn test_key_wait() {
+        // LD V3, K; JP 0x202
+        let output = run_script(&[0xF3, 0x0A, 0x12, 0x02], "s\ns\nkey a\ns\nr\n");
+        assert!(output.contains("Waiting for a key press"));
+        assert!(output.contains("Pressed key A\n"));
+        assert!(output.contains("202: 1202  JP 0x202\n"));
+        assert!(output.contains(" V3=0A "));
+    }
+
+    #[test]
+    fn test_delay_timer_runs() {
+        // LD V0, 0x02; LD DT, V0; LD V1, DT; SE V1, 0x00; JP 0x204; JP 0x20A
+        let rom = [
+            0x60, 0x02, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0A,
+        ];
+        let output = run_script(&rom, "break 20a\ncontinue\n");
+        assert!(output.contains("Breakpoint at 0x20A\n"));
+    }
+
+    #[test]
+    fn test_parse_addr() {
+        assert_eq!(Some(0x2A0), parse_addr("2a0"));
+        assert_eq!(Some(0x2A0), parse_addr("0x2A0"));
+        assert_eq!(None, parse_addr("1000"));
+        assert_eq!(None, parse_addr("xyz"));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Gray level of lit pixels
+const LIT: u8 = 0xFF;
+/// Gray level of dark pixels
+const DARK: u8 = 0x00;
+
+/// Scale a Chip-8 display up to an 8 bit grayscale image, one byte per pixel in row major order
+///
+/// # Arguments
+///
+/// * `display` - Display to render, at any resolution
+/// * `scale` - Size of a Chip-8 pixel, in image pixels
+pub fn grayscale<R: AsRef<[bool]>>(display: &[R], scale: usize) -> Vec<u8> {
+    let mut image = Vec::with_capacity(width(display) * display.len() * scale * scale);
+    for row in display.iter() {
+        let line: Vec<u8> = row
+            .as_ref()
+            .iter()
+            .flat_map(|&lit| std::iter::repeat_n(if lit { LIT } else { DARK }, scale))
+            .collect();
+        for _ in 0..scale {
+            image.extend_from_slice(&line);
+        }
+    }
+    image
+}
+
+/// Encode a Chip-8 display as a grayscale PNG
+///
+/// # Arguments
+///
+/// * `writer` - Destination of the encoded PNG
+/// * `display` - Display to encode, at any resolution
+/// * `scale` - Size of a Chip-8 pixel, in image pixels
+pub fn write_png<W: Write, R: AsRef<[bool]>>(
+    writer: W,
+    display: &[R],
+    scale: usize,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(
+        writer,
+        (width(display) * scale) as u32,
+        (display.len() * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&grayscale(display, scale))
+}
+
+/// Save a Chip-8 display as a grayscale PNG file, replacing any existing file
+///
+/// # Arguments
+///
+/// * `path` - Path of the PNG file to create
+/// * `display` - Display to save, at any resolution
+/// * `scale` - Size of a Chip-8 pixel, in image pixels
+pub fn save_png<P: AsRef<Path>, R: AsRef<[bool]>>(
+    path: P,
+    display: &[R],
+    scale: usize,
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    write_png(BufWriter::new(file), display, scale)
+}
+
+/// Returns the width of a display in pixels, the length of its rows
+fn width<R: AsRef<[bool]>>(display: &[R]) -> usize {
+    display.first().map_or(0, |row| row.as_ref().len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{C8_HEIGHT, C8_WIDTH, HIRES_HEIGHT, HIRES_WIDTH};
+
+    #[test]
+    fn test_write_png() {
+        let mut display = [[false; C8_WIDTH]; C8_HEIGHT];
+        display[0][1] = true;
+        let mut encoded = Vec::new();
+        write_png(&mut encoded, &display, 2).unwrap();
+
+        let decoder = png::Decoder::new(encoded.as_slice());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((128, 64), (info.width, info.height));
+        assert_eq!(png::ColorType::Grayscale, info.color_type);
+
+        let mut image = vec![0; info.buffer_size()];
+        reader.next_frame(&mut image).unwrap();
+        assert_eq!(grayscale(&display, 2), image);
+        // The lit pixel covers columns 2 and 3 of the first two rows
+        assert_eq!([DARK, DARK, LIT, LIT, DARK], image[0..5]);
+        assert_eq!([DARK, DARK, LIT, LIT, DARK], image[128..133]);
+        assert_eq!(DARK, image[256 + 2]);
+
+        // High resolution displays produce twice the image at the same scale
+        let hires = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        encoded.clear();
+        write_png(&mut encoded, &hires, 2).unwrap();
+        let (info, _) = png::Decoder::new(encoded.as_slice()).read_info().unwrap();
+        assert_eq!((256, 128), (info.width, info.height));
+    }
+}
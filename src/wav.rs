@@ -0,0 +1,228 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::Path;
+use std::rc::Rc;
+
+use chip8::AudioSink;
+
+/// Frequency of the recorded beep tone
+const TONE_HZ: u32 = 440;
+/// Amplitude of the recorded square wave
+const AMPLITUDE: i16 = i16::MAX / 4;
+/// Number of video frames per second the recording is synchronized to
+const FRAMES_PER_SECOND: u32 = 60;
+
+/// Format of recordings and the generated tone: mono, 16 bit
+fn wav_spec(sample_rate: u32) -> hound::WavSpec {
+    hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    }
+}
+
+/// Sample of the beep square wave
+///
+/// # Arguments
+///
+/// * `phase` - Position within the square wave period, in samples
+/// * `period` - Length of the square wave period, in samples
+fn square_sample(phase: u32, period: u32) -> i16 {
+    if phase < period / 2 {
+        AMPLITUDE
+    } else {
+        -AMPLITUDE
+    }
+}
+
+/// Encode one second of the beep tone as an in-memory WAV file, a whole number of periods long so it loops
+/// seamlessly
+///
+/// # Arguments
+///
+/// * `sample_rate` - Sample rate of the tone. Should match the audio backend
+pub fn tone(sample_rate: u32) -> Result<Vec<u8>, hound::Error> {
+    let period = sample_rate / TONE_HZ;
+    let mut data = Cursor::new(Vec::new());
+    let mut writer = hound::WavWriter::new(&mut data, wav_spec(sample_rate))?;
+    for sample in 0..period * TONE_HZ {
+        writer.write_sample(square_sample(sample % period, period))?;
+    }
+    writer.finalize()?;
+    Ok(data.into_inner())
+}
+
+/// Renders the beep as a square wave into a WAV file. One frame worth of samples is written per call to
+/// `record_frame`, keeping the audio in step with the 60hz video frames.
+pub struct WavRecorder {
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+    sample_rate: u32,
+    /// Whether the beep is currently on
+    beeping: bool,
+    /// Position within the current square wave period, in samples
+    phase: u32,
+}
+
+impl WavRecorder {
+    /// Returns an idle recorder
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the WAV file. Should match the audio backend
+    pub fn new(sample_rate: u32) -> WavRecorder {
+        WavRecorder {
+            writer: None,
+            sample_rate,
+            beeping: false,
+            phase: 0,
+        }
+    }
+
+    /// Check whether a recording is in progress
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Start recording to a new WAV file, finishing any recording already in progress
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the WAV file to create
+    pub fn start<P: AsRef<Path>>(&mut self, path: P) -> Result<(), hound::Error> {
+        self.stop()?;
+        self.writer = Some(hound::WavWriter::create(path, wav_spec(self.sample_rate))?);
+        self.phase = 0;
+        Ok(())
+    }
+
+    /// Stop recording and finalize the WAV header. Does nothing if no recording is in progress.
+    pub fn stop(&mut self) -> Result<(), hound::Error> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize(),
+            None => Ok(()),
+        }
+    }
+
+    /// Set whether the beep is audible in the samples written from now on
+    ///
+    /// # Arguments
+    ///
+    /// * `on` - True while the beep is sounding
+    pub fn set_beep(&mut self, on: bool) {
+        self.beeping = on;
+    }
+
+    /// Append one video frame worth of samples. Silence is recorded while the beep is off.
+    pub fn record_frame(&mut self) -> Result<(), hound::Error> {
+        let period = self.sample_rate / TONE_HZ;
+        if let Some(writer) = self.writer.as_mut() {
+            for _ in 0..self.sample_rate / FRAMES_PER_SECOND {
+                let sample = if self.beeping {
+                    square_sample(self.phase, period)
+                } else {
+                    0
+                };
+                writer.write_sample(sample)?;
+                self.phase = (self.phase + 1) % period;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Audio sink that tees beep events into a shared `WavRecorder` before forwarding them to the audio backend
+pub struct WavTee {
+    inner: Option<Box<dyn AudioSink>>,
+    recorder: Rc<RefCell<WavRecorder>>,
+}
+
+impl WavTee {
+    /// Returns a tee feeding `recorder` and forwarding to `inner`, if set
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - Audio backend receiving the forwarded events
+    /// * `recorder` - Recorder shared with the frontend, which drives `record_frame`
+    pub fn new(inner: Option<Box<dyn AudioSink>>, recorder: Rc<RefCell<WavRecorder>>) -> WavTee {
+        WavTee { inner, recorder }
+    }
+}
+
+impl AudioSink for WavTee {
+    fn beep_on(&mut self) {
+        self.recorder.borrow_mut().set_beep(true);
+        if let Some(inner) = self.inner.as_mut() {
+            inner.beep_on();
+        }
+    }
+
+    fn beep_off(&mut self) {
+        self.recorder.borrow_mut().set_beep(false);
+        if let Some(inner) = self.inner.as_mut() {
+            inner.beep_off();
+        }
+    }
+
+    fn play_pattern(&mut self, pattern: &[u8; 16]) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.play_pattern(pattern);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_record_tone() {
+        let path = std::env::temp_dir().join("chip8-test-tone.wav");
+        let recorder = Rc::new(RefCell::new(WavRecorder::new(44_100)));
+        let mut tee = WavTee::new(None, recorder.clone());
+
+        recorder.borrow_mut().start(&path).unwrap();
+        tee.beep_on();
+        recorder.borrow_mut().record_frame().unwrap();
+        tee.beep_off();
+        recorder.borrow_mut().record_frame().unwrap();
+        recorder.borrow_mut().stop().unwrap();
+        assert!(!recorder.borrow().is_recording());
+
+        let mut header = [0u8; 44];
+        File::open(&path).unwrap().read_exact(&mut header).unwrap();
+        assert_eq!(b"RIFF", &header[0..4]);
+        assert_eq!(b"WAVE", &header[8..12]);
+        assert_eq!(b"fmt ", &header[12..16]);
+        // Mono, 44100hz, 16 bit
+        assert_eq!([1, 0], header[22..24]);
+        assert_eq!(44_100u32.to_le_bytes(), header[24..28]);
+        assert_eq!([16, 0], header[34..36]);
+        // Two frames of 735 samples each
+        assert_eq!(b"data", &header[36..40]);
+        assert_eq!((2 * 735 * 2u32).to_le_bytes(), header[40..44]);
+
+        let samples: Vec<i16> = hound::WavReader::open(&path)
+            .unwrap()
+            .samples::<i16>()
+            .map(|s| s.unwrap())
+            .collect();
+        assert_eq!(AMPLITUDE, samples[0]);
+        assert!(samples[735..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_tone() {
+        let tone = tone(44_100).unwrap();
+        let mut reader = hound::WavReader::new(tone.as_slice()).unwrap();
+        assert_eq!(wav_spec(44_100), reader.spec());
+        let samples: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        // Whole periods of 100 samples, high then low
+        assert_eq!(44_000, samples.len());
+        assert_eq!(AMPLITUDE, samples[0]);
+        assert_eq!(-AMPLITUDE, samples[50]);
+        assert_eq!(-AMPLITUDE, samples[samples.len() - 1]);
+    }
+}
@@ -0,0 +1,32 @@
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// The core has to build without the `std` feature, for embedding on targets without an operating system
+#[test]
+fn test_build_without_std() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    // A separate target directory avoids waiting on the lock held by the running `cargo test`
+    let target_dir = Path::new(manifest_dir).join("target").join("no-std");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .args(["build", "--lib", "--no-default-features", "--target-dir"])
+        .arg(&target_dir)
+        .status()
+        .expect("Unable to run cargo");
+    assert!(status.success(), "building without std failed");
+}
+
+/// The unit tests of the core have to pass without the `std` feature too, so code and tests stay `no_std` clean
+#[test]
+fn test_unit_tests_without_std() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let target_dir = Path::new(manifest_dir).join("target").join("no-std-test");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(manifest_dir)
+        .args(["test", "--lib", "--no-default-features", "--target-dir"])
+        .arg(&target_dir)
+        .status()
+        .expect("Unable to run cargo");
+    assert!(status.success(), "unit tests without std failed");
+}
@@ -0,0 +1,32 @@
+extern crate chip8;
+
+use chip8::{Cpu, RomError};
+
+/// Programs can be assembled in the test and run through the public API, without ROM files
+#[test]
+fn test_run_assembled_program() {
+    // LD V0, 0x0A; LD F, V0; LD V1, 0x02; DRW V1, V1, 5; JP 0x208
+    let program = [0x60, 0x0A, 0xF0, 0x29, 0x61, 0x02, 0xD1, 0x15, 0x12, 0x08];
+    let mut cpu = Cpu::with_seed(0);
+    cpu.load_rom_bytes(&program).unwrap();
+    cpu.step_frame(8).unwrap();
+
+    assert_eq!(0x208, cpu.pc());
+    assert_eq!((0x0A, 0x02), (cpu.v(0), cpu.v(1)));
+    // The top row of the "A" glyph, 0xF0, drawn at (2, 2)
+    let display = cpu.view_display();
+    let row: Vec<bool> = display[2][..8].to_vec();
+    assert_eq!(
+        vec![false, false, true, true, true, true, false, false],
+        row
+    );
+}
+
+#[test]
+fn test_reject_oversized_program() {
+    let mut cpu = Cpu::with_seed(0);
+    match cpu.load_rom_bytes(&[0; 4096]) {
+        Err(RomError::TooLarge(4096)) => {}
+        other => panic!("Expected TooLarge, got {:?}", other),
+    }
+}